@@ -0,0 +1,129 @@
+// Vergelijkt de scrollende heatmap met de oude aanpak, waarin elk frame de hele
+// buffer een rij werd opgeschoven, gekloond en als nieuwe texture geladen.
+// Draait zonder venster: de texture updates die egui naar de renderer zou sturen
+// worden geteld in plaats van geupload.
+//
+//     cargo bench --bench heatmap
+#[allow(dead_code)]
+#[path = "../src/heatmap.rs"]
+mod heatmap;
+
+use egui::{Color32, ColorImage, Context, RawInput, TextureHandle};
+use heatmap::ScrollingHeatmap;
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 500;
+const WARMUP_FRAMES: usize = 10;
+const SIZES: [(usize, usize); 3] = [(100, 250), (400, 1000), (1000, 4000)];
+
+// Zoals `VisualizationTab::ui` het deed
+struct Legacy {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+    texture: Option<TextureHandle>,
+}
+
+impl Legacy {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color32::BLACK; width * height],
+            texture: None,
+        }
+    }
+
+    // De oude aanpak schoof elk frame een rij op, ook zonder nieuwe metingen
+    fn frame(&mut self, ctx: &Context, row: &[Color32]) {
+        for y in 1..self.height {
+            let start = y * self.width;
+            self.pixels.copy_within(start..start + self.width, (y - 1) * self.width);
+        }
+        let last = (self.height - 1) * self.width;
+        self.pixels[last..last + self.width].copy_from_slice(row);
+        let image = ColorImage {
+            size: [self.width, self.height],
+            pixels: self.pixels.clone(),
+        };
+        self.texture = Some(ctx.load_texture("sensor", image, Default::default()));
+    }
+}
+
+fn row(width: usize, frame: usize) -> Vec<Color32> {
+    (0..width).map(|x| Color32::from_gray(((x + frame) % 256) as u8)).collect()
+}
+
+struct Measurement {
+    per_frame: Duration,
+    uploaded_per_frame: usize,
+}
+
+// Draai een aantal frames en meet de tijd en de hoeveelheid texture data per frame
+fn measure(mut frame: impl FnMut(&Context, usize)) -> Measurement {
+    let ctx = Context::default();
+    let mut elapsed = Duration::ZERO;
+    let mut uploaded = 0;
+
+    for index in 0..WARMUP_FRAMES + FRAMES {
+        let started = Instant::now();
+        let output = ctx.run(RawInput::default(), |ctx| frame(ctx, index));
+        if index < WARMUP_FRAMES {
+            continue;
+        }
+        elapsed += started.elapsed();
+        uploaded += output
+            .textures_delta
+            .set
+            .iter()
+            .map(|(_, delta)| delta.image.width() * delta.image.height() * delta.image.bytes_per_pixel())
+            .sum::<usize>();
+    }
+
+    Measurement {
+        per_frame: elapsed / FRAMES as u32,
+        uploaded_per_frame: uploaded / FRAMES,
+    }
+}
+
+fn report(name: &str, width: usize, height: usize, measurement: Measurement) {
+    println!(
+        "{:<10} {:>5}x{:<5} {:>10.1} µs/frame {:>10.1} KiB/frame",
+        name,
+        width,
+        height,
+        measurement.per_frame.as_secs_f64() * 1e6,
+        measurement.uploaded_per_frame as f64 / 1024.0
+    );
+}
+
+fn main() {
+    for (width, height) in SIZES {
+        println!("-- {}x{}, een nieuwe rij per frame", width, height);
+        let mut legacy = Legacy::new(width, height);
+        report("oud", width, height, measure(|ctx, index| legacy.frame(ctx, &row(width, index))));
+        let mut scrolling = ScrollingHeatmap::new(width, height);
+        report(
+            "ringbuffer",
+            width,
+            height,
+            measure(|ctx, index| {
+                scrolling.push_row(&row(width, index));
+                scrolling.upload(ctx, "sensor");
+            }),
+        );
+
+        // Bij een hogere framerate dan de meetfrequentie komt er niet elk frame een
+        // rij bij; de oude aanpak doet dan hetzelfde werk als hierboven
+        println!("-- {}x{}, frames zonder nieuwe rij", width, height);
+        let mut scrolling = ScrollingHeatmap::new(width, height);
+        report(
+            "ringbuffer",
+            width,
+            height,
+            measure(|ctx, _| {
+                scrolling.upload(ctx, "sensor");
+            }),
+        );
+    }
+}
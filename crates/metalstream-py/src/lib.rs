@@ -0,0 +1,291 @@
+// Python bindings voor de metalstream library: de frame decoder, de berichten en
+// opnames als numpy arrays, zodat analyses en nieuwe detectie algoritmes in Python
+// op dezelfde decoder draaien als de desktop applicatie. Bouwen met maturin:
+//
+//     cd crates/metalstream-py && maturin develop --release
+use metalstream::protocol::{self, FrameDecoder, Message, ParsedMessage, NUM_SENSORS};
+use metalstream::recording::RecordingReader;
+use numpy::{PyArray1, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Zet een serde waarde om naar gewone Python objecten (dict, list, str, ...)
+fn to_python<'py>(py: Python<'py>, value: &impl serde::Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+#[pyclass(name = "Measurement", module = "metalstream", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyMeasurement {
+    #[pyo3(get)]
+    id: u8,
+    #[pyo3(get)]
+    connected: bool,
+    #[pyo3(get)]
+    value: u16,
+}
+
+#[pymethods]
+impl PyMeasurement {
+    #[new]
+    fn new(id: u8, connected: bool, value: u16) -> Self {
+        Self { id, connected, value }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Measurement(id={}, connected={}, value={})", self.id, self.connected, self.value)
+    }
+}
+
+impl From<protocol::Measurement> for PyMeasurement {
+    fn from(measurement: protocol::Measurement) -> Self {
+        Self::new(measurement.id, measurement.connected, measurement.value)
+    }
+}
+
+#[pyclass(name = "ParsedMessage", module = "metalstream", frozen)]
+struct PyParsedMessage(ParsedMessage);
+
+#[pymethods]
+impl PyParsedMessage {
+    #[getter]
+    fn timestamp(&self) -> &str {
+        &self.0.timestamp
+    }
+
+    #[getter]
+    fn command(&self) -> &str {
+        &self.0.command
+    }
+
+    #[getter]
+    fn fields(&self) -> HashMap<String, String> {
+        self.0.fields.clone()
+    }
+
+    // Het gecontroleerde bericht als dict met een `type` sleutel, zoals de API het
+    // verstuurt; een ongeldig bericht geeft een ValueError
+    fn decode<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let message = self.0.decode().map_err(|err| PyValueError::new_err(err.to_string()))?;
+        to_python(py, &message)
+    }
+
+    // De meting als dit een geldig `SMS` bericht is
+    fn measurement(&self) -> Option<PyMeasurement> {
+        match self.0.decode() {
+            Ok(Message::Sms(measurement)) => Some(measurement.into()),
+            _ => None,
+        }
+    }
+
+    fn to_frame(&self) -> String {
+        self.0.to_frame()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_frame()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ParsedMessage({:?})", self.0.to_frame())
+    }
+}
+
+// Parse een enkel frame `$<timestamp>:<command>:<key>=<value>:...#`; None als het
+// geen geldig frame is
+#[pyfunction]
+fn parse_frame(frame: &str) -> Option<PyParsedMessage> {
+    protocol::parse_frame(frame).map(PyParsedMessage)
+}
+
+#[pyclass(name = "FrameDecoder", module = "metalstream")]
+struct PyFrameDecoder {
+    decoder: FrameDecoder,
+    // Aantal afgewezen frames sinds het begin
+    #[pyo3(get)]
+    rejected: u64,
+}
+
+#[pymethods]
+impl PyFrameDecoder {
+    #[new]
+    fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            rejected: 0,
+        }
+    }
+
+    // Geeft de berichten die met deze bytes compleet zijn. Afgewezen frames worden
+    // geteld in `rejected`, of geven met `strict=True` een ValueError
+    #[pyo3(signature = (data, strict = false))]
+    fn push(&mut self, data: &[u8], strict: bool) -> PyResult<Vec<PyParsedMessage>> {
+        let mut messages = Vec::new();
+        for result in self.decoder.push(data) {
+            match result {
+                Ok(message) => messages.push(PyParsedMessage(message)),
+                Err(err) if strict => return Err(PyValueError::new_err(err.to_string())),
+                Err(_) => self.rejected += 1,
+            }
+        }
+        Ok(messages)
+    }
+}
+
+// Een opname van de desktop applicatie (`desktop record` of een batch), in zijn
+// geheel ingelezen. De metingen komen als numpy arrays met een element per bericht,
+// of per scan met een kolom per sensor.
+#[pyclass(name = "Recording", module = "metalstream", frozen)]
+struct PyRecording {
+    // Milliseconden sinds het begin van de opname en het bericht
+    messages: Vec<(u64, ParsedMessage)>,
+    recipe: Option<serde_json::Value>,
+    batch: Option<serde_json::Value>,
+}
+
+impl PyRecording {
+    fn decoded(&self) -> impl Iterator<Item = (u64, Message)> + '_ {
+        self.messages
+            .iter()
+            .filter_map(|(elapsed, message)| Some((*elapsed, message.decode().ok()?)))
+    }
+}
+
+#[pymethods]
+impl PyRecording {
+    #[new]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let mut reader: RecordingReader = RecordingReader::open(&path)?;
+        let mut messages = Vec::new();
+        for recorded in reader.by_ref() {
+            let recorded = recorded?;
+            messages.push((recorded.elapsed.as_millis() as u64, recorded.message));
+        }
+
+        Ok(Self {
+            messages,
+            recipe: reader.recipe,
+            batch: reader.batch,
+        })
+    }
+
+    // Het laatst actieve recept als dict, of None
+    #[getter]
+    fn recipe<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        self.recipe.as_ref().map(|recipe| to_python(py, recipe)).transpose()
+    }
+
+    // De batch waar de opname bij hoort als dict, of None
+    #[getter]
+    fn batch<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        self.batch.as_ref().map(|batch| to_python(py, batch)).transpose()
+    }
+
+    fn __len__(&self) -> usize {
+        self.messages.len()
+    }
+
+    // Alle berichten als `(elapsed_ms, ParsedMessage)`
+    fn messages(&self) -> Vec<(u64, PyParsedMessage)> {
+        self.messages
+            .iter()
+            .map(|(elapsed, message)| (*elapsed, PyParsedMessage(message.clone())))
+            .collect()
+    }
+
+    // De `SMS` metingen: `elapsed_ms`, `sensor`, `connected` en `value`
+    fn measurements<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let (mut elapsed, mut sensor, mut connected, mut value) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (time, message) in self.decoded() {
+            if let Message::Sms(measurement) = message {
+                elapsed.push(time);
+                sensor.push(measurement.id);
+                connected.push(measurement.connected);
+                value.push(measurement.value);
+            }
+        }
+
+        let arrays = PyDict::new(py);
+        arrays.set_item("elapsed_ms", PyArray1::from_vec(py, elapsed))?;
+        arrays.set_item("sensor", PyArray1::from_vec(py, sensor))?;
+        arrays.set_item("connected", PyArray1::from_vec(py, connected))?;
+        arrays.set_item("value", PyArray1::from_vec(py, value))?;
+        Ok(arrays)
+    }
+
+    // De `MET` berichten: `elapsed_ms`, `width` en `length` in mm en `speed` in cm/s
+    fn metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let (mut elapsed, mut width, mut length, mut speed) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (time, message) in self.decoded() {
+            if let Message::Met(metrics) = message {
+                elapsed.push(time);
+                width.push(metrics.width);
+                length.push(metrics.length);
+                speed.push(metrics.speed);
+            }
+        }
+
+        let arrays = PyDict::new(py);
+        arrays.set_item("elapsed_ms", PyArray1::from_vec(py, elapsed))?;
+        arrays.set_item("width", PyArray1::from_vec(py, width))?;
+        arrays.set_item("length", PyArray1::from_vec(py, length))?;
+        arrays.set_item("speed", PyArray1::from_vec(py, speed))?;
+        Ok(arrays)
+    }
+
+    // De metingen per scan, zoals de heatmap ze toont: een sensor die al in de
+    // lopende scan zit begint een nieuwe. Geeft `elapsed_ms` per scan en `values`
+    // met vorm (scans, NUM_SENSORS); NaN waar een sensor niets of niet verbonden meldde
+    fn scans<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let mut elapsed = Vec::new();
+        let mut values = Vec::new();
+        let mut scan = [f64::NAN; NUM_SENSORS];
+        let mut scanned = [false; NUM_SENSORS];
+        let mut last = None;
+
+        for (time, message) in self.decoded() {
+            let Message::Sms(measurement) = message else {
+                continue;
+            };
+            let index = measurement.id as usize - 1;
+            if scanned[index] {
+                elapsed.push(last.unwrap_or(time));
+                values.extend(scan);
+                scan = [f64::NAN; NUM_SENSORS];
+                scanned = [false; NUM_SENSORS];
+            }
+            scanned[index] = true;
+            scan[index] = if measurement.connected { measurement.value as f64 } else { f64::NAN };
+            last = Some(time);
+        }
+        if let Some(time) = last {
+            elapsed.push(time);
+            values.extend(scan);
+        }
+
+        let rows = elapsed.len();
+        let arrays = PyDict::new(py);
+        arrays.set_item("elapsed_ms", PyArray1::from_vec(py, elapsed))?;
+        arrays.set_item("values", PyArray1::from_vec(py, values).reshape([rows, NUM_SENSORS])?)?;
+        Ok(arrays)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Recording({} berichten)", self.messages.len())
+    }
+}
+
+#[pymodule(name = "metalstream")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("NUM_SENSORS", NUM_SENSORS)?;
+    module.add_class::<PyMeasurement>()?;
+    module.add_class::<PyParsedMessage>()?;
+    module.add_class::<PyFrameDecoder>()?;
+    module.add_class::<PyRecording>()?;
+    module.add_function(wrap_pyfunction!(parse_frame, module)?)?;
+    Ok(())
+}
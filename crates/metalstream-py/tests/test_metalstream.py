@@ -0,0 +1,65 @@
+# Tests voor de Python bindings; eerst `maturin develop` in crates/metalstream-py,
+# daarna `pytest tests`
+from pathlib import Path
+
+import numpy as np
+import pytest
+
+import metalstream
+
+DATA = Path(__file__).parent / "data"
+
+
+def test_parse_frame():
+    message = metalstream.parse_frame("$00012345:SMS:ID=3:C=1:V=512#")
+    assert message.timestamp == "00012345"
+    assert message.command == "SMS"
+    assert message.fields == {"ID": "3", "C": "1", "V": "512"}
+    assert message.measurement() == metalstream.Measurement(3, True, 512)
+    assert message.to_frame() == "$00012345:SMS:C=1:ID=3:V=512#"
+
+    assert metalstream.parse_frame("$00012345#") is None
+    assert metalstream.parse_frame("00012345:SMS#") is None
+
+
+def test_decoder_joins_split_frames():
+    decoder = metalstream.FrameDecoder()
+    assert decoder.push(b"ruis$0001:SMS:ID=1") == []
+    messages = decoder.push(b":C=1:V=7#$0002:MET:W=10:L=20:S=3#")
+    assert [message.timestamp for message in messages] == ["0001", "0002"]
+    assert messages[0].measurement() == metalstream.Measurement(1, True, 7)
+    assert decoder.rejected == 0
+
+
+def test_decoder_counts_rejected_frames():
+    decoder = metalstream.FrameDecoder()
+    messages = decoder.push(b"$0001:SMS$0002:SMS:ID=1:C=1:V=7#$#")
+    assert [message.timestamp for message in messages] == ["0002"]
+    assert decoder.rejected == 2
+
+
+def test_decoder_strict_raises():
+    decoder = metalstream.FrameDecoder()
+    with pytest.raises(ValueError):
+        decoder.push(b"$#", strict=True)
+    assert decoder.rejected == 0
+
+
+def test_recording_scans():
+    recording = metalstream.Recording(DATA / "small.rec")
+    assert len(recording) == 7
+    assert recording.recipe["name"] == "Kaas"
+
+    scans = recording.scans()
+    assert scans["elapsed_ms"].tolist() == [0, 10, 20]
+    values = scans["values"]
+    assert values.shape == (3, metalstream.NUM_SENSORS)
+    # S02 is in de eerste scan niet verbonden, S03 meldt zich in de tweede scan niet
+    assert values[0, 0] == 100 and values[0, 2] == 120
+    assert np.isnan(values[0, 1])
+    assert values[1, 1] == 105
+    assert np.isnan(values[1, 2])
+    assert np.isnan(values[:, 3:]).all()
+
+    metrics = recording.metrics()
+    assert metrics["width"].tolist() == [200.0]
@@ -0,0 +1,37 @@
+//! Alles om met de Metalstream Hub te praten zonder de desktop applicatie: het
+//! protocol, de seriele verbinding, de verwerking tot detecties en alarmen, en
+//! het lezen en schrijven van opnames.
+//!
+//! - [`protocol`]: frames (`$<timestamp>:<command>:<key>=<value>...#`) decoderen
+//!   tot [`protocol::Message`]s, en de parameters van de hub.
+//! - [`transport`]: de hub vinden en frames van de seriele poort lezen.
+//! - [`processing`]: de toestand van de lijn, de detector en de alarmen.
+//! - [`recording`]: opnamebestanden, een frame per regel.
+//!
+//! Een opname teruglezen en de detecties tellen:
+//!
+//! ```no_run
+//! use metalstream::processing::{Detector, LineState};
+//! use metalstream::protocol::Message;
+//! use metalstream::recording::RecordingReader;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut line = LineState::default();
+//! let mut detector = Detector::default();
+//! let mut detections = 0;
+//! let reader: RecordingReader = RecordingReader::open("opname.rec".as_ref())?;
+//! for recorded in reader {
+//!     let recorded = recorded?;
+//!     if let Ok(Message::Sms(measurement)) = line.apply(&recorded.message) {
+//!         detections += detector.process(&recorded.message.timestamp, &measurement).is_some() as u32;
+//!     }
+//! }
+//! println!("{} detecties", detections);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod processing;
+pub mod protocol;
+pub mod recording;
+pub mod transport;
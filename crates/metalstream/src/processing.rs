@@ -0,0 +1,530 @@
+//! Van berichten naar de toestand van de lijn, detecties en alarmen.
+
+use crate::protocol::{DecodeError, EventSeverity, HubEvent, HubEventKind, Measurement, Message, ParsedMessage, NUM_SENSORS};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Afmetingen van het product op de band volgens `MET`: breedte en lengte in mm
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Dimensions {
+    pub width: f64,
+    pub length: f64,
+}
+
+impl Dimensions {
+    pub fn new(width: f64, length: f64) -> Self {
+        Self { width, length }
+    }
+}
+
+/// De toestand van de lijn zoals die uit de berichten van de hub volgt
+#[derive(Clone)]
+pub struct LineState {
+    pub connection_states: [bool; NUM_SENSORS],
+    pub measurements: BTreeMap<u8, Measurement>,
+    pub dimensions: Dimensions,
+    pub speed: f64,
+}
+
+impl Default for LineState {
+    fn default() -> Self {
+        Self {
+            connection_states: [false; NUM_SENSORS],
+            measurements: BTreeMap::new(),
+            dimensions: Dimensions::default(),
+            speed: 0.0,
+        }
+    }
+}
+
+impl LineState {
+    /// Decodeer een bericht en werk de lijn bij met metingen en afmetingen
+    pub fn apply(&mut self, message: &ParsedMessage) -> Result<Message, DecodeError> {
+        let decoded = message.decode()?;
+        match &decoded {
+            Message::Sms(measurement) => {
+                self.connection_states[measurement.id as usize - 1] = measurement.connected;
+                self.measurements.insert(measurement.id, *measurement);
+            }
+            Message::Met(metrics) => {
+                self.dimensions = Dimensions::new(metrics.width, metrics.length);
+                self.speed = metrics.speed;
+            }
+            Message::Ack { .. } | Message::Error(_) | Message::Event(_) | Message::Config(_) | Message::Unknown { .. } => {}
+        }
+        Ok(decoded)
+    }
+
+    /// Wordt aangeroepen als de verbinding met de hub wegvalt
+    pub fn reset_metrics(&mut self) {
+        self.speed = 0.0;
+        self.dimensions = Dimensions::new(0.0, 0.0);
+    }
+}
+
+/// Een bewerking op het signaal van een sensor, voordat de detectie ernaar kijkt
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DspStage {
+    /// Gemiddelde over de laatste `window` samples, tegen ruis
+    MovingAverage { window: usize },
+    /// Mediaan over de laatste `window` samples, tegen losse pieken
+    Median { window: usize },
+}
+
+impl DspStage {
+    pub fn label(&self) -> String {
+        match self {
+            DspStage::MovingAverage { window } => format!("Gemiddelde over {} samples", window),
+            DspStage::Median { window } => format!("Mediaan over {} samples", window),
+        }
+    }
+
+    fn window(&self) -> usize {
+        match self {
+            DspStage::MovingAverage { window } | DspStage::Median { window } => (*window).max(1),
+        }
+    }
+}
+
+// Een `DspStage` met de laatste samples van elke sensor
+struct Filter {
+    stage: DspStage,
+    history: [VecDeque<f64>; NUM_SENSORS],
+}
+
+impl Filter {
+    fn new(stage: DspStage) -> Self {
+        Self {
+            stage,
+            history: Default::default(),
+        }
+    }
+
+    fn apply(&mut self, index: usize, value: f64) -> f64 {
+        let history = &mut self.history[index];
+        history.push_back(value);
+        while history.len() > self.stage.window() {
+            history.pop_front();
+        }
+
+        match self.stage {
+            DspStage::MovingAverage { .. } => history.iter().sum::<f64>() / history.len() as f64,
+            DspStage::Median { .. } => {
+                let mut sorted: Vec<f64> = history.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                sorted[sorted.len() / 2]
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.iter_mut().for_each(VecDeque::clear);
+    }
+}
+
+/// Instellingen van de detector, per recept op te slaan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectorSettings {
+    /// Afwijking ten opzichte van de baseline waarboven metaal wordt gedetecteerd
+    pub threshold: f64,
+    /// Hoe snel de baseline meebeweegt met het signaal (0..1)
+    pub baseline_alpha: f64,
+    /// Aantal samples per sensor voordat de baseline betrouwbaar is
+    pub warmup_samples: usize,
+    /// Bewerkingen op het signaal, in volgorde uitgevoerd
+    pub dsp: Vec<DspStage>,
+    /// Sensoren die meedoen aan de detectie, S01 eerst
+    pub sensors: [bool; NUM_SENSORS],
+}
+
+impl Default for DetectorSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 200.0,
+            baseline_alpha: 0.02,
+            warmup_samples: 10,
+            dsp: Vec::new(),
+            sensors: [true; NUM_SENSORS],
+        }
+    }
+}
+
+/// Een detectie: een aaneengesloten periode waarin een of meer sensoren boven de drempel kwamen
+#[derive(Debug, Clone, Serialize)]
+pub struct Detection {
+    pub start_timestamp: String,
+    pub end_timestamp: String,
+    pub sensors: Vec<u8>,
+    pub peak_sensor: u8,
+    pub peak_deviation: f64,
+    pub samples: usize,
+    /// Het recept waarmee is gedetecteerd
+    pub recipe: Option<String>,
+    /// Tijd tussen detectie en het moment dat het product bij de uitwerper is
+    pub reject_delay_ms: u64,
+    /// Lotnummer van de lopende batch
+    pub batch: Option<String>,
+    /// Het product waarin het metaal zat
+    pub item: Option<u64>,
+}
+
+/// Detecteert metaal als afwijking van een meebewegende baseline per sensor.
+/// Een detectie loopt zolang minstens een sensor boven de drempel zit.
+pub struct Detector {
+    settings: DetectorSettings,
+    filters: Vec<Filter>,
+    baselines: [f64; NUM_SENSORS],
+    sample_counts: [usize; NUM_SENSORS],
+    above: [bool; NUM_SENSORS],
+    active: Option<Detection>,
+}
+
+impl Detector {
+    pub fn new(settings: DetectorSettings) -> Self {
+        Self {
+            filters: settings.dsp.iter().copied().map(Filter::new).collect(),
+            settings,
+            baselines: [0.0; NUM_SENSORS],
+            sample_counts: [0; NUM_SENSORS],
+            above: [false; NUM_SENSORS],
+            active: None,
+        }
+    }
+
+    /// Andere instellingen (bijv. een ander recept) vragen om een nieuwe baseline,
+    /// omdat de DSP keten en de gebruikte sensoren kunnen veranderen
+    pub fn set_settings(&mut self, settings: DetectorSettings) {
+        *self = Self::new(settings);
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.settings.threshold
+    }
+
+    /// Een sensor is gekalibreerd zodra de baseline is opgebouwd
+    pub fn is_calibrated(&self, id: u8) -> bool {
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|index| self.sample_counts.get(index))
+            .is_some_and(|count| *count > self.settings.warmup_samples)
+    }
+
+    /// De baseline van een sensor, zodra die is opgebouwd
+    pub fn baseline(&self, id: u8) -> Option<f64> {
+        self.is_calibrated(id).then(|| self.baselines[id as usize - 1])
+    }
+
+    /// Gooi de geleerde baselines weg zodat ze opnieuw worden opgebouwd
+    pub fn calibrate(&mut self) {
+        self.baselines = [0.0; NUM_SENSORS];
+        self.sample_counts = [0; NUM_SENSORS];
+        self.above = [false; NUM_SENSORS];
+        self.active = None;
+        self.filters.iter_mut().for_each(Filter::reset);
+    }
+
+    /// Verwerk een meting; geeft een detectie terug zodra die is afgelopen
+    pub fn process(&mut self, timestamp: &str, measurement: &Measurement) -> Option<Detection> {
+        let index = (measurement.id as usize).checked_sub(1)?;
+        if index >= NUM_SENSORS {
+            return None;
+        }
+        // Een sensor die wegvalt of niet meedoet houdt een lopende detectie niet open
+        if !measurement.connected || !self.settings.sensors[index] {
+            self.above[index] = false;
+            if self.above.iter().any(|above| *above) {
+                return None;
+            }
+            return self.active.take();
+        }
+
+        let value = self
+            .filters
+            .iter_mut()
+            .fold(measurement.value as f64, |value, filter| filter.apply(index, value));
+        if self.sample_counts[index] == 0 {
+            self.baselines[index] = value;
+        }
+        self.sample_counts[index] += 1;
+
+        if self.sample_counts[index] <= self.settings.warmup_samples {
+            self.baselines[index] += (value - self.baselines[index]) / self.sample_counts[index] as f64;
+            return None;
+        }
+
+        let deviation = (value - self.baselines[index]).abs();
+        self.above[index] = deviation > self.settings.threshold;
+
+        if self.above[index] {
+            let detection = self.active.get_or_insert_with(|| Detection {
+                start_timestamp: timestamp.to_string(),
+                end_timestamp: timestamp.to_string(),
+                sensors: Vec::new(),
+                peak_sensor: measurement.id,
+                peak_deviation: 0.0,
+                samples: 0,
+                recipe: None,
+                reject_delay_ms: 0,
+                batch: None,
+                item: None,
+            });
+            detection.end_timestamp = timestamp.to_string();
+            detection.samples += 1;
+            if !detection.sensors.contains(&measurement.id) {
+                detection.sensors.push(measurement.id);
+                detection.sensors.sort();
+            }
+            if deviation > detection.peak_deviation {
+                detection.peak_deviation = deviation;
+                detection.peak_sensor = measurement.id;
+            }
+            return None;
+        }
+
+        // Alleen de baseline bijwerken als er geen metaal onder de sensor zit
+        self.baselines[index] += (value - self.baselines[index]) * self.settings.baseline_alpha;
+
+        if self.above.iter().any(|above| *above) {
+            return None;
+        }
+        self.active.take()
+    }
+
+    /// Sluit een eventuele lopende detectie af, bijv. aan het einde van een opname
+    pub fn finish(&mut self) -> Option<Detection> {
+        self.above = [false; NUM_SENSORS];
+        self.active.take()
+    }
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self::new(DetectorSettings::default())
+    }
+}
+
+/// Soorten alarmen; een alarm van elke soort is hoogstens een keer actief
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", content = "sensor", rename_all = "snake_case")]
+pub enum AlarmKind {
+    HubDisconnected,
+    SensorDisconnected(u8),
+    MetalDetected,
+    /// Door de hub gemelde fouten, zie `HubEventKind`
+    SensorFault(u8),
+    PowerBrownout,
+    WatchdogReset,
+    BufferOverflow,
+    HubError,
+}
+
+impl AlarmKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlarmKind::HubDisconnected => "hub_disconnected",
+            AlarmKind::SensorDisconnected(_) => "sensor_disconnected",
+            AlarmKind::MetalDetected => "metal_detected",
+            AlarmKind::SensorFault(_) => "sensor_fault",
+            AlarmKind::PowerBrownout => "power_brownout",
+            AlarmKind::WatchdogReset => "watchdog_reset",
+            AlarmKind::BufferOverflow => "buffer_overflow",
+            AlarmKind::HubError => "hub_error",
+        }
+    }
+
+    /// Alarmen die blijven staan tot ze worden bevestigd
+    pub fn is_latched(&self) -> bool {
+        !matches!(self, AlarmKind::HubDisconnected | AlarmKind::SensorDisconnected(_))
+    }
+
+    /// Het alarm bij een melding van de hub; informatieve gebeurtenissen geven geen alarm
+    pub fn for_event(event: &HubEvent) -> Option<AlarmKind> {
+        match (&event.kind, event.sensor) {
+            (HubEventKind::SensorFault, Some(sensor)) => Some(AlarmKind::SensorFault(sensor)),
+            (HubEventKind::Brownout, _) => Some(AlarmKind::PowerBrownout),
+            (HubEventKind::WatchdogReset, _) => Some(AlarmKind::WatchdogReset),
+            (HubEventKind::BufferOverflow, _) => Some(AlarmKind::BufferOverflow),
+            _ if event.severity == EventSeverity::Error => Some(AlarmKind::HubError),
+            _ => None,
+        }
+    }
+}
+
+/// Een actief of bevestigd alarm
+#[derive(Debug, Clone, Serialize)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub message: String,
+    /// Unix tijd in milliseconden
+    pub raised_at: u64,
+    /// Lotnummer van de batch waarin het alarm optrad
+    pub batch: Option<String>,
+}
+
+/// Actieve alarmen. Verbindingsalarmen verdwijnen vanzelf als de oorzaak weg is,
+/// detectie-alarmen en fouten van de hub blijven staan totdat ze worden bevestigd.
+#[derive(Default)]
+pub struct Alarms {
+    active: BTreeMap<AlarmKind, Alarm>,
+}
+
+impl Alarms {
+    pub fn active(&self) -> impl Iterator<Item = &Alarm> {
+        self.active.values()
+    }
+
+    /// Werk de verbindingsalarmen bij; geeft de nieuw opgetreden alarmen terug
+    pub fn update_connection(&mut self, hub_connected: bool, line: &LineState) -> Vec<Alarm> {
+        let mut raised = Vec::new();
+
+        raised.extend(self.set(AlarmKind::HubDisconnected, !hub_connected, || {
+            "Geen verbinding met de Metalstream Hub".to_string()
+        }));
+
+        for measurement in line.measurements.values() {
+            raised.extend(self.set(
+                AlarmKind::SensorDisconnected(measurement.id),
+                hub_connected && !measurement.connected,
+                || format!("Sensor S0{} is niet verbonden", measurement.id),
+            ));
+        }
+
+        raised
+    }
+
+    pub fn raise_detection(&mut self, detection: &Detection) -> Option<Alarm> {
+        // Een nieuwe detectie vervangt een nog niet bevestigde detectie
+        self.active.remove(&AlarmKind::MetalDetected);
+        self.set(AlarmKind::MetalDetected, true, || {
+            format!(
+                "Metaal gedetecteerd op {} (piek S0{})",
+                detection.start_timestamp, detection.peak_sensor
+            )
+        })
+    }
+
+    /// Een herhaalde melding werkt de tekst bij, maar geeft geen nieuw alarm
+    pub fn raise_event(&mut self, event: &HubEvent) -> Option<Alarm> {
+        let kind = AlarmKind::for_event(event)?;
+        if let Some(alarm) = self.active.get_mut(&kind) {
+            alarm.message = event.description();
+            return None;
+        }
+        self.set(kind, true, || event.description())
+    }
+
+    pub fn has_latched(&self) -> bool {
+        self.active.keys().any(AlarmKind::is_latched)
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.active.retain(|kind, _| !kind.is_latched());
+    }
+
+    fn set(&mut self, kind: AlarmKind, active: bool, message: impl FnOnce() -> String) -> Option<Alarm> {
+        if !active {
+            self.active.remove(&kind);
+            return None;
+        }
+        if self.active.contains_key(&kind) {
+            return None;
+        }
+
+        let alarm = Alarm {
+            kind,
+            message: message(),
+            raised_at: unix_millis(),
+            batch: None,
+        };
+        self.active.insert(kind, alarm.clone());
+        Some(alarm)
+    }
+}
+
+/// Unix tijd in milliseconden
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parse_frame;
+
+    fn measurement(id: u8, connected: bool, value: u16) -> Measurement {
+        Measurement { id, connected, value }
+    }
+
+    // Een detector met een baseline van 100 op S01 en S02
+    fn calibrated() -> Detector {
+        let mut detector = Detector::default();
+        for _ in 0..=DetectorSettings::default().warmup_samples {
+            assert!(detector.process("0", &measurement(1, true, 100)).is_none());
+            assert!(detector.process("0", &measurement(2, true, 100)).is_none());
+        }
+        assert_eq!(detector.baseline(1), Some(100.0));
+        detector
+    }
+
+    #[test]
+    fn applies_messages_to_line() {
+        let mut line = LineState::default();
+        let sms = parse_frame("$1:SMS:ID=2:C=1:V=40#").unwrap();
+        assert!(matches!(line.apply(&sms), Ok(Message::Sms(_))));
+        assert!(line.connection_states[1]);
+        assert_eq!(line.measurements[&2].value, 40);
+
+        let met = parse_frame("$2:MET:W=120.5:L=300:S=25#").unwrap();
+        line.apply(&met).unwrap();
+        assert_eq!(line.dimensions, Dimensions::new(120.5, 300.0));
+        assert_eq!(line.speed, 25.0);
+
+        // Een ongeldig bericht laat de lijn ongemoeid
+        let invalid = parse_frame("$3:MET:W=-1:L=300:S=25#").unwrap();
+        assert!(line.apply(&invalid).is_err());
+        assert_eq!(line.dimensions.width, 120.5);
+
+        line.reset_metrics();
+        assert_eq!(line.dimensions, Dimensions::default());
+        assert_eq!(line.speed, 0.0);
+    }
+
+    #[test]
+    fn detects_until_all_sensors_are_below_threshold() {
+        let mut detector = calibrated();
+        assert!(detector.process("1", &measurement(1, true, 400)).is_none());
+        assert!(detector.process("2", &measurement(2, true, 500)).is_none());
+        // S01 is terug, maar S02 houdt de detectie open
+        assert!(detector.process("3", &measurement(1, true, 100)).is_none());
+        let detection = detector.process("4", &measurement(2, true, 100)).unwrap();
+        assert_eq!(detection.start_timestamp, "1");
+        assert_eq!(detection.end_timestamp, "2");
+        assert_eq!(detection.sensors, vec![1, 2]);
+        assert_eq!(detection.peak_sensor, 2);
+        assert_eq!(detection.peak_deviation, 400.0);
+        assert_eq!(detection.samples, 2);
+    }
+
+    #[test]
+    fn disconnected_sensor_closes_detection() {
+        let mut detector = calibrated();
+        assert!(detector.process("1", &measurement(1, true, 400)).is_none());
+        let detection = detector.process("2", &measurement(1, false, 0)).unwrap();
+        assert_eq!(detection.sensors, vec![1]);
+        assert!(detector.finish().is_none());
+    }
+
+    #[test]
+    fn finish_closes_running_detection() {
+        let mut detector = calibrated();
+        assert!(detector.process("1", &measurement(1, true, 400)).is_none());
+        assert!(detector.finish().is_some());
+        assert!(detector.process("2", &measurement(1, true, 100)).is_none());
+    }
+}
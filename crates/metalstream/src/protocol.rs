@@ -0,0 +1,494 @@
+//! Frames en berichten van de Metalstream Hub, en de parameters die de hub kent.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Aantal sensoren die worden gebruikt
+pub const NUM_SENSORS: usize = 8;
+
+/// Maximale grootte van de ontvangstbuffer voordat oude data wordt weggegooid
+pub const MAX_BUFFER_LEN: usize = 4096;
+
+/// Een frame van de hub, opgesplitst in timestamp, commando en velden
+#[derive(Debug, Clone, Serialize)]
+pub struct ParsedMessage {
+    pub timestamp: String,
+    pub command: String,
+    pub fields: HashMap<String, String>,
+}
+
+impl ParsedMessage {
+    /// Zet het bericht terug om naar een frame zoals de hub het verstuurt
+    pub fn to_frame(&self) -> String {
+        let mut fields = self.fields.iter().collect::<Vec<_>>();
+        fields.sort();
+
+        let mut frame = format!("${}:{}", self.timestamp, self.command);
+        for (key, value) in fields {
+            frame.push_str(&format!(":{}={}", key, value));
+        }
+        frame.push('#');
+        frame
+    }
+}
+
+// Formatter voor logs
+impl std::fmt::Display for ParsedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} {}",
+            self.timestamp,
+            self.command,
+            self.fields
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Een meting van een enkele sensor uit een `SMS` bericht
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Measurement {
+    pub id: u8,
+    pub connected: bool,
+    pub value: u16,
+}
+
+/// Parse een enkel frame in de vorm `$<timestamp>:<command>:<key>=<value>:...#`
+pub fn parse_frame(frame: &str) -> Option<ParsedMessage> {
+    let inner = frame.strip_prefix('$')?.strip_suffix('#')?;
+
+    // Split de berichten in delen
+    let parts: Vec<&str> = inner.split(':').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+
+    let mut fields = HashMap::new();
+    for field in parts.iter().skip(2) {
+        if let Some((key, value)) = field.split_once('=') {
+            fields.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    Some(ParsedMessage {
+        timestamp: parts[0].to_string(),
+        command: parts[1].to_string(),
+        fields,
+    })
+}
+
+/// Reden waarom een frame uit de datastroom is weggegooid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// `$...#` gevonden, maar zonder timestamp en commando
+    Malformed(String),
+    /// Er begon een nieuw frame voordat dit frame was afgesloten
+    Interrupted(String),
+    /// De buffer liep vol zonder dat er een frame werd afgesloten
+    Overflow(usize),
+}
+
+impl FrameError {
+    /// Korte reden, bijvoorbeeld als label voor metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            FrameError::Malformed(_) => "malformed",
+            FrameError::Interrupted(_) => "interrupted",
+            FrameError::Overflow(_) => "overflow",
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Malformed(frame) => write!(f, "onleesbaar frame {:?}", frame),
+            FrameError::Interrupted(frame) => write!(f, "onderbroken frame {:?}", frame),
+            FrameError::Overflow(len) => write!(f, "buffer overgelopen na {} bytes zonder frame einde", len),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Verzamelt binnenkomende bytes en haalt er complete `$...#` frames uit,
+/// ook als een frame over meerdere reads verdeeld is.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: String,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Geeft alle frames die met deze bytes compleet zijn, of de reden waarom ze zijn afgewezen
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<ParsedMessage, FrameError>> {
+        self.buffer.push_str(&String::from_utf8_lossy(bytes));
+
+        let mut results = Vec::new();
+        while let Some(frame) = self.next_frame() {
+            results.push(frame.and_then(|frame| parse_frame(&frame).ok_or(FrameError::Malformed(frame))));
+        }
+
+        if self.buffer.len() > MAX_BUFFER_LEN {
+            results.push(Err(FrameError::Overflow(self.buffer.len())));
+            self.buffer.clear();
+        }
+
+        results
+    }
+
+    fn next_frame(&mut self) -> Option<Result<String, FrameError>> {
+        let start = self.buffer.find('$')?;
+        // Alles voor het begin van een frame is ruis
+        self.buffer.drain(..start);
+
+        let end = self.buffer.find('#')?;
+        // Begint er een nieuw frame voordat het huidige is afgesloten? Dan is het huidige kapot.
+        if let Some(restart) = self.buffer[1..end].rfind('$') {
+            let interrupted = self.buffer.drain(..=restart).collect();
+            return Some(Err(FrameError::Interrupted(interrupted)));
+        }
+
+        Some(Ok(self.buffer.drain(..=end).collect()))
+    }
+}
+
+/// Afmetingen en snelheid van het laatste product uit een `MET` bericht
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct LineMetrics {
+    pub width: f64,
+    pub length: f64,
+    pub speed: f64,
+}
+
+/// Een bericht van de hub, gecontroleerd en omgezet naar de juiste types
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// `SMS:ID=<1..8>:C=<0|1>:V=<u16>`, een meting van een enkele sensor
+    Sms(Measurement),
+    /// `MET:W=<mm>:L=<mm>:S=<cm/s>`, de afmetingen van een product
+    Met(LineMetrics),
+    /// `ACK:CMD=<commando>`, bevestiging van een opdracht aan de hub
+    Ack { command: String },
+    /// `ERR:CODE=<code>[:ID=<sensor>][:MSG=<tekst>]`, een foutmelding van de hub
+    Error(HubEvent),
+    /// `EVT:CODE=<code>[:ID=<sensor>][:MSG=<tekst>]`, een gebeurtenis op de hub
+    Event(HubEvent),
+    /// `CFG:<PARAMETER>=<waarde>:...`, de configuratie van de hub, antwoord op `GET_CFG`
+    Config(HubConfig),
+    /// Een commando dat de applicatie (nog) niet kent
+    Unknown { command: String },
+}
+
+/// Een instelbare parameter van de hub. Waarden zijn gehele getallen in `unit`.
+#[derive(Debug, Clone, Copy)]
+pub struct ParameterSpec {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub unit: &'static str,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl ParameterSpec {
+    pub fn validate(&self, value: u32) -> Result<(), String> {
+        if (self.min..=self.max).contains(&value) {
+            Ok(())
+        } else {
+            Err(format!("{} moet tussen {} en {} {} liggen", self.label, self.min, self.max, self.unit))
+        }
+    }
+}
+
+const fn gain(key: &'static str, label: &'static str) -> ParameterSpec {
+    ParameterSpec { key, label, unit: "x", min: 1, max: 128 }
+}
+
+/// Alle parameters die de hub via `GET_CFG` en `SET_CFG` kent
+pub const PARAMETERS: [ParameterSpec; NUM_SENSORS + 3] = [
+    gain("GAIN1", "Versterking S01"),
+    gain("GAIN2", "Versterking S02"),
+    gain("GAIN3", "Versterking S03"),
+    gain("GAIN4", "Versterking S04"),
+    gain("GAIN5", "Versterking S05"),
+    gain("GAIN6", "Versterking S06"),
+    gain("GAIN7", "Versterking S07"),
+    gain("GAIN8", "Versterking S08"),
+    ParameterSpec { key: "FREQ", label: "Excitatiefrequentie", unit: "Hz", min: 1_000, max: 100_000 },
+    ParameterSpec { key: "RATE", label: "Samplefrequentie", unit: "Hz", min: 10, max: 5_000 },
+    ParameterSpec { key: "INTERVAL", label: "Rapportage-interval", unit: "ms", min: 10, max: 10_000 },
+];
+
+pub fn parameter(key: &str) -> Option<&'static ParameterSpec> {
+    PARAMETERS.iter().find(|spec| spec.key == key)
+}
+
+/// Parameterwaarden per sleutel, bijvoorbeeld `FREQ` => 20000
+pub type HubConfig = BTreeMap<String, u32>;
+
+/// Ernst van een fout of gebeurtenis op de hub
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl EventSeverity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventSeverity::Info => "Info",
+            EventSeverity::Warning => "Waarschuwing",
+            EventSeverity::Error => "Fout",
+        }
+    }
+}
+
+/// Soorten fouten en gebeurtenissen die de hub kan melden, op basis van `CODE`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HubEventKind {
+    /// `SENSOR_FAULT`, een sensor geeft een hardwarefout (met `ID`)
+    SensorFault,
+    /// `BROWNOUT`, de voedingsspanning van de hub is weggezakt
+    Brownout,
+    /// `WDT_RESET`, de hub is door de watchdog herstart
+    WatchdogReset,
+    /// `BUF_OVERFLOW`, de hub heeft metingen weggegooid
+    BufferOverflow,
+    /// `CONFIG_CHANGED`, de configuratie van de hub is aangepast
+    ConfigChanged,
+    Other(String),
+}
+
+impl HubEventKind {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "SENSOR_FAULT" => HubEventKind::SensorFault,
+            "BROWNOUT" => HubEventKind::Brownout,
+            "WDT_RESET" => HubEventKind::WatchdogReset,
+            "BUF_OVERFLOW" => HubEventKind::BufferOverflow,
+            "CONFIG_CHANGED" => HubEventKind::ConfigChanged,
+            other => HubEventKind::Other(other.to_string()),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            HubEventKind::SensorFault => "SENSOR_FAULT",
+            HubEventKind::Brownout => "BROWNOUT",
+            HubEventKind::WatchdogReset => "WDT_RESET",
+            HubEventKind::BufferOverflow => "BUF_OVERFLOW",
+            HubEventKind::ConfigChanged => "CONFIG_CHANGED",
+            HubEventKind::Other(code) => code,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            HubEventKind::SensorFault => "Sensorfout",
+            HubEventKind::Brownout => "Spanningsdip",
+            HubEventKind::WatchdogReset => "Watchdog reset",
+            HubEventKind::BufferOverflow => "Buffer overgelopen",
+            HubEventKind::ConfigChanged => "Configuratie gewijzigd",
+            HubEventKind::Other(code) => code,
+        }
+    }
+
+    fn severity(&self) -> Option<EventSeverity> {
+        match self {
+            HubEventKind::SensorFault | HubEventKind::Brownout => Some(EventSeverity::Error),
+            HubEventKind::WatchdogReset | HubEventKind::BufferOverflow => Some(EventSeverity::Warning),
+            HubEventKind::ConfigChanged => Some(EventSeverity::Info),
+            HubEventKind::Other(_) => None,
+        }
+    }
+}
+
+/// Een fout of gebeurtenis die door de hub zelf is gemeld
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HubEvent {
+    pub kind: HubEventKind,
+    pub severity: EventSeverity,
+    pub sensor: Option<u8>,
+    pub message: Option<String>,
+}
+
+impl HubEvent {
+    pub fn description(&self) -> String {
+        let mut text = self.kind.label().to_string();
+        if let Some(sensor) = self.sensor {
+            text.push_str(&format!(" op S0{}", sensor));
+        }
+        if let Some(message) = &self.message {
+            text.push_str(&format!(": {}", message));
+        }
+        text
+    }
+}
+
+/// Waarom een bekend commando niet kon worden omgezet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    MissingField { command: String, field: &'static str },
+    InvalidField { command: String, field: &'static str, value: String },
+}
+
+impl DecodeError {
+    /// Korte reden, bijvoorbeeld als label voor metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            DecodeError::MissingField { .. } => "missing_field",
+            DecodeError::InvalidField { .. } => "invalid_field",
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::MissingField { command, field } => write!(f, "{}: veld {} ontbreekt", command, field),
+            DecodeError::InvalidField { command, field, value } => {
+                write!(f, "{}: ongeldige waarde {:?} voor veld {}", command, value, field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl ParsedMessage {
+    fn field(&self, field: &'static str) -> Result<&str, DecodeError> {
+        self.fields.get(field).map(String::as_str).ok_or_else(|| DecodeError::MissingField {
+            command: self.command.clone(),
+            field,
+        })
+    }
+
+    // Lees een veld en controleer de waarde met `valid`
+    fn parse_field<T: std::str::FromStr>(&self, field: &'static str, valid: impl Fn(&T) -> bool) -> Result<T, DecodeError> {
+        let value = self.field(field)?;
+        value.parse().ok().filter(|parsed| valid(parsed)).ok_or_else(|| DecodeError::InvalidField {
+            command: self.command.clone(),
+            field,
+            value: value.to_string(),
+        })
+    }
+
+    // Onbekende codes krijgen de ernst `fallback`, afhankelijk van `ERR` of `EVT`
+    fn decode_event(&self, fallback: EventSeverity) -> Result<HubEvent, DecodeError> {
+        let kind = HubEventKind::from_code(self.field("CODE")?);
+        let sensor = match self.fields.contains_key("ID") {
+            true => Some(self.parse_field("ID", |id: &u8| (1..=NUM_SENSORS as u8).contains(id))?),
+            false => None,
+        };
+        if kind == HubEventKind::SensorFault && sensor.is_none() {
+            return Err(DecodeError::MissingField {
+                command: self.command.clone(),
+                field: "ID",
+            });
+        }
+
+        Ok(HubEvent {
+            severity: kind.severity().unwrap_or(fallback),
+            kind,
+            sensor,
+            message: self.fields.get("MSG").cloned(),
+        })
+    }
+
+    pub fn decode(&self) -> Result<Message, DecodeError> {
+        let dimension = |value: &f64| value.is_finite() && *value >= 0.0;
+
+        match self.command.as_str() {
+            "SMS" => Ok(Message::Sms(Measurement {
+                id: self.parse_field("ID", |id: &u8| (1..=NUM_SENSORS as u8).contains(id))?,
+                connected: self.parse_field("C", |c: &u8| *c <= 1)? != 0,
+                value: self.parse_field("V", |_: &u16| true)?,
+            })),
+            "MET" => Ok(Message::Met(LineMetrics {
+                width: self.parse_field("W", dimension)?,
+                length: self.parse_field("L", dimension)?,
+                speed: self.parse_field("S", dimension)?,
+            })),
+            "ACK" => Ok(Message::Ack {
+                command: self.field("CMD")?.to_string(),
+            }),
+            "CFG" => {
+                // Onbekende parameters van nieuwere firmware worden overgeslagen
+                let mut config = HubConfig::new();
+                for spec in PARAMETERS.iter().filter(|spec| self.fields.contains_key(spec.key)) {
+                    let value = self.parse_field(spec.key, |value: &u32| spec.validate(*value).is_ok())?;
+                    config.insert(spec.key.to_string(), value);
+                }
+                Ok(Message::Config(config))
+            }
+            "ERR" => Ok(Message::Error(self.decode_event(EventSeverity::Error)?)),
+            "EVT" => Ok(Message::Event(self.decode_event(EventSeverity::Info)?)),
+            _ => Ok(Message::Unknown {
+                command: self.command.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_frame_fields() {
+        let message = parse_frame("$00012345:SMS:ID=3:C=1:V=512#").unwrap();
+        assert_eq!(message.timestamp, "00012345");
+        assert_eq!(message.command, "SMS");
+        assert_eq!(message.fields.get("V").map(String::as_str), Some("512"));
+        assert_eq!(message.to_frame(), "$00012345:SMS:C=1:ID=3:V=512#");
+
+        assert!(parse_frame("$00012345#").is_none());
+        assert!(parse_frame("00012345:SMS#").is_none());
+        assert!(parse_frame("$00012345:SMS").is_none());
+    }
+
+    #[test]
+    fn decodes_frames_split_over_reads() {
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.push(b"ruis$0001:SMS:ID=1").is_empty());
+        let results = decoder.push(b":C=1:V=7#$0002:MET:W=10:L=20:S=3#");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().timestamp, "0001");
+        assert_eq!(results[1].as_ref().unwrap().command, "MET");
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        let mut decoder = FrameDecoder::new();
+        let results = decoder.push(b"$0001:SMS$0002:SMS:ID=1:C=1:V=7#$#");
+        assert!(matches!(&results[0], Err(FrameError::Interrupted(frame)) if frame == "$0001:SMS"));
+        assert_eq!(results[1].as_ref().unwrap().timestamp, "0002");
+        assert!(matches!(&results[2], Err(FrameError::Malformed(frame)) if frame == "$#"));
+
+        let results = decoder.push(&[b'$'; MAX_BUFFER_LEN + 1]);
+        assert!(matches!(results[..], [Err(FrameError::Overflow(len))] if len == MAX_BUFFER_LEN + 1));
+    }
+
+    #[test]
+    fn decodes_messages() {
+        let decode = |frame: &str| parse_frame(frame).unwrap().decode();
+        assert!(matches!(
+            decode("$1:SMS:ID=8:C=0:V=3#"),
+            Ok(Message::Sms(Measurement { id: 8, connected: false, value: 3 }))
+        ));
+        assert!(matches!(decode("$1:SMS:ID=9:C=1:V=3#"), Err(DecodeError::InvalidField { field: "ID", .. })));
+        assert!(matches!(decode("$1:MET:W=10:S=3#"), Err(DecodeError::MissingField { field: "L", .. })));
+        assert!(matches!(decode("$1:XYZ#"), Ok(Message::Unknown { .. })));
+    }
+}
@@ -0,0 +1,145 @@
+//! Opnamebestanden met de ruwe frames, om een sessie later opnieuw te verwerken.
+
+use crate::protocol::{parse_frame, ParsedMessage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Eerste regel van elk opnamebestand
+const HEADER: &str = "# metalstream recording v1";
+// Begin van een regel met het recept dat vanaf dat moment actief is
+const RECIPE_PREFIX: &str = "# recipe ";
+// Begin van een regel met de batch waarvan de opname deel uitmaakt
+const BATCH_PREFIX: &str = "# batch ";
+// Begin van een regel met een afgerond product; alleen ter informatie, bij het
+// teruglezen worden producten opnieuw uit de `MET` berichten bepaald
+const ITEM_PREFIX: &str = "# item ";
+
+/// Een opgenomen bericht met de tijd sinds het begin van de opname
+#[derive(Debug, Clone)]
+pub struct RecordedMessage {
+    pub elapsed: Duration,
+    pub message: ParsedMessage,
+}
+
+/// Schrijft berichten naar een opnamebestand, een frame per regel:
+/// `<milliseconden sinds start> $<timestamp>:<command>:...#`
+/// Het actieve recept staat als JSON op een regel `# recipe {...}`, de batch op `# batch {...}`
+/// en elk afgerond product op `# item {...}`
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Maak een nieuwe opname; een bestaand bestand wordt overschreven
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, message: &ParsedMessage) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{} {}",
+            self.started.elapsed().as_millis(),
+            message.to_frame()
+        )?;
+        // Direct wegschrijven zodat een afgebroken opname bruikbaar blijft
+        self.writer.flush()
+    }
+
+    pub fn record_batch(&mut self, batch: &impl Serialize) -> io::Result<()> {
+        writeln!(self.writer, "{}{}", BATCH_PREFIX, serde_json::to_string(batch)?)?;
+        self.writer.flush()
+    }
+
+    pub fn record_recipe(&mut self, recipe: &impl Serialize) -> io::Result<()> {
+        writeln!(self.writer, "{}{}", RECIPE_PREFIX, serde_json::to_string(recipe)?)?;
+        self.writer.flush()
+    }
+
+    pub fn record_item(&mut self, item: &impl Serialize) -> io::Result<()> {
+        writeln!(self.writer, "{}{}", ITEM_PREFIX, serde_json::to_string(item)?)?;
+        self.writer.flush()
+    }
+}
+
+/// Leest een opnamebestand regel voor regel in. Het recept en de batch worden als
+/// `R` en `B` gelezen; zonder eigen types blijven ze ruwe JSON.
+pub struct RecordingReader<R = serde_json::Value, B = serde_json::Value> {
+    lines: io::Lines<BufReader<File>>,
+    /// Het recept dat actief was bij het laatst gelezen bericht
+    pub recipe: Option<R>,
+    pub batch: Option<B>,
+}
+
+impl<R: DeserializeOwned, B: DeserializeOwned> RecordingReader<R, B> {
+    /// Open een opname; geeft een fout als het bestand geen metalstream opname is
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        match lines.next() {
+            Some(Ok(header)) if header == HEADER => Ok(Self {
+                lines,
+                recipe: None,
+                batch: None,
+            }),
+            Some(Err(err)) => Err(err),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is geen metalstream opname", path.display()),
+            )),
+        }
+    }
+}
+
+impl<R: DeserializeOwned, B: DeserializeOwned> Iterator for RecordingReader<R, B> {
+    type Item = io::Result<RecordedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+
+            if let Some(json) = line.strip_prefix(RECIPE_PREFIX) {
+                match serde_json::from_str(json) {
+                    Ok(recipe) => self.recipe = Some(recipe),
+                    Err(err) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err))),
+                }
+                continue;
+            }
+            if let Some(json) = line.strip_prefix(BATCH_PREFIX) {
+                match serde_json::from_str(json) {
+                    Ok(batch) => self.batch = Some(batch),
+                    Err(err) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err))),
+                }
+                continue;
+            }
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parsed = line.split_once(' ').and_then(|(elapsed, frame)| {
+                Some(RecordedMessage {
+                    elapsed: Duration::from_millis(elapsed.parse().ok()?),
+                    message: parse_frame(frame.trim())?,
+                })
+            });
+
+            return Some(parsed.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("ongeldige regel in opname: {}", line))
+            }));
+        }
+    }
+}
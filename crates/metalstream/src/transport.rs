@@ -0,0 +1,247 @@
+//! De seriele verbinding met de hub: de poort vinden, frames lezen en opdrachten sturen.
+
+use crate::protocol::{FrameDecoder, FrameError, ParsedMessage};
+use chrono::{DateTime, Local};
+use serialport::{available_ports, SerialPort, SerialPortInfo, SerialPortType};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Wordt gebruikt voor het scannen naar de Metalshare Hub
+pub const KNOWN_MANUFACTURER: &str = "Espressif";
+/// Baudrate van de seriele verbinding met de hub
+pub const BAUDRATE: u32 = 115200;
+/// Stukken ruwe data die op de meeluisteraar mogen wachten. Leest die niet (de GUI
+/// tekent bijvoorbeeld niet), dan worden ze daarboven overgeslagen en geteld.
+const TAP_QUEUE: usize = 10_000;
+
+/// De poort waarmee de lees-thread verbonden is
+#[derive(Clone)]
+pub struct ConnectionInfo {
+    pub port_path: String,
+    pub baudrate: u32,
+}
+
+impl ConnectionInfo {
+    pub fn new(port_path: String, baudrate: u32) -> Self {
+        ConnectionInfo {
+            port_path,
+            baudrate,
+        }
+    }
+}
+
+/// Of de poort een Metalstream Hub is, op basis van de USB fabrikant
+pub fn is_hub(port: &SerialPortInfo) -> bool {
+    if let SerialPortType::UsbPort(usb) = &port.port_type {
+        if let Some(manufacturer) = &usb.manufacturer {
+            return manufacturer == KNOWN_MANUFACTURER;
+        }
+    }
+    false
+}
+
+/// Zoek naar de poort van de Metalstream Hub
+pub fn find_hub_port() -> Option<String> {
+    available_ports()
+        .ok()?
+        .into_iter()
+        .filter(is_hub)
+        .map(|port| port.port_name)
+        .next_back()
+}
+
+/// Richting van de bytes: van (`Rx`) of naar (`Tx`) de hub
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// Ruwe bytes zoals ze over de lijn gingen, nog voor het parsen van frames
+pub struct RawChunk {
+    pub time: DateTime<Local>,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+/// Gedeelde toegang tot de open poort: bytes naar de hub sturen en meeluisteren
+/// met de ruwe datastroom. Goedkoop te clonen.
+#[derive(Clone, Default)]
+pub struct SerialLink {
+    port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
+    tap: Arc<Mutex<Option<SyncSender<RawChunk>>>>,
+    dropped: Arc<AtomicU64>,
+    // Laat de lees-thread de poort vrijgeven, bijvoorbeeld voor een firmware update
+    suspended: Arc<AtomicBool>,
+}
+
+impl SerialLink {
+    /// Ontvang voortaan alle ruwe bytes in beide richtingen
+    pub fn tap(&self) -> Receiver<RawChunk> {
+        let (sender, receiver) = sync_channel(TAP_QUEUE);
+        *self.tap.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Aantal stukken dat is overgeslagen omdat de meeluisteraar achterliep
+    pub fn dropped_chunks(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn send(&self, bytes: &[u8]) -> std::io::Result<()> {
+        let mut port = self.port.lock().unwrap();
+        let port = port
+            .as_mut()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotConnected, "geen verbinding met de hub"))?;
+        port.write_all(bytes)?;
+        port.flush()?;
+        self.record(Direction::Tx, bytes);
+        Ok(())
+    }
+
+    fn record(&self, direction: Direction, bytes: &[u8]) {
+        let mut tap = self.tap.lock().unwrap();
+        if let Some(sender) = tap.as_ref() {
+            let chunk = RawChunk {
+                time: Local::now(),
+                direction,
+                bytes: bytes.to_vec(),
+            };
+            match sender.try_send(chunk) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                        log::warn!("De meeluisteraar loopt achter; ruwe data wordt overgeslagen");
+                    }
+                }
+                Err(TrySendError::Disconnected(_)) => *tap = None,
+            }
+        }
+    }
+
+    fn attach(&self, port: Option<Box<dyn SerialPort>>) {
+        *self.port.lock().unwrap() = port;
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.port.lock().unwrap().is_some()
+    }
+
+    /// Sluit de poort tot `resume`; de lees-thread maakt daarna zelf weer verbinding
+    pub fn suspend(&self) {
+        self.suspended.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.suspended.store(false, Ordering::Relaxed);
+    }
+
+    fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::Relaxed)
+    }
+}
+
+/// Handel de seriele communicatie in een aparte thread af. Geparste berichten en
+/// afgewezen frames worden via `sender` doorgestuurd, zodat de ontvanger ze kan
+/// tellen; de thread stopt als de ontvanger weg is.
+pub fn spawn_reader(
+    port_path: String,
+    sender: Sender<Result<ParsedMessage, FrameError>>,
+    is_connected: Arc<AtomicBool>,
+    connection_info: Arc<Mutex<Option<ConnectionInfo>>>,
+    link: SerialLink,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || loop {
+        if link.is_suspended() {
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        let port_result = serialport::new(&port_path, BAUDRATE)
+            .timeout(Duration::from_secs(1))
+            .open();
+
+        match port_result {
+            Ok(mut port) => {
+                is_connected.store(true, Ordering::Relaxed);
+                *connection_info.lock().unwrap() = Some(ConnectionInfo::new(port_path.clone(), BAUDRATE));
+                link.attach(port.try_clone().ok());
+
+                let mut decoder = FrameDecoder::new();
+                let mut buffer = vec![0; 1024];
+                loop {
+                    if link.is_suspended() {
+                        is_connected.store(false, Ordering::Relaxed);
+                        link.attach(None);
+                        break;
+                    }
+
+                    match port.read(&mut buffer) {
+                        Ok(size) if size > 0 => {
+                            link.record(Direction::Rx, &buffer[..size]);
+                            for result in decoder.push(&buffer[..size]) {
+                                if sender.send(result).is_err() {
+                                    is_connected.store(false, Ordering::Relaxed);
+                                    link.attach(None);
+                                    return;
+                                }
+                            }
+                        }
+                        Ok(_) => {
+                            continue;
+                        }
+                        Err(err) => match err.kind() {
+                            // Negeer timeouts
+                            std::io::ErrorKind::TimedOut => {
+                                continue;
+                            }
+                            _ => {
+                                log::error!("Serial error {}", err);
+                                // Zet de verbinding naar false
+                                is_connected.store(false, Ordering::Relaxed);
+                                link.attach(None);
+                                break;
+                            }
+                        },
+                    }
+                }
+            }
+            Err(err) => {
+                log::warn!("Kan {} niet openen: {}", port_path, err);
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tap_drops_chunks_when_the_listener_falls_behind() {
+        let link = SerialLink::default();
+        // Zonder meeluisteraar wordt er niets bijgehouden
+        link.record(Direction::Rx, b"$1:SMS#");
+        assert_eq!(link.dropped_chunks(), 0);
+
+        let receiver = link.tap();
+        for _ in 0..TAP_QUEUE + 5 {
+            link.record(Direction::Rx, b"$1:SMS#");
+        }
+        assert_eq!(link.dropped_chunks(), 5);
+        assert_eq!(receiver.try_iter().count(), TAP_QUEUE);
+
+        link.record(Direction::Tx, b"CAL\n");
+        let chunk = receiver.try_recv().unwrap();
+        assert_eq!((chunk.direction, chunk.bytes.as_slice()), (Direction::Tx, b"CAL\n".as_slice()));
+
+        // Een meeluisteraar die weg is wordt losgelaten
+        drop(receiver);
+        link.record(Direction::Rx, b"$2:SMS#");
+        assert!(link.tap.lock().unwrap().is_none());
+    }
+}
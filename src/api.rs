@@ -0,0 +1,248 @@
+use crate::live::{Command, LiveHandle};
+use serde::Serialize;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+#[derive(clap::Args)]
+pub struct ApiArgs {
+    /// Start de HTTP/WebSocket API
+    #[arg(long)]
+    pub api: bool,
+    /// Adres waarop de API luistert
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    pub api_addr: SocketAddr,
+    /// Token dat clients moeten meesturen; standaard wordt er een gegenereerd
+    #[arg(long, env = "METALSTREAM_API_TOKEN")]
+    pub api_token: Option<String>,
+}
+
+// Start de API in een eigen thread. Elke WebSocket verbinding krijgt ook een eigen thread.
+pub fn start(args: &ApiArgs, live: LiveHandle) -> std::io::Result<()> {
+    let server = Server::http(args.api_addr).map_err(std::io::Error::other)?;
+    re_log::info!("API luistert op http://{}", args.api_addr);
+
+    // Het token hoort niet in het logbestand; een gegenereerd token gaat alleen
+    // naar stderr, zodat de gebruiker het een keer kan overnemen
+    let token = match &args.api_token {
+        Some(token) => token.clone(),
+        None => {
+            let token = format!("{:032x}", rand::random::<u128>());
+            eprintln!("API token: {}", token);
+            token
+        }
+    };
+
+    serve(server, token, live);
+    Ok(())
+}
+
+fn serve(server: Server, token: String, live: LiveHandle) {
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &token, &live);
+        }
+    });
+}
+
+fn handle(request: Request, token: &str, live: &LiveHandle) {
+    if !is_authorized(&request, token) {
+        respond(request, 401, &Error { error: "unauthorized" });
+        return;
+    }
+
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    match (request.method(), path.as_str()) {
+        (Method::Get, "/api/status") => respond(request, 200, &live.snapshot()),
+        (Method::Get, "/api/measurements") => respond(request, 200, &live.snapshot().measurements),
+        (Method::Get, "/api/alarms") => respond(request, 200, &live.snapshot().alarms),
+        (Method::Get, "/api/stream") => stream(request, live),
+        (Method::Post, "/api/start") => command(request, live, Command::Start),
+        (Method::Post, "/api/stop") => command(request, live, Command::Stop),
+        (Method::Post, "/api/calibrate") => command(request, live, Command::Calibrate),
+        (Method::Post, "/api/alarms/acknowledge") => command(request, live, Command::AcknowledgeAlarms),
+        _ => respond(request, 404, &Error { error: "not found" }),
+    }
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: &'static str,
+}
+
+// Het token mag als `Authorization: Bearer <token>` header of als `?token=` worden meegegeven.
+// Dat laatste is nodig voor WebSockets vanuit een browser.
+fn is_authorized(request: &Request, token: &str) -> bool {
+    let from_header = request.headers().iter().any(|header| {
+        header.field.equiv("Authorization")
+            && header.value.as_str().strip_prefix("Bearer ").is_some_and(|given| token_matches(given, token))
+    });
+    let from_query = request
+        .url()
+        .split_once('?')
+        .map(|(_, query)| {
+            query
+                .split('&')
+                .any(|pair| pair.strip_prefix("token=").is_some_and(|given| token_matches(given, token)))
+        })
+        .unwrap_or(false);
+
+    from_header || from_query
+}
+
+// Vergelijk in een vaste tijd, zodat de duur van een mislukte poging niet verraadt
+// hoeveel tekens van het token goed waren
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn json_response(status: u16, body: &impl Serialize) -> Response<Cursor<Vec<u8>>> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+}
+
+fn respond(request: Request, status: u16, body: &impl Serialize) {
+    request.respond(json_response(status, body)).ok();
+}
+
+fn command(request: Request, live: &LiveHandle, command: Command) {
+    let accepted = format!("{:?}", command);
+    live.send_command(command);
+    respond(request, 202, &serde_json::json!({ "accepted": accepted }));
+}
+
+fn stream(request: Request, live: &LiveHandle) {
+    let Some(key) = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| header.value.to_string())
+    else {
+        respond(request, 400, &Error { error: "expected websocket upgrade" });
+        return;
+    };
+
+    // `Upgrade` en `Connection` worden door tiny_http zelf toegevoegd
+    let accept_key = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept_key.as_bytes()).unwrap());
+
+    let events = live.subscribe();
+    let stream = request.upgrade("websocket", response);
+
+    std::thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for event in events {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if socket.send(Message::Text(text.into())).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live::{self, LiveEvent};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    const TOKEN: &str = "geheim";
+
+    fn start_local() -> (SocketAddr, LiveHandle, Receiver<Command>) {
+        let (live, commands) = live::channel_pair();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        serve(server, TOKEN.to_string(), live.clone());
+        (addr, live, commands)
+    }
+
+    // Een kaal HTTP/1.1 verzoek; geeft de statuscode en de body
+    fn request(addr: SocketAddr, method: &str, path: &str, authorization: Option<&str>) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: 0\r\n", method, path, addr);
+        if let Some(authorization) = authorization {
+            request.push_str(&format!("Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split(' ').nth(1).unwrap().parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+        (status, body)
+    }
+
+    #[test]
+    fn rejects_missing_or_wrong_token() {
+        let (addr, _live, _commands) = start_local();
+        assert_eq!(request(addr, "GET", "/api/status", None).0, 401);
+        assert_eq!(request(addr, "GET", "/api/status", Some("Bearer fout")).0, 401);
+        assert_eq!(request(addr, "GET", "/api/status?token=geheim2", None).0, 401);
+    }
+
+    #[test]
+    fn status_with_header_or_query_token() {
+        let (addr, _live, _commands) = start_local();
+        let (status, body) = request(addr, "GET", "/api/status", Some("Bearer geheim"));
+        assert_eq!(status, 200);
+        assert!(serde_json::from_str::<serde_json::Value>(&body).unwrap().get("connected").is_some());
+        assert_eq!(request(addr, "GET", "/api/status?token=geheim", None).0, 200);
+    }
+
+    #[test]
+    fn start_sends_command() {
+        let (addr, _live, commands) = start_local();
+        assert_eq!(request(addr, "POST", "/api/start", Some("Bearer geheim")).0, 202);
+        assert!(matches!(commands.recv_timeout(Duration::from_secs(1)), Ok(Command::Start)));
+    }
+
+    #[test]
+    fn unknown_path_is_not_found() {
+        let (addr, _live, _commands) = start_local();
+        assert_eq!(request(addr, "GET", "/api/bestaat-niet", Some("Bearer geheim")).0, 404);
+    }
+
+    #[test]
+    fn websocket_streams_events() {
+        let (addr, live, _commands) = start_local();
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let url = format!("ws://{}/api/stream?token={}", addr, TOKEN);
+        let (mut socket, response) = tungstenite::client(url, stream).unwrap();
+        assert_eq!(response.status(), 101);
+
+        let message = metalstream::protocol::parse_frame("$12:SMS:ID=1:C=1:V=512#").unwrap();
+        live.publish(LiveEvent::Message(message));
+
+        let Message::Text(text) = socket.read().unwrap() else {
+            panic!("verwacht een tekstbericht");
+        };
+        let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(event["type"], "message");
+        assert_eq!(event["command"], "SMS");
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(token_matches("geheim", "geheim"));
+        assert!(!token_matches("geheiM", "geheim"));
+        assert!(!token_matches("geheim2", "geheim"));
+        assert!(!token_matches("", "geheim"));
+    }
+}
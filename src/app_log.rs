@@ -0,0 +1,461 @@
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use egui_extras::{Column, TableBuilder};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+
+// Aantal regels dat in de applicatie bewaard blijft
+const APP_LOG_CAPACITY: usize = 10_000;
+// Grootte waarbij het logbestand wordt geroteerd en het aantal oude bestanden dat blijft staan
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+const KEEP_FILES: usize = 3;
+
+// Onze eigen modules loggen standaard op Info, afhankelijkheden alleen vanaf Warn
+const APP_MODULE: &str = "desktop";
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+const APP_LEVEL: LevelFilter = LevelFilter::Info;
+
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+pub struct AppLogRecord {
+    // Oplopend volgnummer, zodat de tab bijhoudt welke regels hij al gefilterd heeft
+    pub seq: u64,
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl AppLogRecord {
+    fn to_line(&self) -> String {
+        format!(
+            "{} {:<5} {}: {}",
+            self.time.format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+// Verbositeit per module; de langste module die past bepaalt het niveau
+struct Levels {
+    default: LevelFilter,
+    modules: BTreeMap<String, LevelFilter>,
+    // Hoogste niveau dat de `log` crate moet doorlaten naast de andere loggers
+    base: LevelFilter,
+}
+
+impl Levels {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module.as_str() || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.values().copied().chain([self.default, self.base]).max().unwrap_or(self.default)
+    }
+}
+
+// Logbestand dat bij `MAX_FILE_SIZE` wordt doorgeschoven naar `.1`, `.2`, ...
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for index in (1..KEEP_FILES).rev() {
+            std::fs::rename(self.rotated_path(index), self.rotated_path(index + 1)).ok();
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+// `$XDG_STATE_HOME/metalstream`, of `~/.local/state/metalstream` als die niet is gezet
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+        .map(|dir| dir.join("metalstream"))
+}
+
+struct Records {
+    entries: VecDeque<Arc<AppLogRecord>>,
+    next_seq: u64,
+    // Verhoogd bij wissen zodat de tab opnieuw moet filteren
+    generation: u64,
+    targets: BTreeSet<String>,
+    file: Option<RotatingFile>,
+}
+
+impl Records {
+    fn first_seq(&self) -> u64 {
+        self.entries.front().map(|entry| entry.seq).unwrap_or(self.next_seq)
+    }
+}
+
+// Het applicatielog: alle berichten die via `re_log`/`log` binnenkomen, in het
+// geheugen voor de Application Log tab en in een roterend bestand.
+pub struct AppLog {
+    levels: RwLock<Levels>,
+    records: Mutex<Records>,
+    pub file_path: Option<PathBuf>,
+}
+
+pub static APP_LOG: LazyLock<AppLog> = LazyLock::new(AppLog::new);
+
+impl AppLog {
+    fn new() -> Self {
+        let file_path = state_dir().map(|dir| dir.join("metalstream.log"));
+        let file = file_path.clone().and_then(|path| RotatingFile::open(path).ok());
+
+        Self {
+            levels: RwLock::new(Levels {
+                default: DEFAULT_LEVEL,
+                modules: BTreeMap::from([(APP_MODULE.to_string(), APP_LEVEL)]),
+                base: log::max_level(),
+            }),
+            records: Mutex::new(Records {
+                entries: VecDeque::new(),
+                next_seq: 0,
+                generation: 0,
+                targets: BTreeSet::new(),
+                file,
+            }),
+            file_path,
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.read().unwrap().level_for(metadata.target())
+    }
+
+    fn push(&self, record: &Record) {
+        let mut records = self.records.lock().unwrap();
+        let entry = AppLogRecord {
+            seq: records.next_seq,
+            time: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        // Lukt schrijven niet, dan blijft het log alleen in het geheugen
+        if let Some(file) = records.file.as_mut() {
+            if file.write_line(&entry.to_line()).is_err() {
+                records.file = None;
+            }
+        }
+
+        if !records.targets.contains(&entry.target) {
+            records.targets.insert(entry.target.clone());
+        }
+        records.next_seq += 1;
+        records.entries.push_back(Arc::new(entry));
+        while records.entries.len() > APP_LOG_CAPACITY {
+            records.entries.pop_front();
+        }
+    }
+
+    pub fn default_level(&self) -> LevelFilter {
+        self.levels.read().unwrap().default
+    }
+
+    pub fn module_levels(&self) -> BTreeMap<String, LevelFilter> {
+        self.levels.read().unwrap().modules.clone()
+    }
+
+    pub fn set_default_level(&self, level: LevelFilter) {
+        let mut levels = self.levels.write().unwrap();
+        levels.default = level;
+        log::set_max_level(levels.max_level());
+    }
+
+    // Stel het niveau van een module in, of verwijder de uitzondering met `None`
+    pub fn set_module_level(&self, module: &str, level: Option<LevelFilter>) {
+        let mut levels = self.levels.write().unwrap();
+        match level {
+            Some(level) => levels.modules.insert(module.to_string(), level),
+            None => levels.modules.remove(module),
+        };
+        log::set_max_level(levels.max_level());
+    }
+
+    pub fn clear(&self) {
+        let mut records = self.records.lock().unwrap();
+        records.entries.clear();
+        records.generation += 1;
+    }
+}
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        APP_LOG.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            APP_LOG.push(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = APP_LOG.records.lock().unwrap().file.as_mut() {
+            file.file.flush().ok();
+        }
+    }
+}
+
+// Zet de logging van re_log (naar de terminal) op en hang het applicatielog eraan
+pub fn init() {
+    re_log::setup_logging();
+    APP_LOG.levels.write().unwrap().base = log::max_level();
+
+    if re_log::add_boxed_logger(Box::new(Logger)).is_err() {
+        eprintln!("Applicatielog kon niet worden gekoppeld");
+        return;
+    }
+    log::set_max_level(APP_LOG.levels.read().unwrap().max_level());
+
+    let persisted = APP_LOG.records.lock().unwrap().file.is_some();
+    match &APP_LOG.file_path {
+        Some(path) if persisted => re_log::info!("Applicatielog wordt bewaard in {}", path.display()),
+        Some(path) => re_log::warn!("Kan {} niet openen, applicatielog wordt niet bewaard", path.display()),
+        None => re_log::warn!("Geen state map gevonden, applicatielog wordt niet bewaard"),
+    }
+}
+
+fn level_color(level: Level, visuals: &egui::Visuals) -> egui::Color32 {
+    match level {
+        Level::Error => visuals.error_fg_color,
+        Level::Warn => visuals.warn_fg_color,
+        Level::Info => visuals.text_color(),
+        Level::Debug | Level::Trace => visuals.weak_text_color(),
+    }
+}
+
+fn level_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, level: &mut LevelFilter) -> bool {
+    let before = *level;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(level.as_str())
+        .width(70.0)
+        .show_ui(ui, |ui| {
+            for option in LEVELS {
+                ui.selectable_value(level, option, option.as_str());
+            }
+        });
+    *level != before
+}
+
+// Tab met de diagnostiek van de applicatie zelf, los van de berichten van de hub
+pub struct AppLogTab {
+    min_level: LevelFilter,
+    text: String,
+    paused: bool,
+    show_settings: bool,
+    new_module: String,
+    // Gefilterde regels; de lock op het log wordt niet vastgehouden tijdens het tekenen,
+    // anders zou een logbericht vanuit egui zelf vastlopen.
+    visible: VecDeque<Arc<AppLogRecord>>,
+    cache_key: Option<(u64, LevelFilter, String)>,
+    scanned_until: u64,
+}
+
+impl AppLogTab {
+    pub fn new() -> Self {
+        Self {
+            min_level: LevelFilter::Trace,
+            text: String::new(),
+            paused: false,
+            show_settings: false,
+            new_module: String::new(),
+            visible: VecDeque::new(),
+            cache_key: None,
+            scanned_until: 0,
+        }
+    }
+
+    // Filter alleen de regels die er sinds het vorige frame bij zijn gekomen; alles
+    // opnieuw als het filter is veranderd of het log is gewist
+    fn update_visible(&mut self) {
+        let records = APP_LOG.records.lock().unwrap();
+        let key = (records.generation, self.min_level, self.text.to_lowercase());
+        if self.cache_key.as_ref() != Some(&key) {
+            self.visible.clear();
+            self.scanned_until = 0;
+        }
+        let (_, level, text) = self.cache_key.insert(key);
+
+        let first_seq = records.first_seq();
+        let skip = self.scanned_until.saturating_sub(first_seq) as usize;
+        for record in records.entries.iter().skip(skip) {
+            if record.level <= *level && (text.is_empty() || record.to_line().to_lowercase().contains(text.as_str())) {
+                self.visible.push_back(record.clone());
+            }
+        }
+        self.scanned_until = records.next_seq;
+
+        // Regels die uit het log zijn gevallen
+        while self.visible.front().is_some_and(|record| record.seq < first_seq) {
+            self.visible.pop_front();
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, targets: &BTreeSet<String>) {
+        ui.horizontal(|ui| {
+            ui.label("Standaard niveau");
+            let mut level = APP_LOG.default_level();
+            if level_combo(ui, "app_log_default_level", &mut level) {
+                APP_LOG.set_default_level(level);
+            }
+        });
+
+        for (module, mut level) in APP_LOG.module_levels() {
+            ui.horizontal(|ui| {
+                ui.monospace(&module);
+                if level_combo(ui, ("app_log_module_level", &module), &mut level) {
+                    APP_LOG.set_module_level(&module, Some(level));
+                }
+                if ui.small_button("Verwijder").clicked() {
+                    APP_LOG.set_module_level(&module, None);
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("app_log_new_module")
+                .selected_text(if self.new_module.is_empty() { "Module" } else { &self.new_module })
+                .show_ui(ui, |ui| {
+                    for target in targets {
+                        ui.selectable_value(&mut self.new_module, target.clone(), target);
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.new_module).hint_text("bijv. desktop::serial").desired_width(150.0));
+            if ui.add_enabled(!self.new_module.is_empty(), egui::Button::new("Toevoegen")).clicked() {
+                APP_LOG.set_module_level(self.new_module.trim(), Some(LevelFilter::Debug));
+                self.new_module.clear();
+            }
+        });
+    }
+}
+
+impl RenderableTab for AppLogTab {
+    fn title(&self) -> &str {
+        "Application Log"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, _state: &mut GlobalState) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Niveau");
+            level_combo(ui, "app_log_min_level", &mut self.min_level);
+            ui.add(egui::TextEdit::singleline(&mut self.text).hint_text("Zoeken").desired_width(150.0));
+            ui.separator();
+            ui.toggle_value(&mut self.paused, "Pauzeer scrollen");
+            ui.toggle_value(&mut self.show_settings, "Verbositeit");
+            if ui.button("Wissen").clicked() {
+                APP_LOG.clear();
+            }
+            if let Some(path) = &APP_LOG.file_path {
+                ui.weak(path.display().to_string());
+            }
+        });
+
+        if self.show_settings {
+            ui.separator();
+            let targets = APP_LOG.records.lock().unwrap().targets.clone();
+            self.settings_ui(ui, &targets);
+        }
+        ui.separator();
+
+        self.update_visible();
+        let visible = &self.visible;
+
+        let row_height = egui::TextStyle::Monospace.resolve(ui.style()).size + 4.0;
+        let visuals = ui.visuals().clone();
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .stick_to_bottom(!self.paused)
+            .column(Column::auto().at_least(90.0))
+            .column(Column::auto().at_least(50.0))
+            .column(Column::auto().at_least(120.0))
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for title in ["Tijd", "Niveau", "Module", "Bericht"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, visible.len(), |mut row| {
+                    let record = &visible[row.index()];
+                    let color = level_color(record.level, &visuals);
+                    let text = |text: String| egui::RichText::new(text).monospace().color(color);
+
+                    row.col(|ui| {
+                        ui.label(text(record.time.format("%H:%M:%S%.3f").to_string()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(record.level.to_string()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(record.target.clone()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(record.message.clone())).on_hover_text(record.to_line());
+                    });
+                });
+            });
+    }
+}
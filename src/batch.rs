@@ -0,0 +1,697 @@
+use crate::items::{Item, Verdict};
+use crate::live::Command;
+use crate::recipes::Recipe;
+use crate::recording::Recorder;
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use egui_extras::{Column, TableBuilder};
+use metalstream::processing::{unix_millis, Alarm, Detection};
+use metalstream::protocol::ParsedMessage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Gegevens die bij het starten van een batch worden ingevuld
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchInfo {
+    pub lot: String,
+    pub recipe: Option<String>,
+    pub operator: String,
+    pub notes: String,
+    // Unix tijd in milliseconden
+    pub started_at: u64,
+    // Productielijn en seriele poort van de hub voor rapporten
+    #[serde(default)]
+    pub line: String,
+    #[serde(default)]
+    pub hub: Option<String>,
+    // Versie van de firmware op de hub, als die bekend is
+    #[serde(default)]
+    pub firmware: Option<String>,
+}
+
+// Tellers die tijdens een batch worden bijgehouden
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchCounts {
+    pub measurements: u64,
+    pub items_passed: u64,
+    pub rejects: u64,
+    pub detections: u64,
+    pub alarms: u64,
+    pub connection_losses: u64,
+    // Tijd dat de detectie stil stond of de hub niet verbonden was
+    pub downtime_ms: u64,
+}
+
+impl BatchCounts {
+    pub fn add(&mut self, other: &BatchCounts) {
+        self.measurements += other.measurements;
+        self.items_passed += other.items_passed;
+        self.rejects += other.rejects;
+        self.detections += other.detections;
+        self.alarms += other.alarms;
+        self.connection_losses += other.connection_losses;
+        self.downtime_ms += other.downtime_ms;
+    }
+}
+
+// Een lopende batch wordt al bewaard, zodat hij een crash of stroomstoring overleeft
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    #[default]
+    Ended,
+    Running,
+    // Liep nog toen het programma stopte; de tellers zijn van de laatste keer bewaren
+    Interrupted,
+}
+
+// Het resultaat van een batch, als JSON naast de opname bewaard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSummary {
+    #[serde(flatten)]
+    pub info: BatchInfo,
+    pub ended_at: u64,
+    pub counts: BatchCounts,
+    // Opname met alle berichten uit de batch
+    pub recording: Option<PathBuf>,
+    #[serde(default)]
+    pub status: BatchStatus,
+}
+
+impl BatchSummary {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.ended_at.saturating_sub(self.info.started_at))
+    }
+}
+
+// `$XDG_STATE_HOME/metalstream/batches`
+pub fn batches_dir() -> Option<PathBuf> {
+    crate::app_log::state_dir().map(|dir| dir.join("batches"))
+}
+
+pub fn format_time(unix_millis: u64) -> String {
+    DateTime::from_timestamp_millis(unix_millis as i64)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// Zo vaak worden de tellers van de lopende batch bewaard
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Batch {
+    info: BatchInfo,
+    counts: BatchCounts,
+    recorder: Option<Recorder>,
+    recording: Option<PathBuf>,
+    connected: bool,
+    down_since: Option<Instant>,
+    saved_at: Instant,
+}
+
+impl Batch {
+    fn summary(&self, status: BatchStatus) -> BatchSummary {
+        BatchSummary {
+            info: self.info.clone(),
+            ended_at: unix_millis(),
+            counts: self.counts.clone(),
+            recording: self.recording.clone(),
+            status,
+        }
+    }
+
+    fn add_downtime(&mut self, until: Instant) {
+        if let Some(since) = self.down_since.take() {
+            self.counts.downtime_ms += until.duration_since(since).as_millis() as u64;
+        }
+    }
+}
+
+// De lopende batch en de eerder afgeronde batches
+#[derive(Default)]
+pub struct Batches {
+    dir: Option<PathBuf>,
+    current: Option<Batch>,
+    // Gedeeld met de snapshots voor de GUI; alleen bij een nieuwe batch wordt er gekopieerd
+    history: Arc<Vec<BatchSummary>>,
+}
+
+impl Batches {
+    // Batches die nog liepen toen het programma stopte, worden als onderbroken afgesloten
+    pub fn load() -> Self {
+        Self::load_from(batches_dir(), true)
+    }
+
+    // Alleen lezen, voor rapporten vanaf de command line terwijl de GUI misschien een batch draait
+    pub fn read_history() -> Arc<Vec<BatchSummary>> {
+        Self::load_from(batches_dir(), false).history
+    }
+
+    fn load_from(dir: Option<PathBuf>, recover: bool) -> Self {
+        let mut history = Vec::new();
+        if let Some(entries) = dir.as_ref().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                let summary = std::fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| serde_json::from_str::<BatchSummary>(&json).map_err(|err| err.to_string()));
+                match summary {
+                    Ok(mut summary) => {
+                        // Het programma stopte tijdens deze batch
+                        if recover && summary.status == BatchStatus::Running {
+                            re_log::warn!(
+                                "Batch {} is niet afgerond; afgesloten met de tellers van {}",
+                                summary.info.lot,
+                                format_time(summary.ended_at)
+                            );
+                            summary.status = BatchStatus::Interrupted;
+                            if let Err(err) = save_summary(&path, &summary) {
+                                re_log::error!("Kan batch {} niet bijwerken: {}", summary.info.lot, err);
+                            }
+                        }
+                        history.push(summary);
+                    }
+                    Err(err) => re_log::warn!("Batch {} overgeslagen: {}", path.display(), err),
+                }
+            }
+        }
+        history.sort_by_key(|summary| std::cmp::Reverse(summary.info.started_at));
+
+        Self {
+            dir,
+            current: None,
+            history: Arc::new(history),
+        }
+    }
+
+    pub fn current(&self) -> Option<(&BatchInfo, &BatchCounts)> {
+        self.current.as_ref().map(|batch| (&batch.info, &batch.counts))
+    }
+
+    pub fn lot(&self) -> Option<&str> {
+        self.current.as_ref().map(|batch| batch.info.lot.as_str())
+    }
+
+    pub fn history(&self) -> Arc<Vec<BatchSummary>> {
+        self.history.clone()
+    }
+
+    // Begin een batch en een opname van alle berichten erin
+    pub fn start(&mut self, info: BatchInfo, recipe: Option<&Recipe>, connected: bool) -> Result<(), String> {
+        if self.current.is_some() {
+            return Err("er loopt al een batch".to_string());
+        }
+        if info.lot.trim().is_empty() {
+            return Err("een batch heeft een lotnummer nodig".to_string());
+        }
+
+        let (recorder, recording) = match create_recording(self.dir.as_deref(), &info, recipe) {
+            Ok((recorder, path)) => (Some(recorder), Some(path)),
+            Err(err) => {
+                re_log::error!("Kan de opname van batch {} niet maken: {}", info.lot, err);
+                (None, None)
+            }
+        };
+
+        re_log::info!("Batch {} gestart door {}", info.lot, info.operator);
+        let batch = Batch {
+            info,
+            counts: BatchCounts::default(),
+            recorder,
+            recording,
+            connected,
+            down_since: None,
+            saved_at: Instant::now(),
+        };
+        self.save(&batch.summary(BatchStatus::Running));
+        self.current = Some(batch);
+        Ok(())
+    }
+
+    // Rond de lopende batch af en bewaar de samenvatting
+    pub fn end(&mut self) -> Option<BatchSummary> {
+        let mut batch = self.current.take()?;
+        batch.add_downtime(Instant::now());
+
+        let summary = batch.summary(BatchStatus::Ended);
+        self.save(&summary);
+        re_log::info!(
+            "Batch {} afgerond: {} goed, {} afgekeurd",
+            summary.info.lot,
+            summary.counts.items_passed,
+            summary.counts.rejects
+        );
+
+        Arc::make_mut(&mut self.history).insert(0, summary.clone());
+        Some(summary)
+    }
+
+    // Elk bericht van de hub komt in de opname van de batch
+    pub fn on_message(&mut self, message: &ParsedMessage) {
+        let Some(batch) = &mut self.current else {
+            return;
+        };
+        if let Some(recorder) = &mut batch.recorder {
+            if let Err(err) = recorder.record(message) {
+                re_log::error!("Opname van batch {} gestopt: {}", batch.info.lot, err);
+                batch.recorder = None;
+            }
+        }
+    }
+
+    pub fn on_measurement(&mut self) {
+        if let Some(batch) = &mut self.current {
+            batch.counts.measurements += 1;
+        }
+    }
+
+    // Een afgerond product telt mee en komt in de opname
+    pub fn on_item(&mut self, item: &Item) {
+        let Some(batch) = &mut self.current else {
+            return;
+        };
+        match item.verdict {
+            Verdict::Pass => batch.counts.items_passed += 1,
+            Verdict::Reject => batch.counts.rejects += 1,
+        }
+        if let Some(recorder) = &mut batch.recorder {
+            recorder.record_item(item).ok();
+        }
+    }
+
+    pub fn on_detection(&mut self, detection: &mut Detection) {
+        if let Some(batch) = &mut self.current {
+            batch.counts.detections += 1;
+            detection.batch = Some(batch.info.lot.clone());
+        }
+    }
+
+    pub fn on_alarm(&mut self, alarm: &mut Alarm) {
+        if let Some(batch) = &mut self.current {
+            batch.counts.alarms += 1;
+            alarm.batch = Some(batch.info.lot.clone());
+        }
+    }
+
+    // Een ander recept midden in de batch wordt in de opname vastgelegd
+    pub fn on_recipe(&mut self, recipe: &Recipe) {
+        if let Some(recorder) = self.current.as_mut().and_then(|batch| batch.recorder.as_mut()) {
+            recorder.record_recipe(recipe).ok();
+        }
+    }
+
+    // Houd verbindingsverlies en stilstand bij; wordt elk frame aangeroepen
+    pub fn update(&mut self, connected: bool, running: bool) {
+        let Some(batch) = &mut self.current else {
+            return;
+        };
+        if batch.connected && !connected {
+            batch.counts.connection_losses += 1;
+        }
+        batch.connected = connected;
+
+        let now = Instant::now();
+        match (connected && running, batch.down_since) {
+            (false, None) => batch.down_since = Some(now),
+            (true, Some(_)) => batch.add_downtime(now),
+            _ => {}
+        }
+
+        if now.duration_since(batch.saved_at) >= SAVE_INTERVAL {
+            batch.saved_at = now;
+            let summary = batch.summary(BatchStatus::Running);
+            self.save(&summary);
+        }
+    }
+
+    fn save(&self, summary: &BatchSummary) {
+        let result = match &self.dir {
+            Some(dir) => std::fs::create_dir_all(dir)
+                .map_err(Into::into)
+                .and_then(|()| save_summary(&dir.join(format!("{}.json", file_stem(&summary.info))), summary)),
+            None => Err("geen map voor batches gevonden".into()),
+        };
+        if let Err(err) = result {
+            re_log::error!("Kan de samenvatting van batch {} niet opslaan: {}", summary.info.lot, err);
+        }
+    }
+}
+
+fn file_stem(info: &BatchInfo) -> String {
+    let lot: String = info
+        .lot
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let started = DateTime::from_timestamp_millis(info.started_at as i64).unwrap_or_default().with_timezone(&Local);
+    format!("{}-{}", started.format("%Y%m%d-%H%M%S"), lot)
+}
+
+fn create_recording(
+    dir: Option<&Path>,
+    info: &BatchInfo,
+    recipe: Option<&Recipe>,
+) -> Result<(Recorder, PathBuf), Box<dyn std::error::Error>> {
+    let dir = dir.ok_or("geen map voor batches gevonden")?;
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.rec", file_stem(info)));
+
+    let mut recorder = Recorder::create(&path)?;
+    recorder.record_batch(info)?;
+    if let Some(recipe) = recipe {
+        recorder.record_recipe(recipe)?;
+    }
+    Ok((recorder, path))
+}
+
+fn save_summary(path: &Path, summary: &BatchSummary) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::write(path, serde_json::to_string_pretty(summary)?)?;
+    Ok(())
+}
+
+// Tab om batches te starten en te beëindigen, met de tellers en eerdere batches
+pub struct BatchTab {
+    lot: String,
+    line: String,
+    operator: String,
+    notes: String,
+    status: Option<Result<String, String>>,
+    // Antwoorden van de engine op Start en Beëindig
+    started: Option<Receiver<Result<(), String>>>,
+    ended: Option<Receiver<Option<BatchSummary>>>,
+}
+
+impl BatchTab {
+    pub fn new() -> Self {
+        Self {
+            lot: String::new(),
+            line: String::new(),
+            operator: String::new(),
+            notes: String::new(),
+            status: None,
+            started: None,
+            ended: None,
+        }
+    }
+
+    fn poll(&mut self) {
+        match self.started.as_ref().map(|reply| reply.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => {}
+            Some(result) => {
+                let result = result.unwrap_or_else(|_| Err("engine gestopt".to_string()));
+                self.status = Some(result.map(|()| format!("Batch {} gestart", self.lot.trim())));
+                self.started = None;
+            }
+        }
+        match self.ended.as_ref().map(|reply| reply.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => {}
+            Some(summary) => {
+                if let Ok(Some(summary)) = summary {
+                    self.status = Some(Ok(format!(
+                        "Batch {} afgerond: {} goed, {} afgekeurd, {} detecties",
+                        summary.info.lot, summary.counts.items_passed, summary.counts.rejects, summary.counts.detections
+                    )));
+                    self.lot.clear();
+                    self.notes.clear();
+                }
+                self.ended = None;
+            }
+        }
+    }
+
+    fn start_ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        let recipe = state.engine.recipes.active().cloned();
+
+        egui::Grid::new("batch_start").num_columns(2).show(ui, |ui| {
+            ui.label("Lotnummer");
+            ui.text_edit_singleline(&mut self.lot);
+            ui.end_row();
+
+            ui.label("Lijn");
+            ui.text_edit_singleline(&mut self.line);
+            ui.end_row();
+
+            ui.label("Operator");
+            ui.text_edit_singleline(&mut self.operator);
+            ui.end_row();
+
+            ui.label("Recept");
+            ui.label(recipe.as_ref().map_or("Standaard", |recipe| recipe.name.as_str()))
+                .on_hover_text("Kies het recept in het zijpaneel");
+            ui.end_row();
+
+            ui.label("Notities");
+            ui.add(egui::TextEdit::multiline(&mut self.notes).desired_rows(3));
+            ui.end_row();
+        });
+
+        let can_start = !self.lot.trim().is_empty() && self.started.is_none();
+        if ui.add_enabled(can_start, egui::Button::new("Start batch")).clicked() {
+            let info = BatchInfo {
+                lot: self.lot.trim().to_string(),
+                recipe: None,
+                operator: self.operator.trim().to_string(),
+                notes: self.notes.trim().to_string(),
+                started_at: unix_millis(),
+                line: self.line.trim().to_string(),
+                hub: None,
+                firmware: None,
+            };
+            self.started = Some(state.live.request(|reply| Command::StartBatch { info, reply }));
+        }
+    }
+
+    fn current_ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        let Some((info, counts)) = &state.engine.batch else {
+            return;
+        };
+
+        ui.heading(format!("Batch {}", info.lot));
+        ui.label(format!(
+            "Gestart {} door {}, recept {}",
+            format_time(info.started_at),
+            if info.operator.is_empty() { "-" } else { &info.operator },
+            info.recipe.as_deref().unwrap_or("standaard")
+        ));
+        if !info.notes.is_empty() {
+            ui.weak(&info.notes);
+        }
+        counts_grid(ui, "batch_counts", counts, Duration::from_millis(unix_millis().saturating_sub(info.started_at)));
+
+        if ui.add_enabled(self.ended.is_none(), egui::Button::new("Beëindig batch")).clicked() {
+            self.ended = Some(state.live.request(|reply| Command::EndBatch { reply }));
+        }
+    }
+}
+
+fn counts_grid(ui: &mut egui::Ui, id: &str, counts: &BatchCounts, duration: Duration) {
+    egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+        for (label, value) in [
+            ("Duur", format_duration(duration)),
+            ("Producten goed", counts.items_passed.to_string()),
+            ("Afgekeurd", counts.rejects.to_string()),
+            ("Detecties", counts.detections.to_string()),
+            ("Alarmen", counts.alarms.to_string()),
+            ("Metingen", counts.measurements.to_string()),
+            ("Stilstand", format_duration(Duration::from_millis(counts.downtime_ms))),
+            ("Verbinding verloren", counts.connection_losses.to_string()),
+        ] {
+            ui.label(label);
+            ui.label(value);
+            ui.end_row();
+        }
+    });
+}
+
+impl RenderableTab for BatchTab {
+    fn title(&self) -> &str {
+        "Batches"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.poll();
+        if state.engine.batch.is_some() {
+            self.current_ui(ui, state);
+        } else {
+            self.start_ui(ui, state);
+        }
+
+        match &self.status {
+            Some(Ok(text)) => {
+                ui.label(text);
+            }
+            Some(Err(text)) => {
+                ui.colored_label(ui.visuals().error_fg_color, text);
+            }
+            None => {}
+        }
+
+        ui.separator();
+        ui.strong("Eerdere batches");
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .column(Column::auto().at_least(80.0))
+            .column(Column::auto().at_least(130.0))
+            .columns(Column::auto(), 7)
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for title in ["Lot", "Start", "Duur", "Recept", "Operator", "Goed", "Afgekeurd", "Detecties", "Stilstand", "Verbinding weg"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                let history = &state.engine.batch_history;
+                body.rows(18.0, history.len(), |mut row| {
+                    let summary = &history[row.index()];
+                    let cells = [
+                        match summary.status {
+                            BatchStatus::Interrupted => format!("{} (onderbroken)", summary.info.lot),
+                            _ => summary.info.lot.clone(),
+                        },
+                        format_time(summary.info.started_at),
+                        format_duration(summary.duration()),
+                        summary.info.recipe.clone().unwrap_or("standaard".to_string()),
+                        summary.info.operator.clone(),
+                        summary.counts.items_passed.to_string(),
+                        summary.counts.rejects.to_string(),
+                        summary.counts.detections.to_string(),
+                        format_duration(Duration::from_millis(summary.counts.downtime_ms)),
+                        summary.counts.connection_losses.to_string(),
+                    ];
+                    for cell in cells {
+                        row.col(|ui| {
+                            ui.label(cell);
+                        });
+                    }
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batches(name: &str) -> Batches {
+        let dir = std::env::temp_dir().join(format!("metalstream-batches-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        Batches {
+            dir: Some(dir),
+            ..Default::default()
+        }
+    }
+
+    fn info(lot: &str) -> BatchInfo {
+        BatchInfo {
+            lot: lot.to_string(),
+            recipe: None,
+            operator: "Jan".to_string(),
+            notes: String::new(),
+            started_at: 1_700_000_000_000,
+            line: "Lijn 1".to_string(),
+            hub: None,
+            firmware: None,
+        }
+    }
+
+    fn saved(batches: &Batches) -> Vec<BatchSummary> {
+        Batches::load_from(batches.dir.clone(), false).history.to_vec()
+    }
+
+    #[test]
+    fn start_checks_the_lot_and_allows_one_batch() {
+        let mut batches = batches("start");
+        assert!(batches.start(info("  "), None, true).is_err());
+        assert!(batches.current().is_none());
+
+        batches.start(info("L-1"), None, true).unwrap();
+        assert!(batches.start(info("L-2"), None, true).is_err());
+        assert_eq!(batches.lot(), Some("L-1"));
+
+        // De lopende batch staat meteen op schijf, met een opname
+        let saved = saved(&batches);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].status, BatchStatus::Running);
+        assert!(saved[0].recording.as_ref().is_some_and(|path| path.exists()));
+        std::fs::remove_dir_all(batches.dir.unwrap()).ok();
+    }
+
+    #[test]
+    fn update_counts_connection_losses_and_downtime() {
+        let mut batches = batches("update");
+        batches.start(info("L-1"), None, true).unwrap();
+
+        batches.update(true, true);
+        batches.update(false, true);
+        batches.update(false, true);
+        let counts = batches.current().unwrap().1;
+        assert_eq!(counts.connection_losses, 1);
+        assert_eq!(counts.downtime_ms, 0);
+
+        // Twee seconden weg geweest
+        batches.current.as_mut().unwrap().down_since = Some(Instant::now() - Duration::from_secs(2));
+        batches.update(true, true);
+        let counts = batches.current().unwrap().1.clone();
+        assert!(counts.downtime_ms >= 2000);
+
+        // Stilstand zonder verbindingsverlies
+        batches.update(true, false);
+        assert!(batches.current.as_ref().unwrap().down_since.is_some());
+        assert_eq!(batches.current().unwrap().1.connection_losses, 1);
+        std::fs::remove_dir_all(batches.dir.unwrap()).ok();
+    }
+
+    #[test]
+    fn end_saves_the_summary() {
+        let mut batches = batches("end");
+        assert!(batches.end().is_none());
+
+        batches.start(info("L-1"), None, true).unwrap();
+        batches.on_measurement();
+        batches.on_measurement();
+        let summary = batches.end().unwrap();
+        assert_eq!(summary.status, BatchStatus::Ended);
+        assert_eq!(summary.counts.measurements, 2);
+        assert!(batches.current().is_none());
+        assert_eq!(batches.history().len(), 1);
+
+        let saved = saved(&batches);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].status, BatchStatus::Ended);
+        assert_eq!(saved[0].counts.measurements, 2);
+        std::fs::remove_dir_all(batches.dir.unwrap()).ok();
+    }
+
+    #[test]
+    fn load_recovers_a_batch_that_was_still_running() {
+        let mut batches = batches("recover");
+        batches.start(info("L-1"), None, true).unwrap();
+        let dir = batches.dir.clone();
+        // Het programma stopt zonder de batch te beëindigen
+        drop(batches);
+
+        // Alleen lezen laat het bestand met rust
+        let read = Batches::load_from(dir.clone(), false);
+        assert_eq!(read.history[0].status, BatchStatus::Running);
+
+        let loaded = Batches::load_from(dir.clone(), true);
+        assert_eq!(loaded.history.len(), 1);
+        assert_eq!(loaded.history[0].status, BatchStatus::Interrupted);
+        assert!(loaded.current().is_none());
+        assert_eq!(saved(&loaded)[0].status, BatchStatus::Interrupted);
+        std::fs::remove_dir_all(dir.unwrap()).ok();
+    }
+}
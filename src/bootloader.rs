@@ -0,0 +1,518 @@
+use crate::firmware::checksum;
+use md5::{Digest, Md5};
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// Serial protocol van de ROM bootloader van de ESP32, zoals esptool het gebruikt.
+// Pakketten worden met SLIP ingepakt; een verzoek is
+// `00 <cmd> <len u16> <checksum u32> <data>` en een antwoord
+// `01 <cmd> <len u16> <value u32> <data> <status> <error> 00 00`.
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+const FLASH_BEGIN: u8 = 0x02;
+const FLASH_DATA: u8 = 0x03;
+const FLASH_END: u8 = 0x04;
+const SYNC: u8 = 0x08;
+const SPI_ATTACH: u8 = 0x0D;
+const SPI_FLASH_MD5: u8 = 0x13;
+
+// Blokgrootte waarmee de ROM bootloader flash schrijft
+pub const FLASH_BLOCK_SIZE: usize = 0x400;
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+
+const SYNC_ATTEMPTS: usize = 10;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(3);
+// Wissen en de MD5 berekenen duurt langer naarmate het image groter is
+const ERASE_TIMEOUT_PER_MB: Duration = Duration::from_secs(10);
+
+pub fn slip_encode(packet: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(packet.len() + 2);
+    encoded.push(SLIP_END);
+    for &byte in packet {
+        match byte {
+            SLIP_END => encoded.extend([SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => encoded.extend([SLIP_ESC, SLIP_ESC_ESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(SLIP_END);
+    encoded
+}
+
+// Haalt SLIP pakketten uit een bytestroom
+#[derive(Default)]
+pub struct SlipDecoder {
+    packet: Vec<u8>,
+    in_packet: bool,
+    escaped: bool,
+}
+
+impl SlipDecoder {
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match byte {
+            SLIP_END if self.in_packet && !self.packet.is_empty() => {
+                self.in_packet = false;
+                return Some(std::mem::take(&mut self.packet));
+            }
+            SLIP_END => self.in_packet = true,
+            _ if !self.in_packet => {}
+            SLIP_ESC => self.escaped = true,
+            byte => {
+                let byte = match (self.escaped, byte) {
+                    (true, SLIP_ESC_END) => SLIP_END,
+                    (true, SLIP_ESC_ESC) => SLIP_ESC,
+                    (_, byte) => byte,
+                };
+                self.escaped = false;
+                self.packet.push(byte);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum FlashError {
+    Io(io::Error),
+    // Geen antwoord op de SYNC, de hub staat niet in de bootloader
+    NoSync,
+    Timeout(u8),
+    Status { command: u8, error: u8 },
+    Verify { expected: String, actual: String },
+    Cancelled,
+}
+
+impl std::fmt::Display for FlashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlashError::Io(err) => write!(f, "seriele fout: {}", err),
+            FlashError::NoSync => write!(f, "geen contact met de bootloader"),
+            FlashError::Timeout(command) => write!(f, "geen antwoord van de bootloader op commando 0x{:02X}", command),
+            FlashError::Status { command, error } => {
+                write!(f, "bootloader weigert commando 0x{:02X} (fout 0x{:02X})", command, error)
+            }
+            FlashError::Verify { expected, actual } => {
+                write!(f, "verificatie mislukt: MD5 {} verwacht, {} gelezen", expected, actual)
+            }
+            FlashError::Cancelled => write!(f, "afgebroken"),
+        }
+    }
+}
+
+impl std::error::Error for FlashError {}
+
+impl From<io::Error> for FlashError {
+    fn from(err: io::Error) -> Self {
+        FlashError::Io(err)
+    }
+}
+
+// Verbinding met de bootloader: de seriele poort van de hub, of een simulatie
+pub trait BootloaderPort: Read + Write + Send {
+    // Herstart de chip met IO0 laag, zodat hij in de ROM bootloader start
+    fn enter_bootloader(&mut self) -> io::Result<()>;
+    // Herstart de chip normaal, zodat de nieuwe firmware start
+    fn hard_reset(&mut self) -> io::Result<()>;
+}
+
+// DTR stuurt IO0 en RTS stuurt EN aan via de auto-reset schakeling op het bord
+impl BootloaderPort for Box<dyn SerialPort> {
+    fn enter_bootloader(&mut self) -> io::Result<()> {
+        self.write_data_terminal_ready(false)?;
+        self.write_request_to_send(true)?;
+        std::thread::sleep(Duration::from_millis(100));
+        self.write_data_terminal_ready(true)?;
+        self.write_request_to_send(false)?;
+        std::thread::sleep(Duration::from_millis(50));
+        self.write_data_terminal_ready(false)?;
+        self.clear(serialport::ClearBuffer::Input)?;
+        Ok(())
+    }
+
+    fn hard_reset(&mut self) -> io::Result<()> {
+        self.write_request_to_send(true)?;
+        std::thread::sleep(Duration::from_millis(100));
+        self.write_request_to_send(false)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlashProgress {
+    Resetting,
+    Syncing,
+    Erasing,
+    Writing { written: usize, total: usize },
+    Verifying,
+    Restarting,
+    Done,
+}
+
+impl FlashProgress {
+    pub fn label(&self) -> String {
+        match self {
+            FlashProgress::Resetting => "Hub naar bootloader herstarten".to_string(),
+            FlashProgress::Syncing => "Verbinden met bootloader".to_string(),
+            FlashProgress::Erasing => "Flash wissen".to_string(),
+            FlashProgress::Writing { written, total } => format!("Schrijven {} / {} bytes", written, total),
+            FlashProgress::Verifying => "Verifieren".to_string(),
+            FlashProgress::Restarting => "Hub herstarten".to_string(),
+            FlashProgress::Done => "Klaar".to_string(),
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        match self {
+            FlashProgress::Resetting | FlashProgress::Syncing | FlashProgress::Erasing => 0.0,
+            FlashProgress::Writing { written, total } => *written as f32 / (*total).max(1) as f32,
+            FlashProgress::Verifying | FlashProgress::Restarting | FlashProgress::Done => 1.0,
+        }
+    }
+}
+
+pub struct Flasher<P: BootloaderPort> {
+    port: P,
+    decoder: SlipDecoder,
+}
+
+impl<P: BootloaderPort> Flasher<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            decoder: SlipDecoder::default(),
+        }
+    }
+
+    pub fn into_port(self) -> P {
+        self.port
+    }
+
+    fn send(&mut self, command: u8, data: &[u8], checksum: u32) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(8 + data.len());
+        packet.extend([0x00, command]);
+        packet.extend((data.len() as u16).to_le_bytes());
+        packet.extend(checksum.to_le_bytes());
+        packet.extend(data);
+        self.port.write_all(&slip_encode(&packet))?;
+        self.port.flush()
+    }
+
+    // Wacht op het antwoord op `command` en geef de data zonder statusbytes terug
+    fn receive(&mut self, command: u8, timeout: Duration) -> Result<Vec<u8>, FlashError> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0u8; 256];
+
+        while Instant::now() < deadline {
+            let size = match self.port.read(&mut buffer) {
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            for &byte in &buffer[..size] {
+                let Some(packet) = self.decoder.push(byte) else {
+                    continue;
+                };
+                // Antwoorden op eerdere commando's (zoals herhaalde SYNCs) overslaan
+                if packet.len() < 8 + 4 || packet[0] != 0x01 || packet[1] != command {
+                    continue;
+                }
+
+                let data = &packet[8..];
+                let (data, status) = data.split_at(data.len() - 4);
+                if status[0] != 0 {
+                    return Err(FlashError::Status { command, error: status[1] });
+                }
+                return Ok(data.to_vec());
+            }
+        }
+
+        Err(FlashError::Timeout(command))
+    }
+
+    fn command(&mut self, command: u8, data: &[u8], checksum: u32, timeout: Duration) -> Result<Vec<u8>, FlashError> {
+        self.send(command, data, checksum)?;
+        self.receive(command, timeout)
+    }
+
+    pub fn sync(&mut self) -> Result<(), FlashError> {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+
+        for _ in 0..SYNC_ATTEMPTS {
+            match self.command(SYNC, &data, 0, Duration::from_millis(200)) {
+                Ok(_) => return Ok(()),
+                Err(FlashError::Timeout(_)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(FlashError::NoSync)
+    }
+
+    // Flash `image` op `offset`, met voortgang via `progress`. Stopt als `progress` false teruggeeft.
+    pub fn flash(
+        &mut self,
+        image: &[u8],
+        offset: u32,
+        mut progress: impl FnMut(FlashProgress) -> bool,
+    ) -> Result<(), FlashError> {
+        let mut report = |stage| if progress(stage) { Ok(()) } else { Err(FlashError::Cancelled) };
+
+        report(FlashProgress::Resetting)?;
+        self.port.enter_bootloader()?;
+
+        report(FlashProgress::Syncing)?;
+        self.sync()?;
+        self.command(SPI_ATTACH, &[0; 8], 0, COMMAND_TIMEOUT)?;
+
+        report(FlashProgress::Erasing)?;
+        let blocks = image.len().div_ceil(FLASH_BLOCK_SIZE);
+        let erase_size = image.len().div_ceil(FLASH_SECTOR_SIZE) * FLASH_SECTOR_SIZE;
+        let erase_timeout = COMMAND_TIMEOUT + ERASE_TIMEOUT_PER_MB.mul_f64(erase_size as f64 / 1_048_576.0);
+        let mut begin = Vec::with_capacity(16);
+        for value in [erase_size as u32, blocks as u32, FLASH_BLOCK_SIZE as u32, offset] {
+            begin.extend(value.to_le_bytes());
+        }
+        self.command(FLASH_BEGIN, &begin, 0, erase_timeout)?;
+
+        for (sequence, chunk) in image.chunks(FLASH_BLOCK_SIZE).enumerate() {
+            // Het laatste blok wordt aangevuld met 0xFF, net als gewiste flash
+            let mut block = chunk.to_vec();
+            block.resize(FLASH_BLOCK_SIZE, 0xFF);
+
+            let mut data = Vec::with_capacity(16 + FLASH_BLOCK_SIZE);
+            for value in [FLASH_BLOCK_SIZE as u32, sequence as u32, 0, 0] {
+                data.extend(value.to_le_bytes());
+            }
+            data.extend(&block);
+            self.command(FLASH_DATA, &data, checksum(&block) as u32, COMMAND_TIMEOUT)?;
+
+            let written = (sequence * FLASH_BLOCK_SIZE + chunk.len()).min(image.len());
+            report(FlashProgress::Writing { written, total: image.len() })?;
+        }
+
+        report(FlashProgress::Verifying)?;
+        let mut md5 = Vec::with_capacity(16);
+        for value in [offset, image.len() as u32, 0, 0] {
+            md5.extend(value.to_le_bytes());
+        }
+        let response = self.command(SPI_FLASH_MD5, &md5, 0, erase_timeout)?;
+        let actual = String::from_utf8_lossy(&response).to_lowercase();
+        let expected = format!("{:x}", Md5::digest(image));
+        if actual != expected {
+            return Err(FlashError::Verify { expected, actual });
+        }
+
+        report(FlashProgress::Restarting)?;
+        // Blijf in de bootloader na FLASH_END; de hard reset start de nieuwe firmware
+        self.command(FLASH_END, &1u32.to_le_bytes(), 0, COMMAND_TIMEOUT)?;
+        self.port.hard_reset()?;
+
+        report(FlashProgress::Done)?;
+        Ok(())
+    }
+}
+
+// Een nagebootste ROM bootloader met flash in het geheugen, om de update zonder
+// hub te kunnen doorlopen (`desktop flash --simulate`)
+pub struct SimulatedBootloader {
+    pub flash: Vec<u8>,
+    in_bootloader: bool,
+    decoder: SlipDecoder,
+    output: VecDeque<u8>,
+    write_offset: usize,
+    pub restarts: usize,
+}
+
+impl SimulatedBootloader {
+    pub fn new(flash_size: usize) -> Self {
+        Self {
+            flash: vec![0xFF; flash_size],
+            in_bootloader: false,
+            decoder: SlipDecoder::default(),
+            output: VecDeque::new(),
+            write_offset: 0,
+            restarts: 0,
+        }
+    }
+
+    fn respond(&mut self, command: u8, value: u32, data: &[u8], error: Option<u8>) {
+        let mut packet = vec![0x01, command];
+        packet.extend(((data.len() + 4) as u16).to_le_bytes());
+        packet.extend(value.to_le_bytes());
+        packet.extend(data);
+        packet.extend([error.is_some() as u8, error.unwrap_or(0), 0, 0]);
+        self.output.extend(slip_encode(&packet));
+    }
+
+    fn handle(&mut self, packet: &[u8]) {
+        if packet.len() < 8 || packet[0] != 0x00 {
+            return;
+        }
+        let command = packet[1];
+        let expected_checksum = u32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let data = &packet[8..];
+        let word = |index: usize| {
+            data.get(index * 4..index * 4 + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                .unwrap_or_default()
+        };
+
+        match command {
+            SYNC | SPI_ATTACH | FLASH_END => self.respond(command, 0, &[], None),
+            FLASH_BEGIN => {
+                let (erase_size, offset) = (word(0), word(3));
+                if offset + erase_size > self.flash.len() {
+                    return self.respond(command, 0, &[], Some(0x05));
+                }
+                self.flash[offset..offset + erase_size].fill(0xFF);
+                self.write_offset = offset;
+                self.respond(command, 0, &[], None);
+            }
+            FLASH_DATA => {
+                let block = &data[16.min(data.len())..];
+                let end = self.write_offset + block.len();
+                if checksum(block) as u32 != expected_checksum || block.len() != word(0) || end > self.flash.len() {
+                    return self.respond(command, 0, &[], Some(0x07));
+                }
+                self.flash[self.write_offset..end].copy_from_slice(block);
+                self.write_offset = end;
+                self.respond(command, 0, &[], None);
+            }
+            SPI_FLASH_MD5 => {
+                let (offset, size) = (word(0), word(1));
+                match self.flash.get(offset..offset + size) {
+                    Some(region) => {
+                        let digest = format!("{:x}", Md5::digest(region));
+                        self.respond(command, 0, digest.as_bytes(), None);
+                    }
+                    None => self.respond(command, 0, &[], Some(0x05)),
+                }
+            }
+            _ => self.respond(command, 0, &[], Some(0x05)),
+        }
+    }
+}
+
+impl Read for SimulatedBootloader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "geen data"));
+        }
+        let size = buf.len().min(self.output.len());
+        for (target, byte) in buf.iter_mut().zip(self.output.drain(..size)) {
+            *target = byte;
+        }
+        Ok(size)
+    }
+}
+
+impl Write for SimulatedBootloader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Buiten de bootloader draait de firmware en wordt het protocol genegeerd
+        if self.in_bootloader {
+            for &byte in buf {
+                if let Some(packet) = self.decoder.push(byte) {
+                    self.handle(&packet);
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BootloaderPort for SimulatedBootloader {
+    fn enter_bootloader(&mut self) -> io::Result<()> {
+        self.in_bootloader = true;
+        self.output.clear();
+        Ok(())
+    }
+
+    fn hard_reset(&mut self) -> io::Result<()> {
+        self.in_bootloader = false;
+        self.restarts += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLASH_SIZE: usize = 0x40000;
+    const OFFSET: u32 = 0x10000;
+
+    // Een image van een paar blokken met een onvolledig laatste blok
+    fn image() -> Vec<u8> {
+        (0..FLASH_BLOCK_SIZE * 3 + 100).map(|index| (index * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn flashes_image_and_verifies_md5() {
+        let image = image();
+        let mut stages = Vec::new();
+        let mut flasher = Flasher::new(SimulatedBootloader::new(FLASH_SIZE));
+        flasher
+            .flash(&image, OFFSET, |stage| {
+                stages.push(stage);
+                true
+            })
+            .unwrap();
+
+        let bootloader = flasher.into_port();
+        let start = OFFSET as usize;
+        assert_eq!(&bootloader.flash[start..start + image.len()], &image[..]);
+        // De rest van het laatste blok en de sector blijft gewist
+        assert!(bootloader.flash[start + image.len()..start + 0x1000].iter().all(|byte| *byte == 0xFF));
+        assert_eq!(bootloader.restarts, 1);
+
+        assert_eq!(stages.first(), Some(&FlashProgress::Resetting));
+        assert!(stages.contains(&FlashProgress::Writing { written: image.len(), total: image.len() }));
+        assert!(stages.contains(&FlashProgress::Verifying));
+        assert_eq!(stages.last(), Some(&FlashProgress::Done));
+    }
+
+    #[test]
+    fn error_status_is_reported() {
+        // Het image past niet meer in de flash: de bootloader weigert FLASH_BEGIN
+        let mut flasher = Flasher::new(SimulatedBootloader::new(FLASH_SIZE));
+        let result = flasher.flash(&image(), FLASH_SIZE as u32, |_| true);
+        assert!(matches!(result, Err(FlashError::Status { command: FLASH_BEGIN, error: 0x05 })));
+        assert_eq!(flasher.into_port().restarts, 0);
+    }
+
+    #[test]
+    fn cancel_during_transfer() {
+        let image = image();
+        let mut flasher = Flasher::new(SimulatedBootloader::new(FLASH_SIZE));
+        let result = flasher.flash(&image, OFFSET, |stage| {
+            !matches!(stage, FlashProgress::Writing { written, .. } if written >= FLASH_BLOCK_SIZE * 2)
+        });
+        assert!(matches!(result, Err(FlashError::Cancelled)));
+
+        // Alleen de eerste twee blokken zijn geschreven en de hub is niet herstart
+        let bootloader = flasher.into_port();
+        let start = OFFSET as usize;
+        assert_eq!(&bootloader.flash[start..start + FLASH_BLOCK_SIZE * 2], &image[..FLASH_BLOCK_SIZE * 2]);
+        assert!(bootloader.flash[start + FLASH_BLOCK_SIZE * 2..start + image.len()].iter().all(|byte| *byte == 0xFF));
+        assert_eq!(bootloader.restarts, 0);
+    }
+
+    #[test]
+    fn slip_round_trip() {
+        let packet = [0x01, SLIP_END, 0x02, SLIP_ESC, 0x03];
+        let mut decoder = SlipDecoder::default();
+        let decoded: Vec<_> = slip_encode(&packet).into_iter().filter_map(|byte| decoder.push(byte)).collect();
+        assert_eq!(decoded, vec![packet.to_vec()]);
+    }
+}
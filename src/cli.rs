@@ -0,0 +1,450 @@
+use crate::bootloader::{BootloaderPort, FlashProgress, Flasher, SimulatedBootloader};
+use crate::export::{export, ExportFormat};
+use crate::firmware::{FirmwareImage, APP_OFFSET};
+use crate::items::{Item, ItemTracker, Verdict};
+use crate::recipes::{Recipe, RecipeBook};
+use crate::recording::{Recorder, RecordingReader};
+use crate::report::{parse_time, Report, ReportSource};
+use clap::{Parser, Subcommand};
+use metalstream::processing::{Detection, Detector, LineState};
+use metalstream::protocol::{FrameError, HubEvent, Message, ParsedMessage};
+use metalstream::transport::{self, ConnectionInfo, SerialLink};
+use serialport::{available_ports, SerialPortType};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(name = "desktop", version, about = "Metalstream desktop app; zonder subcommando start de GUI")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub api: crate::api::ApiArgs,
+
+    #[command(flatten)]
+    pub mqtt: crate::mqtt::MqttArgs,
+
+    #[command(flatten)]
+    pub modbus: crate::modbus::ModbusArgs,
+
+    #[command(flatten)]
+    pub opcua: crate::opc_ua::OpcUaArgs,
+
+    #[command(flatten)]
+    pub metrics: crate::metrics::MetricsArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Toon alle seriele poorten en markeer de Metalstream Hub
+    ListPorts,
+    /// Print binnenkomende berichten van de hub
+    Monitor {
+        /// Seriele poort, standaard de gevonden hub
+        port: Option<String>,
+    },
+    /// Neem binnenkomende berichten op in een bestand
+    Record {
+        /// Seriele poort, standaard de gevonden hub
+        port: Option<String>,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Stop na dit aantal seconden
+        #[arg(short, long)]
+        duration: Option<u64>,
+        /// Naam van het recept dat in de opname wordt vastgelegd
+        #[arg(long)]
+        recipe: Option<String>,
+    },
+    /// Speel een opname af alsof de berichten van de hub komen
+    Replay {
+        file: PathBuf,
+        /// Afspeelsnelheid; 0 speelt alles direct af
+        #[arg(short, long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Exporteer een opname naar CSV of Parquet
+    Export {
+        file: PathBuf,
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+        /// Uitvoerbestand, standaard de opname met een nieuwe extensie
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Draai de detectie over een opname en print een samenvatting
+    Analyze {
+        file: PathBuf,
+        /// Recept voor de detectie, standaard het recept uit de opname
+        #[arg(long)]
+        recipe: Option<String>,
+        /// Afwijking van de baseline waarboven metaal wordt gedetecteerd, overschrijft het recept
+        #[arg(short, long)]
+        threshold: Option<f64>,
+    },
+    /// Toon de opgeslagen recepten
+    Recipes,
+    /// Maak een rapport van een afgeronde batch of van alle batches in een periode
+    Report {
+        /// Lotnummer van de batch
+        #[arg(long, conflicts_with_all = ["from", "to"], required_unless_present = "from")]
+        batch: Option<String>,
+        /// Begin van de periode, bijv. "2025-01-31 06:00"
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// Einde van de periode
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+        /// Uitvoerbestanden; de extensie (.html of .pdf) bepaalt het formaat
+        #[arg(short, long, required = true)]
+        output: Vec<PathBuf>,
+    },
+    /// Print de Modbus registermap als Markdown
+    ModbusMap,
+    /// Controleer een firmware image en flash het naar de hub
+    Flash {
+        image: PathBuf,
+        /// Seriele poort, standaard de gevonden hub
+        port: Option<String>,
+        /// Flash adres, decimaal of hexadecimaal (0x...)
+        #[arg(long, default_value_t = APP_OFFSET, value_parser = parse_address)]
+        offset: u32,
+        /// Alleen het image controleren, niet flashen
+        #[arg(long)]
+        check: bool,
+        /// Flash naar een nagebootste bootloader in plaats van de hub
+        #[arg(long, conflicts_with = "port")]
+        simulate: bool,
+    },
+}
+
+fn parse_address(value: &str) -> Result<u32, String> {
+    let result = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+// Voert een subcommando uit en geeft de exit code terug
+pub fn run(command: Command) -> i32 {
+    let result = match command {
+        Command::ListPorts => list_ports(),
+        Command::Monitor { port } => monitor(port),
+        Command::Record {
+            port,
+            output,
+            duration,
+            recipe,
+        } => record(port, &output, duration.map(Duration::from_secs), recipe),
+        Command::Replay { file, speed } => replay(&file, speed),
+        Command::Export { file, format, output } => {
+            let output = output.unwrap_or_else(|| file.with_extension(format.extension()));
+            export(&file, &output, format).map(|rows| eprintln!("{} rijen geschreven naar {}", rows, output.display()))
+        }
+        Command::Analyze { file, recipe, threshold } => analyze(&file, recipe, threshold),
+        Command::Recipes => list_recipes(),
+        Command::Report { batch, from, to, output } => report(batch, from, to, &output),
+        Command::ModbusMap => {
+            print!("{}", crate::modbus::register_map_markdown());
+            Ok(())
+        }
+        Command::Flash {
+            image,
+            port,
+            offset,
+            check,
+            simulate,
+        } => flash(&image, port, offset, check, simulate),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        }
+    }
+}
+
+fn list_ports() -> CliResult {
+    for port in available_ports()? {
+        let description = match &port.port_type {
+            SerialPortType::UsbPort(usb) => format!(
+                "USB {:04x}:{:04x} {}",
+                usb.vid,
+                usb.pid,
+                usb.manufacturer.as_deref().unwrap_or("")
+            ),
+            SerialPortType::PciPort => "PCI".to_string(),
+            SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+            SerialPortType::Unknown => "Unknown".to_string(),
+        };
+        let marker = if transport::is_hub(&port) { " (Metalstream Hub)" } else { "" };
+        println!("{}\t{}{}", port.port_name, description, marker);
+    }
+    Ok(())
+}
+
+fn open_port(port: Option<String>) -> Result<Receiver<Result<ParsedMessage, FrameError>>, Box<dyn std::error::Error>> {
+    let port_path = port
+        .or_else(transport::find_hub_port)
+        .ok_or("geen Metalstream Hub gevonden, geef een poort op")?;
+    eprintln!("Verbinden met {}", port_path);
+
+    let (sender, receiver) = channel();
+    transport::spawn_reader(
+        port_path,
+        sender,
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(Mutex::new(None::<ConnectionInfo>)),
+        SerialLink::default(),
+    );
+    Ok(receiver)
+}
+
+fn monitor(port: Option<String>) -> CliResult {
+    for message in open_port(port)? {
+        match message {
+            Ok(message) => println!("{}", message),
+            Err(err) => eprintln!("Frame afgewezen: {}", err),
+        }
+    }
+    Ok(())
+}
+
+fn find_recipe(name: &str) -> Result<Recipe, Box<dyn std::error::Error>> {
+    RecipeBook::load().get(name).cloned().ok_or_else(|| format!("geen recept {} gevonden", name).into())
+}
+
+fn list_recipes() -> CliResult {
+    let book = RecipeBook::load();
+    for recipe in book.recipes() {
+        println!(
+            "{}\t{} x {} mm\tdrempel {}\tuitwerpen na {} ms",
+            recipe.name, recipe.width, recipe.length, recipe.detector.threshold, recipe.reject_delay_ms
+        );
+        for stage in &recipe.detector.dsp {
+            println!("\t{}", stage.label());
+        }
+    }
+    Ok(())
+}
+
+fn record(port: Option<String>, output: &Path, duration: Option<Duration>, recipe: Option<String>) -> CliResult {
+    let recipe = recipe.as_deref().map(find_recipe).transpose()?;
+    let receiver = open_port(port)?;
+    let mut recorder = Recorder::create(output)?;
+    if let Some(recipe) = &recipe {
+        recorder.record_recipe(recipe)?;
+    }
+    let started = Instant::now();
+    let mut count = 0;
+
+    loop {
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break;
+        }
+        match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(message)) => {
+                recorder.record(&message)?;
+                count += 1;
+            }
+            Ok(Err(err)) => eprintln!("Frame afgewezen: {}", err),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    eprintln!("{} berichten opgenomen in {}", count, output.display());
+    Ok(())
+}
+
+fn replay(file: &Path, speed: f64) -> CliResult {
+    let started = Instant::now();
+    for recorded in RecordingReader::open(file)? {
+        let recorded = recorded?;
+        if speed > 0.0 {
+            let due = recorded.elapsed.div_f64(speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        println!("{}", recorded.message);
+    }
+    Ok(())
+}
+
+fn analyze(file: &Path, recipe: Option<String>, threshold: Option<f64>) -> CliResult {
+    let fixed_recipe = recipe.as_deref().map(find_recipe).transpose()?;
+    let mut line = LineState::default();
+    let mut detector = Detector::default();
+    let mut recipe: Option<Recipe> = None;
+    let mut recipes_used: Vec<String> = Vec::new();
+    let mut frames: BTreeMap<String, usize> = BTreeMap::new();
+    let mut unhandled = 0;
+    let mut events: Vec<(String, HubEvent)> = Vec::new();
+    let mut invalid: BTreeMap<String, usize> = BTreeMap::new();
+    let mut detections: Vec<Detection> = Vec::new();
+    let mut tracker = ItemTracker::default();
+    let mut items: Vec<Item> = Vec::new();
+    let mut duration = Duration::ZERO;
+
+    let mut reader = RecordingReader::open(file)?;
+    while let Some(recorded) = reader.next() {
+        let recorded = recorded?;
+        duration = recorded.elapsed;
+
+        // Een opgegeven recept gaat voor het recept uit de opname
+        let current = fixed_recipe.clone().or_else(|| reader.recipe.clone());
+        if current != recipe || recipes_used.is_empty() {
+            let mut settings = current.as_ref().map(|recipe| recipe.detector.clone()).unwrap_or_default();
+            if let Some(threshold) = threshold {
+                settings.threshold = threshold;
+            }
+            detections.extend(detector.finish().map(|detection| tagged(detection, recipe.as_ref(), &mut tracker)));
+            detector.set_settings(settings);
+            recipes_used.push(current.as_ref().map_or("standaard".to_string(), |recipe| recipe.name.clone()));
+            recipe = current;
+        }
+        *frames.entry(recorded.message.command.clone()).or_default() += 1;
+        let time = recorded.elapsed.as_millis() as u64;
+        items.extend(tracker.poll(time));
+
+        match line.apply(&recorded.message) {
+            Ok(Message::Sms(measurement)) => {
+                let detection = detector.process(&recorded.message.timestamp, &measurement);
+                detections.extend(detection.map(|detection| tagged(detection, recipe.as_ref(), &mut tracker)));
+            }
+            Ok(Message::Met(metrics)) => items.extend(tracker.on_met(time, &recorded.message.timestamp, &metrics)),
+            Ok(Message::Unknown { .. }) => unhandled += 1,
+            Ok(Message::Error(event) | Message::Event(event)) => events.push((recorded.message.timestamp.clone(), event)),
+            Ok(_) => {}
+            Err(err) => *invalid.entry(err.to_string()).or_default() += 1,
+        }
+    }
+    detections.extend(detector.finish().map(|detection| tagged(detection, recipe.as_ref(), &mut tracker)));
+    items.extend(tracker.finish());
+    let rejects = items.iter().filter(|item| item.verdict == Verdict::Reject).count();
+
+    println!("Opname:      {}", file.display());
+    println!("Duur:        {:.1} s", duration.as_secs_f64());
+    println!("Recept:      {}", recipes_used.join(", "));
+    if let Some(batch) = &reader.batch {
+        println!("Batch:       {} ({}, gestart {})", batch.lot, batch.operator, crate::batch::format_time(batch.started_at));
+    }
+    for (command, count) in &frames {
+        println!("Frames {:<5} {}", command, count);
+    }
+    println!("Ongeldig:    {}", invalid.values().sum::<usize>());
+    for (reason, count) in &invalid {
+        println!("  {:<5} {}", count, reason);
+    }
+    println!("Onbekend:    {}", unhandled);
+    println!("Sensoren:    {}", line.measurements.keys().map(|id| format!("S0{}", id)).collect::<Vec<_>>().join(" "));
+    println!("Meldingen:   {}", events.len());
+    for (timestamp, event) in &events {
+        println!("  {} {:<12} {}", timestamp, event.severity.label(), event.description());
+    }
+    println!("Producten:   {} ({} goed, {} afgekeurd)", items.len(), items.len() - rejects, rejects);
+    println!("Detecties:   {}", detections.len());
+
+    for (index, detection) in detections.iter().enumerate() {
+        println!(
+            "  #{:<3} {} - {}  sensoren {:?}  piek S0{} ({:.0})  {} samples  {}{}",
+            index + 1,
+            detection.start_timestamp,
+            detection.end_timestamp,
+            detection.sensors,
+            detection.peak_sensor,
+            detection.peak_deviation,
+            detection.samples,
+            detection.recipe.as_deref().unwrap_or("standaard"),
+            detection.item.map(|id| format!("  product #{}", id)).unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
+
+fn report(batch: Option<String>, from: Option<String>, to: Option<String>, outputs: &[PathBuf]) -> CliResult {
+    let source = match (batch, from, to) {
+        (Some(lot), _, _) => ReportSource::Batch(lot),
+        (None, Some(from), Some(to)) => ReportSource::Range {
+            from: parse_time(&from)?,
+            to: parse_time(&to)?,
+        },
+        _ => return Err("geef een batch of een periode op".into()),
+    };
+
+    let report = Report::build(&crate::batch::Batches::read_history(), &source)?;
+    for note in &report.notes {
+        eprintln!("Let op: {}", note);
+    }
+    for output in outputs {
+        report.save(output)?;
+        eprintln!("{} opgeslagen in {}", report.header.title, output.display());
+    }
+    Ok(())
+}
+
+fn tagged(mut detection: Detection, recipe: Option<&Recipe>, tracker: &mut ItemTracker) -> Detection {
+    if let Some(recipe) = recipe {
+        recipe.tag(&mut detection);
+    }
+    tracker.on_detection(&mut detection);
+    detection
+}
+
+fn flash(image: &Path, port: Option<String>, offset: u32, check: bool, simulate: bool) -> CliResult {
+    let image = FirmwareImage::open(image)?;
+    println!("{}", image.summary());
+    if check {
+        return Ok(());
+    }
+
+    if simulate {
+        let mut flasher = Flasher::new(SimulatedBootloader::new(SIMULATED_FLASH_SIZE));
+        flash_with_progress(&mut flasher, &image, offset)?;
+        let simulated = flasher.into_port();
+        eprintln!("Gesimuleerde hub {} keer herstart", simulated.restarts);
+        return Ok(());
+    }
+
+    let port_path = port
+        .or_else(transport::find_hub_port)
+        .ok_or("geen Metalstream Hub gevonden, geef een poort op")?;
+    eprintln!("Flashen via {}", port_path);
+    let port = serialport::new(&port_path, transport::BAUDRATE)
+        .timeout(Duration::from_millis(100))
+        .open()?;
+    flash_with_progress(&mut Flasher::new(port), &image, offset)
+}
+
+// Grootte van de flash van de nagebootste hub
+const SIMULATED_FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+fn flash_with_progress<P: BootloaderPort>(flasher: &mut Flasher<P>, image: &FirmwareImage, offset: u32) -> CliResult {
+    let mut last = None;
+    flasher.flash(&image.data, offset, |progress| {
+        // Alleen bij een nieuwe stap of elke 10% een regel printen
+        let step = match progress {
+            FlashProgress::Writing { written, total } => written * 10 / total.max(1),
+            _ => 0,
+        };
+        let key = (std::mem::discriminant(&progress), step);
+        if last != Some(key) {
+            eprintln!("{}", progress.label());
+            last = Some(key);
+        }
+        true
+    })?;
+    Ok(())
+}
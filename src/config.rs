@@ -0,0 +1,349 @@
+use crate::live::Command;
+use crate::{GlobalState, RenderableTab};
+use metalstream::processing::unix_millis;
+use metalstream::protocol::{parameter, HubConfig, HubEvent, HubEventKind, ParsedMessage, PARAMETERS};
+use metalstream::transport::SerialLink;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Hoe lang we op een antwoord van de hub wachten
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Opdrachten aan de hub voor het lezen en schrijven van de configuratie
+const READ_COMMAND: &str = "GET_CFG";
+const WRITE_COMMAND: &str = "SET_CFG";
+// Code waarmee de hub een `SET_CFG` weigert
+const REJECTED_CODE: &str = "CFG_REJECTED";
+
+// Een configuratie die als JSON bestand bewaard kan worden
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigProfile {
+    pub name: String,
+    pub parameters: HubConfig,
+}
+
+impl ConfigProfile {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let profile: ConfigProfile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for (key, value) in &profile.parameters {
+            let spec = parameter(key).ok_or_else(|| format!("onbekende parameter {}", key))?;
+            spec.validate(*value)?;
+        }
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Parameters waarvan de waarde in `new` afwijkt van `old`
+pub fn changes(old: &HubConfig, new: &HubConfig) -> HubConfig {
+    new.iter()
+        .filter(|(key, value)| old.get(*key) != Some(value))
+        .map(|(key, value)| (key.clone(), *value))
+        .collect()
+}
+
+#[derive(Clone)]
+enum Request {
+    Read,
+    Write(HubConfig),
+}
+
+#[derive(Clone)]
+struct Pending {
+    request: Request,
+    sent: Instant,
+}
+
+// De configuratie zoals de hub die het laatst heeft gemeld, en de opdracht
+// waarop we nog een antwoord verwachten
+#[derive(Default, Clone)]
+pub struct HubConfigState {
+    pub live: Option<HubConfig>,
+    pending: Option<Pending>,
+    pub status: Option<Result<String, String>>,
+}
+
+impl HubConfigState {
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn send(&mut self, link: &SerialLink, request: Request) {
+        let (command, fields) = match &request {
+            Request::Read => (READ_COMMAND, HubConfig::new()),
+            Request::Write(changes) => (WRITE_COMMAND, changes.clone()),
+        };
+        let frame = ParsedMessage {
+            timestamp: unix_millis().to_string(),
+            command: command.to_string(),
+            fields: fields.into_iter().map(|(key, value)| (key, value.to_string())).collect(),
+        }
+        .to_frame();
+
+        match link.send(frame.as_bytes()) {
+            Ok(()) => {
+                self.pending = Some(Pending {
+                    request,
+                    sent: Instant::now(),
+                });
+                self.status = None;
+            }
+            Err(err) => self.status = Some(Err(format!("Versturen mislukt: {}", err))),
+        }
+    }
+
+    pub fn read(&mut self, link: &SerialLink) {
+        self.send(link, Request::Read);
+    }
+
+    pub fn write(&mut self, link: &SerialLink, changes: HubConfig) {
+        self.send(link, Request::Write(changes));
+    }
+
+    pub fn on_config(&mut self, config: HubConfig) {
+        if matches!(self.pending, Some(Pending { request: Request::Read, .. })) {
+            self.pending = None;
+            self.status = Some(Ok("Configuratie gelezen".to_string()));
+        }
+        self.live = Some(config);
+    }
+
+    pub fn on_ack(&mut self, command: &str, link: &SerialLink) {
+        if command != WRITE_COMMAND {
+            return;
+        }
+        if let Some(Pending { request: Request::Write(changes), .. }) = self.pending.take() {
+            re_log::info!("Hub heeft {} parameter(s) overgenomen", changes.len());
+            // Opnieuw lezen zodat we zien wat de hub werkelijk gebruikt
+            self.read(link);
+            self.status = Some(Ok(format!("{} parameter(s) geschreven en bevestigd", changes.len())));
+        }
+    }
+
+    pub fn on_event(&mut self, event: &HubEvent, link: &SerialLink) {
+        match &event.kind {
+            HubEventKind::Other(code) if code == REJECTED_CODE => {
+                if matches!(self.pending, Some(Pending { request: Request::Write(_), .. })) {
+                    self.pending = None;
+                    self.status = Some(Err(format!("Hub weigert de configuratie: {}", event.message.clone().unwrap_or_default())));
+                }
+            }
+            // Op de hub zelf aangepast: de getoonde configuratie is niet meer actueel
+            HubEventKind::ConfigChanged if !self.is_busy() => self.read(link),
+            _ => {}
+        }
+    }
+
+    pub fn check_timeout(&mut self) {
+        if self.pending.as_ref().is_some_and(|pending| pending.sent.elapsed() > RESPONSE_TIMEOUT) {
+            let command = match self.pending.take().map(|pending| pending.request) {
+                Some(Request::Write(_)) => WRITE_COMMAND,
+                _ => READ_COMMAND,
+            };
+            self.status = Some(Err(format!("Geen antwoord van de hub op {}", command)));
+        }
+    }
+}
+
+// Tab om de parameters van de hub te lezen, aan te passen en terug te schrijven
+pub struct ConfigTab {
+    // Bewerkte waarden, nog niet naar de hub geschreven
+    draft: HubConfig,
+    profile: Option<ConfigProfile>,
+    profile_name: String,
+    profile_path: String,
+    profile_status: Option<Result<String, String>>,
+}
+
+impl ConfigTab {
+    pub fn new() -> Self {
+        Self {
+            draft: HubConfig::new(),
+            profile: None,
+            profile_name: "Standaard".to_string(),
+            profile_path: "profiel.json".to_string(),
+            profile_status: None,
+        }
+    }
+
+    fn errors(&self) -> Vec<String> {
+        self.draft
+            .iter()
+            .filter_map(|(key, value)| parameter(key).and_then(|spec| spec.validate(*value).err()))
+            .collect()
+    }
+
+    fn profile_ui(&mut self, ui: &mut egui::Ui, live: Option<&HubConfig>) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Profiel");
+            ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("Naam").desired_width(120.0));
+            ui.add(egui::TextEdit::singleline(&mut self.profile_path).desired_width(180.0));
+
+            if ui.add_enabled(!self.draft.is_empty(), egui::Button::new("Opslaan")).clicked() {
+                let profile = ConfigProfile {
+                    name: self.profile_name.clone(),
+                    parameters: self.draft.clone(),
+                };
+                self.profile_status = Some(match profile.save(Path::new(&self.profile_path)) {
+                    Ok(()) => Ok(format!("Opgeslagen in {}", self.profile_path)),
+                    Err(err) => Err(format!("Opslaan mislukt: {}", err)),
+                });
+            }
+
+            if ui.button("Laden").clicked() {
+                match ConfigProfile::load(Path::new(&self.profile_path)) {
+                    Ok(profile) => {
+                        self.profile_status = Some(Ok(format!("Profiel \"{}\" geladen", profile.name)));
+                        self.profile_name = profile.name.clone();
+                        self.profile = Some(profile);
+                    }
+                    Err(err) => self.profile_status = Some(Err(format!("Laden mislukt: {}", err))),
+                }
+            }
+
+            if let Some(profile) = &self.profile {
+                if ui.button("Profiel overnemen").clicked() {
+                    self.draft.extend(profile.parameters.clone());
+                }
+                if let Some(live) = live {
+                    let differences = changes(live, &profile.parameters).len();
+                    ui.label(format!("{} verschil(len) met de hub", differences));
+                }
+            }
+
+            status_label(ui, &self.profile_status);
+        });
+    }
+}
+
+fn status_label(ui: &mut egui::Ui, status: &Option<Result<String, String>>) {
+    match status {
+        Some(Ok(text)) => {
+            ui.label(text);
+        }
+        Some(Err(text)) => {
+            ui.colored_label(ui.visuals().error_fg_color, text);
+        }
+        None => {}
+    }
+}
+
+impl RenderableTab for ConfigTab {
+    fn title(&self) -> &str {
+        "Configuratie"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        let hub_config = &state.engine.hub_config;
+        let connected = state.engine.connected;
+        let busy = hub_config.is_busy();
+
+        // Eerste keer dat de configuratie binnenkomt: begin met bewerken vanaf de hub
+        if self.draft.is_empty() {
+            if let Some(live) = &hub_config.live {
+                self.draft = live.clone();
+            }
+        }
+
+        let pending_changes = hub_config.live.as_ref().map(|live| changes(live, &self.draft)).unwrap_or_default();
+        let errors = self.errors();
+
+        ui.horizontal_wrapped(|ui| {
+            if ui.add_enabled(connected && !busy, egui::Button::new("Lees van hub")).clicked() {
+                state.live.send_command(Command::ReadHubConfig);
+            }
+
+            let can_write = connected && !busy && errors.is_empty() && !pending_changes.is_empty();
+            if ui
+                .add_enabled(can_write, egui::Button::new(format!("Schrijf naar hub ({})", pending_changes.len())))
+                .clicked()
+            {
+                state.live.send_command(Command::WriteHubConfig(pending_changes.clone()));
+            }
+
+            if ui.add_enabled(hub_config.live.is_some(), egui::Button::new("Herstel")).clicked() {
+                self.draft = hub_config.live.clone().unwrap_or_default();
+            }
+
+            if busy {
+                ui.add(egui::Spinner::new());
+            }
+            status_label(ui, &hub_config.status);
+        });
+
+        self.profile_ui(ui, hub_config.live.as_ref());
+        ui.separator();
+
+        if self.draft.is_empty() {
+            ui.label("Nog geen configuratie van de hub gelezen.");
+            return;
+        }
+
+        let live = hub_config.live.clone().unwrap_or_default();
+        let profile = self.profile.as_ref().map(|profile| profile.parameters.clone());
+        let visuals = ui.visuals().clone();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("hub_config_grid").striped(true).num_columns(5).show(ui, |ui| {
+                for title in ["Parameter", "Waarde", "Bereik", "Hub", "Profiel"] {
+                    ui.strong(title);
+                }
+                ui.end_row();
+
+                for spec in &PARAMETERS {
+                    ui.label(spec.label).on_hover_text(spec.key);
+
+                    match self.draft.get_mut(spec.key) {
+                        Some(value) => {
+                            let response = ui.add(egui::DragValue::new(value).suffix(format!(" {}", spec.unit)));
+                            if let Err(err) = spec.validate(*value) {
+                                ui.painter().rect_stroke(
+                                    response.rect.expand(1.0),
+                                    2.0,
+                                    egui::Stroke::new(1.0, visuals.error_fg_color),
+                                );
+                                response.on_hover_text(err);
+                            }
+                        }
+                        None => {
+                            ui.weak("–");
+                        }
+                    }
+
+                    ui.weak(format!("{} – {} {}", spec.min, spec.max, spec.unit));
+
+                    let live_value = live.get(spec.key);
+                    let changed = self.draft.get(spec.key) != live_value;
+                    let text = live_value.map(|value| value.to_string()).unwrap_or("–".to_string());
+                    if changed {
+                        ui.colored_label(visuals.warn_fg_color, text).on_hover_text("Wijkt af van de bewerkte waarde");
+                    } else {
+                        ui.label(text);
+                    }
+
+                    match profile.as_ref().and_then(|profile| profile.get(spec.key)) {
+                        Some(value) if Some(value) != live_value => {
+                            ui.colored_label(visuals.warn_fg_color, value.to_string()).on_hover_text("Wijkt af van de hub");
+                        }
+                        Some(value) => {
+                            ui.label(value.to_string());
+                        }
+                        None => {
+                            ui.weak("–");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        for error in errors {
+            ui.colored_label(visuals.error_fg_color, error);
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use crate::batch::{BatchCounts, BatchInfo, BatchSummary, Batches};
+use crate::config::HubConfigState;
+use crate::items::{Item, ItemTracker};
+use crate::live::{self, LiveEvent, LiveHandle, Snapshot};
+use crate::logs::{LogEntry, LogLevel};
+use crate::metrics::METRICS;
+use crate::recipes::RecipeBook;
+use crate::snapshots::{DetectionSnapshot, SnapshotWindow};
+use metalstream::processing::{self, unix_millis, Alarm, Alarms, Detection, Detector, LineState};
+use metalstream::protocol::{self, FrameError, HubEvent, LineMetrics, Measurement, Message, ParsedMessage};
+use metalstream::transport::{self, ConnectionInfo, SerialLink};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Zo lang wacht de engine op nieuwe berichten voordat de timers (alarmen,
+// producten, batches) toch worden bijgewerkt
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// Maximaal aantal berichten per ronde, zodat de GUI ook tijdens het inhalen van
+// een achterstand regelmatig een nieuwe snapshot krijgt
+const MAX_BATCH: usize = 256;
+// Zolang er geen verbinding is, zo vaak naar de hub zoeken
+const PORT_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+// Updates die op de GUI mogen wachten. Tekent de GUI niet (bijvoorbeeld
+// geminimaliseerd), dan slaat de engine daarboven updates over in plaats van het
+// geheugen te laten vollopen; de verwerking zelf loopt gewoon door.
+const UI_QUEUE: usize = 100_000;
+
+// Wat de GUI van de engine te zien krijgt. Na elke ronde maakt de engine een
+// nieuwe; de GUI tekent steeds de laatste zonder op de engine te wachten.
+#[derive(Default)]
+pub struct UiSnapshot {
+    pub connected: bool,
+    pub connection_info: Option<ConnectionInfo>,
+    pub serial_port_path: String,
+    pub running: bool,
+    pub line: LineState,
+    pub alarms: Vec<Alarm>,
+    pub has_latched_alarm: bool,
+    pub recipes: Arc<RecipeBook>,
+    pub batch: Option<(BatchInfo, BatchCounts)>,
+    pub batch_history: Arc<Vec<BatchSummary>>,
+    pub hub_config: HubConfigState,
+    // Het product waar de laatste `MET` bij hoort
+    pub current_item: Option<Item>,
+    // Updates die zijn overgeslagen omdat de GUI te ver achterliep
+    pub skipped_updates: u64,
+}
+
+// Wat er in een ronde is gebeurd, voor de weergaven die de GUI zelf bijhoudt
+// (logs, heatmaps, galerij, producten, statistiek en meldingen)
+pub enum UiUpdate {
+    Log(LogEntry),
+    Measurement {
+        // Unix tijd in milliseconden
+        time: u64,
+        timestamp: String,
+        measurement: Measurement,
+        speed: f64,
+        baseline: Option<f64>,
+    },
+    Met { time: u64, metrics: LineMetrics },
+    Detection { time: u64, snapshot: DetectionSnapshot },
+    Item(Item),
+    HubEvent { timestamp: String, event: HubEvent },
+}
+
+// De kant van de GUI: de laatste snapshot en de updates voor de eigen weergaven
+pub struct UiFeed {
+    snapshot: Arc<Mutex<Arc<UiSnapshot>>>,
+    pub updates: Receiver<UiUpdate>,
+}
+
+impl UiFeed {
+    pub fn snapshot(&self) -> Arc<UiSnapshot> {
+        self.snapshot.lock().unwrap().clone()
+    }
+}
+
+// De toestand van de verwerking; alleen de engine kan erbij
+struct EngineState {
+    is_connected: Arc<AtomicBool>,
+    connection_info: Arc<Mutex<Option<ConnectionInfo>>>,
+    serial_port_path: String,
+    // Schrijftoegang tot de hub
+    serial_link: SerialLink,
+    thread_spawned: bool,
+    line: LineState,
+
+    // Of de detectie loopt (Start/Stop)
+    running: bool,
+    detector: Detector,
+    alarms: Alarms,
+    detections: u64,
+    last_detection: Option<Detection>,
+    // Producten op de band, opgebouwd uit de `MET` berichten
+    item_tracker: ItemTracker,
+    hub_config: HubConfigState,
+    // Poort en versie van de laatst geflashte firmware
+    firmware: Option<(String, String)>,
+    // Gedeeld met de snapshots; alleen bij een wijziging wordt er gekopieerd
+    recipes: Arc<RecipeBook>,
+    batches: Batches,
+    // De laatste scans voor de heatmap van een detectie
+    snapshots: SnapshotWindow,
+    skipped_updates: u64,
+}
+
+impl EngineState {
+    fn new(serial_link: SerialLink) -> Self {
+        Self {
+            is_connected: Arc::new(AtomicBool::new(false)),
+            connection_info: Arc::new(Mutex::new(None)),
+            serial_port_path: String::new(),
+            serial_link,
+            thread_spawned: false,
+            line: LineState::default(),
+            running: true,
+            detector: Detector::default(),
+            alarms: Alarms::default(),
+            detections: 0,
+            last_detection: None,
+            item_tracker: ItemTracker::default(),
+            hub_config: HubConfigState::default(),
+            firmware: None,
+            recipes: Arc::new(RecipeBook::load()),
+            batches: Batches::load(),
+            snapshots: SnapshotWindow::default(),
+            skipped_updates: 0,
+        }
+    }
+
+    fn select_recipe(&mut self, name: Option<&str>) {
+        let settings = Arc::make_mut(&mut self.recipes).select(name);
+        self.detector.set_settings(settings);
+        if let Some(recipe) = self.recipes.active() {
+            self.batches.on_recipe(recipe);
+        }
+    }
+}
+
+// De verwerking van de hub data in een eigen thread. De engine leest de seriele
+// poort uit, draait detectie, alarmen, producten en opnames en publiceert de
+// snapshot voor de koppelingen, onafhankelijk van hoe vaak de GUI tekent; een
+// geminimaliseerd venster stopt de detectie dus niet meer. De engine is de enige
+// eigenaar van de toestand: de GUI krijgt een snapshot en updates en stuurt haar
+// acties als `live::Command`.
+pub struct Engine {
+    state: EngineState,
+    live: LiveHandle,
+    commands: Receiver<live::Command>,
+    receiver: Option<Receiver<Result<ParsedMessage, FrameError>>>,
+    last_port_scan: Option<Instant>,
+    snapshot: Arc<Mutex<Arc<UiSnapshot>>>,
+    updates: SyncSender<UiUpdate>,
+}
+
+impl Engine {
+    pub fn spawn(live: LiveHandle, commands: Receiver<live::Command>, serial_link: SerialLink) -> UiFeed {
+        let snapshot = Arc::new(Mutex::new(Arc::new(UiSnapshot::default())));
+        let (updates, receiver) = sync_channel(UI_QUEUE);
+        let feed = UiFeed {
+            snapshot: snapshot.clone(),
+            updates: receiver,
+        };
+
+        std::thread::Builder::new()
+            .name("engine".to_string())
+            .spawn(move || {
+                // Recepten en batches van schijf laden gebeurt al in de thread
+                let mut engine = Self {
+                    state: EngineState::new(serial_link),
+                    live,
+                    commands,
+                    receiver: None,
+                    last_port_scan: None,
+                    snapshot,
+                    updates,
+                };
+                loop {
+                    engine.step();
+                }
+            })
+            .expect("Kan de verwerkingsthread niet starten");
+        feed
+    }
+
+    fn step(&mut self) {
+        let messages = self.receive();
+
+        let started = Instant::now();
+        self.connect();
+        for message in messages {
+            match message {
+                Ok(message) => {
+                    METRICS.frames.with_label_values(&[&message.command]).inc();
+                    self.handle_message(message);
+                }
+                Err(err) => {
+                    METRICS.parse_errors.with_label_values(&["frame", err.reason()]).inc();
+                    re_log::warn!("Frame afgewezen: {}", err);
+                }
+            }
+        }
+        self.update();
+        METRICS.processing_time.observe(started.elapsed().as_secs_f64());
+    }
+
+    fn receive(&self) -> Vec<Result<ParsedMessage, FrameError>> {
+        let Some(receiver) = &self.receiver else {
+            std::thread::sleep(POLL_INTERVAL);
+            return Vec::new();
+        };
+
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(first) => std::iter::once(first).chain(receiver.try_iter().take(MAX_BATCH - 1)).collect(),
+            Err(RecvTimeoutError::Timeout) => Vec::new(),
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(POLL_INTERVAL);
+                Vec::new()
+            }
+        }
+    }
+
+    // Zoek de hub en start de seriele thread zodra er een poort bekend is
+    fn connect(&mut self) {
+        let state = &mut self.state;
+        if !state.thread_spawned && !state.serial_port_path.is_empty() {
+            let (sender, receiver) = channel();
+            transport::spawn_reader(
+                state.serial_port_path.clone(),
+                sender,
+                state.is_connected.clone(),
+                state.connection_info.clone(),
+                state.serial_link.clone(),
+            );
+            self.receiver = Some(receiver);
+            state.thread_spawned = true;
+        }
+
+        let scan_due = self.last_port_scan.is_none_or(|scanned| scanned.elapsed() >= PORT_SCAN_INTERVAL);
+        if !state.is_connected.load(Ordering::Relaxed) && scan_due {
+            self.last_port_scan = Some(Instant::now());
+            if let Some(port_path) = transport::find_hub_port() {
+                state.serial_port_path = port_path;
+            }
+        }
+    }
+
+    // Een update voor de GUI; loopt de GUI te ver achter, dan wordt hij overgeslagen
+    fn send(&mut self, update: UiUpdate) {
+        if let Err(TrySendError::Full(_)) = self.updates.try_send(update) {
+            if self.state.skipped_updates == 0 {
+                re_log::warn!("De GUI loopt achter; updates voor de weergave worden overgeslagen");
+            }
+            self.state.skipped_updates += 1;
+        }
+    }
+
+    fn handle_message(&mut self, log_message: ParsedMessage) {
+        let mut level = LogLevel::Info;
+        self.state.batches.on_message(&log_message);
+
+        match self.state.line.apply(&log_message) {
+            Ok(Message::Sms(measurement)) => {
+                let state = &mut self.state;
+                state.snapshots.add(&log_message.timestamp, &measurement, &state.detector);
+                state.batches.on_measurement();
+                let update = UiUpdate::Measurement {
+                    time: unix_millis(),
+                    timestamp: log_message.timestamp.clone(),
+                    measurement,
+                    speed: state.line.speed,
+                    baseline: state.detector.baseline(measurement.id),
+                };
+                self.send(update);
+
+                if self.state.running {
+                    if let Some(detection) = self.state.detector.process(&log_message.timestamp, &measurement) {
+                        self.detection(detection);
+                    }
+                }
+            },
+            Ok(Message::Met(metrics)) => {
+                re_log::debug!("MET message: {:?}", metrics);
+                self.send(UiUpdate::Met { time: unix_millis(), metrics });
+                if let Some(item) = self.state.item_tracker.on_met(unix_millis(), &log_message.timestamp, &metrics) {
+                    self.finish_item(item);
+                }
+            },
+            Ok(Message::Ack { command }) => {
+                re_log::debug!("Hub bevestigt {}", command);
+                self.state.hub_config.on_ack(&command, &self.state.serial_link);
+            },
+            Ok(Message::Config(config)) => {
+                re_log::debug!("Configuratie van de hub: {:?}", config);
+                self.state.hub_config.on_config(config);
+            },
+            Ok(Message::Error(event) | Message::Event(event)) => {
+                METRICS.hub_events.with_label_values(&[event.kind.code()]).inc();
+                re_log::warn!("Hub meldt {}", event.description());
+                level = match event.severity {
+                    protocol::EventSeverity::Info => LogLevel::Info,
+                    protocol::EventSeverity::Warning => LogLevel::Warning,
+                    protocol::EventSeverity::Error => LogLevel::Error,
+                };
+
+                self.state.hub_config.on_event(&event, &self.state.serial_link);
+                if let Some(alarm) = self.state.alarms.raise_event(&event) {
+                    self.publish_alarm(alarm);
+                }
+                self.live.publish(LiveEvent::HubEvent(event.clone()));
+                self.send(UiUpdate::HubEvent {
+                    timestamp: log_message.timestamp.clone(),
+                    event,
+                });
+            },
+            Ok(Message::Unknown { command }) => {
+                METRICS.parse_errors.with_label_values(&["message", "unknown_command"]).inc();
+                re_log::warn!("Onbekend commando {} in {}", command, log_message.to_frame());
+                level = LogLevel::Warning;
+            },
+            Err(err) => {
+                METRICS.parse_errors.with_label_values(&["message", err.reason()]).inc();
+                re_log::warn!("Ongeldig bericht {}: {}", log_message.to_frame(), err);
+                level = LogLevel::Error;
+            },
+        }
+
+        self.send(UiUpdate::Log(LogEntry::from_message(&log_message, &self.state.serial_port_path, level)));
+
+        self.live.publish(LiveEvent::Message(log_message));
+    }
+
+    fn detection(&mut self, mut detection: Detection) {
+        let state = &mut self.state;
+        if let Some(recipe) = state.recipes.active() {
+            recipe.tag(&mut detection);
+        }
+        state.batches.on_detection(&mut detection);
+        state.item_tracker.on_detection(&mut detection);
+        state.detections += 1;
+        METRICS.detections.inc();
+        if let Some(alarm) = state.alarms.raise_detection(&detection) {
+            self.publish_alarm(alarm);
+        }
+        self.live.publish(LiveEvent::Detection(detection.clone()));
+
+        let time = unix_millis();
+        let snapshot = self.state.snapshots.capture(&detection, self.state.detector.threshold(), time);
+        self.send(UiUpdate::Detection { time, snapshot });
+        self.state.last_detection = Some(detection);
+    }
+
+    // Alles wat niet door een bericht maar door de tijd of een commando gebeurt
+    fn update(&mut self) {
+        let is_connected = self.state.is_connected.load(Ordering::Relaxed);
+        if !is_connected {
+            self.state.line.reset_metrics();
+        }
+
+        self.state.hub_config.check_timeout();
+
+        let commands: Vec<_> = self.commands.try_iter().collect();
+        for command in commands {
+            self.handle_command(command, is_connected);
+        }
+
+        let alarms = self.state.alarms.update_connection(is_connected, &self.state.line);
+        for alarm in alarms {
+            self.publish_alarm(alarm);
+        }
+        if let Some(item) = self.state.item_tracker.poll(unix_millis()) {
+            self.finish_item(item);
+        }
+        self.state.batches.update(is_connected, self.state.running);
+        self.publish_snapshot(is_connected);
+    }
+
+    fn handle_command(&mut self, command: live::Command, is_connected: bool) {
+        let state = &mut self.state;
+        match command {
+            live::Command::Start => state.running = true,
+            live::Command::Stop => state.running = false,
+            live::Command::Calibrate => state.detector.calibrate(),
+            live::Command::AcknowledgeAlarms => state.alarms.acknowledge(),
+            live::Command::SelectRecipe(name) => state.select_recipe(name.as_deref()),
+            live::Command::SaveRecipe { recipe, previous, reply } => {
+                let was_active = state.recipes.active().is_some_and(|active| Some(&active.name) == previous.as_ref());
+                let name = recipe.name.clone();
+                let result = Arc::make_mut(&mut state.recipes).save(recipe, previous.as_deref());
+                // Een aangepast actief recept direct gebruiken
+                if result.is_ok() && was_active {
+                    state.select_recipe(Some(&name));
+                }
+                reply.send(result.map_err(|err| err.to_string())).ok();
+            }
+            live::Command::RemoveRecipe { name, reply } => {
+                let was_active = state.recipes.active().is_some_and(|active| active.name == name);
+                let result = Arc::make_mut(&mut state.recipes).remove(&name);
+                if result.is_ok() && was_active {
+                    state.select_recipe(None);
+                }
+                reply.send(result.map_err(|err| err.to_string())).ok();
+            }
+            live::Command::StartBatch { mut info, reply } => {
+                let recipe = state.recipes.active().cloned();
+                info.recipe = recipe.as_ref().map(|recipe| recipe.name.clone());
+                info.hub = state.connection_info.lock().unwrap().as_ref().map(|info| info.port_path.clone());
+                info.firmware = state
+                    .firmware
+                    .as_ref()
+                    .filter(|(port_path, _)| info.hub.as_ref() == Some(port_path))
+                    .map(|(_, version)| version.clone());
+                reply.send(state.batches.start(info, recipe.as_ref(), is_connected)).ok();
+            }
+            live::Command::EndBatch { reply } => {
+                reply.send(state.batches.end()).ok();
+            }
+            live::Command::ReadHubConfig => state.hub_config.read(&state.serial_link),
+            live::Command::WriteHubConfig(changes) => state.hub_config.write(&state.serial_link, changes),
+            live::Command::FirmwareFlashed { port_path, version } => state.firmware = Some((port_path, version)),
+        }
+    }
+
+    fn publish_alarm(&mut self, mut alarm: processing::Alarm) {
+        self.state.batches.on_alarm(&mut alarm);
+        METRICS.alarms.with_label_values(&[alarm.kind.name()]).inc();
+        self.live.publish(LiveEvent::Alarm(alarm));
+    }
+
+    fn finish_item(&mut self, item: Item) {
+        self.state.batches.on_item(&item);
+        METRICS.items.with_label_values(&[item.verdict.name()]).inc();
+        self.live.publish(LiveEvent::Item(item.clone()));
+        self.send(UiUpdate::Item(item));
+    }
+
+    // Deel de huidige toestand met de externe koppelingen en de GUI
+    fn publish_snapshot(&self, is_connected: bool) {
+        let state = &self.state;
+        let connection_info = state.connection_info.lock().unwrap().clone();
+        self.live.set_snapshot(Snapshot {
+            connected: is_connected,
+            port_path: connection_info.as_ref().map(|info| info.port_path.clone()),
+            running: state.running,
+            speed: state.line.speed,
+            width: state.line.dimensions.width,
+            length: state.line.dimensions.length,
+            measurements: state.line.measurements.values().copied().collect(),
+            calibrated: state.line.measurements.keys().copied().filter(|id| state.detector.is_calibrated(*id)).collect(),
+            alarms: state.alarms.active().cloned().collect(),
+            detections: state.detections,
+            last_detection: state.last_detection.clone(),
+            recipe: state.recipes.active().map(|recipe| recipe.name.clone()),
+            batch: state.batches.lot().map(str::to_string),
+        });
+
+        let snapshot = UiSnapshot {
+            connected: is_connected,
+            connection_info,
+            serial_port_path: state.serial_port_path.clone(),
+            running: state.running,
+            line: state.line.clone(),
+            alarms: state.alarms.active().cloned().collect(),
+            has_latched_alarm: state.alarms.has_latched(),
+            recipes: state.recipes.clone(),
+            batch: state.batches.current().map(|(info, counts)| (info.clone(), counts.clone())),
+            batch_history: state.batches.history(),
+            hub_config: state.hub_config.clone(),
+            current_item: state.item_tracker.current().cloned(),
+            skipped_updates: state.skipped_updates,
+        };
+        *self.snapshot.lock().unwrap() = Arc::new(snapshot);
+    }
+}
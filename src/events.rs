@@ -0,0 +1,174 @@
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use egui_extras::{Column, TableBuilder};
+use metalstream::protocol::{EventSeverity, HubEvent};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Aantal meldingen dat in de geschiedenis blijft staan
+const HISTORY_CAPACITY: usize = 1_000;
+// Hoe lang een melding in beeld blijft
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(8);
+const MAX_NOTIFICATIONS: usize = 5;
+
+pub struct EventRecord {
+    pub received: DateTime<Local>,
+    // Timestamp van de hub uit het frame
+    pub timestamp: String,
+    pub event: HubEvent,
+}
+
+// Geschiedenis van fouten en gebeurtenissen van de hub, plus de meldingen die
+// nog in beeld staan
+#[derive(Default)]
+pub struct HubEvents {
+    history: VecDeque<EventRecord>,
+    notifications: VecDeque<(Instant, HubEvent)>,
+}
+
+impl HubEvents {
+    pub fn push(&mut self, timestamp: &str, event: HubEvent) {
+        self.notifications.push_back((Instant::now(), event.clone()));
+        while self.notifications.len() > MAX_NOTIFICATIONS {
+            self.notifications.pop_front();
+        }
+
+        self.history.push_back(EventRecord {
+            received: Local::now(),
+            timestamp: timestamp.to_string(),
+            event,
+        });
+        while self.history.len() > HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    // Toon de meldingen rechtsonder in beeld
+    pub fn show_notifications(&mut self, ctx: &egui::Context) {
+        self.notifications.retain(|(shown, _)| shown.elapsed() < NOTIFICATION_DURATION);
+        if self.notifications.is_empty() {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("hub_event_notifications"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -12.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                let mut dismissed = None;
+                for (index, (_, event)) in self.notifications.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(300.0);
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(event.severity.label()).strong().color(severity_color(event.severity, ui.visuals())));
+                            ui.label(event.description());
+                            if ui.small_button(egui_material_icons::icons::ICON_CLOSE).clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                    });
+                }
+                if let Some(index) = dismissed {
+                    self.notifications.remove(index);
+                }
+            });
+
+        // Opnieuw tekenen zodat meldingen ook zonder nieuwe data verdwijnen
+        ctx.request_repaint_after(Duration::from_secs(1));
+    }
+}
+
+fn severity_color(severity: EventSeverity, visuals: &egui::Visuals) -> egui::Color32 {
+    match severity {
+        EventSeverity::Info => visuals.text_color(),
+        EventSeverity::Warning => visuals.warn_fg_color,
+        EventSeverity::Error => visuals.error_fg_color,
+    }
+}
+
+// Tab met de geschiedenis van alle fouten en gebeurtenissen van de hub
+pub struct EventsTab {
+    min_severity: EventSeverity,
+}
+
+impl EventsTab {
+    pub fn new() -> Self {
+        Self {
+            min_severity: EventSeverity::Info,
+        }
+    }
+}
+
+impl RenderableTab for EventsTab {
+    fn title(&self) -> &str {
+        "Events"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        ui.horizontal(|ui| {
+            for severity in [EventSeverity::Info, EventSeverity::Warning, EventSeverity::Error] {
+                ui.selectable_value(&mut self.min_severity, severity, severity.label());
+            }
+            ui.separator();
+            if ui.button("Wissen").clicked() {
+                state.hub_events.clear();
+            }
+        });
+        ui.separator();
+
+        let records: Vec<&EventRecord> = state
+            .hub_events
+            .history
+            .iter()
+            .rev()
+            .filter(|record| record.event.severity >= self.min_severity)
+            .collect();
+        let row_height = egui::TextStyle::Body.resolve(ui.style()).size + 6.0;
+        let visuals = ui.visuals().clone();
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .column(Column::auto().at_least(90.0))
+            .column(Column::auto().at_least(60.0))
+            .column(Column::auto().at_least(80.0))
+            .column(Column::auto().at_least(140.0))
+            .column(Column::auto().at_least(50.0))
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for title in ["Tijd", "Hub tijd", "Ernst", "Soort", "Sensor", "Bericht"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, records.len(), |mut row| {
+                    let record = records[row.index()];
+                    let event = &record.event;
+
+                    row.col(|ui| {
+                        ui.label(record.received.format("%H:%M:%S").to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(&record.timestamp);
+                    });
+                    row.col(|ui| {
+                        ui.colored_label(severity_color(event.severity, &visuals), event.severity.label());
+                    });
+                    row.col(|ui| {
+                        ui.label(event.kind.label()).on_hover_text(event.kind.code());
+                    });
+                    row.col(|ui| {
+                        ui.label(event.sensor.map(|id| format!("S0{}", id)).unwrap_or_default());
+                    });
+                    row.col(|ui| {
+                        ui.label(event.message.clone().unwrap_or_default());
+                    });
+                });
+            });
+    }
+}
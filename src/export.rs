@@ -0,0 +1,270 @@
+use crate::items::Item;
+use crate::recording::{RecordedMessage, Replay, ReplayEvent};
+use arrow::array::{ArrayRef, Float64Array, StringArray, UInt16Array, UInt64Array, UInt8Array, BooleanArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use metalstream::protocol::Message;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// Een platte rij per bericht, plus een rij met commando `ITEM` per afgerond product;
+// velden die niet bij het commando horen blijven leeg
+struct Row {
+    elapsed_ms: u64,
+    timestamp: String,
+    command: String,
+    sensor_id: Option<u8>,
+    connected: Option<bool>,
+    value: Option<u16>,
+    width: Option<f64>,
+    length: Option<f64>,
+    speed: Option<f64>,
+    // Alleen voor `ERR` en `EVT` berichten
+    event: Option<String>,
+    severity: Option<String>,
+    message: Option<String>,
+    // Het product waar een `MET` bij hoort, en het oordeel bij een `ITEM` rij
+    item_id: Option<u64>,
+    verdict: Option<String>,
+}
+
+impl From<&RecordedMessage> for Row {
+    fn from(recorded: &RecordedMessage) -> Self {
+        let fields = &recorded.message.fields;
+        let event = match recorded.message.decode() {
+            Ok(Message::Error(event) | Message::Event(event)) => Some(event),
+            _ => None,
+        };
+        Row {
+            elapsed_ms: recorded.elapsed.as_millis() as u64,
+            timestamp: recorded.message.timestamp.clone(),
+            command: recorded.message.command.clone(),
+            sensor_id: fields.get("ID").and_then(|v| v.parse().ok()),
+            connected: fields.get("C").and_then(|v| v.parse::<u8>().ok()).map(|c| c != 0),
+            value: fields.get("V").and_then(|v| v.parse().ok()),
+            width: fields.get("W").and_then(|v| v.parse().ok()),
+            length: fields.get("L").and_then(|v| v.parse().ok()),
+            speed: fields.get("S").and_then(|v| v.parse().ok()),
+            event: event.as_ref().map(|event| event.kind.code().to_string()),
+            severity: event.as_ref().map(|event| format!("{:?}", event.severity).to_lowercase()),
+            message: event.and_then(|event| event.message),
+            item_id: None,
+            verdict: None,
+        }
+    }
+}
+
+impl From<Item> for Row {
+    fn from(item: Item) -> Self {
+        Row {
+            elapsed_ms: item.ended_at,
+            timestamp: item.timestamp,
+            command: "ITEM".to_string(),
+            sensor_id: item.peak_sensor,
+            connected: None,
+            value: None,
+            width: Some(item.width),
+            length: Some(item.length),
+            speed: Some(item.speed),
+            event: None,
+            severity: None,
+            message: None,
+            item_id: Some(item.id),
+            verdict: Some(item.verdict.name().to_string()),
+        }
+    }
+}
+
+// Loopt de opname door met de detectie, zodat elk product een oordeel krijgt
+fn read_rows(recording: &Path) -> Result<Vec<Row>, Box<dyn std::error::Error>> {
+    let mut replay = Replay::open(recording)?;
+    let mut rows = Vec::new();
+
+    while let Some(event) = replay.next() {
+        match event? {
+            ReplayEvent::Message { recorded, decoded, .. } => {
+                let mut row = Row::from(&recorded);
+                if let Ok(Message::Met(_)) = decoded {
+                    row.item_id = replay.current_item().map(|item| item.id);
+                }
+                rows.push(row);
+            }
+            ReplayEvent::Item(item) => rows.push(Row::from(item)),
+            ReplayEvent::Detection { .. } => {}
+        }
+    }
+    Ok(rows)
+}
+
+pub fn export(recording: &Path, output: &Path, format: ExportFormat) -> Result<usize, Box<dyn std::error::Error>> {
+    let rows = read_rows(recording)?;
+
+    match format {
+        ExportFormat::Csv => write_csv(&rows, output)?,
+        ExportFormat::Parquet => write_parquet(&rows, output)?,
+    }
+
+    Ok(rows.len())
+}
+
+fn write_csv(rows: &[Row], output: &Path) -> std::io::Result<()> {
+    fn cell<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    // Tekst met komma's of aanhalingstekens tussen aanhalingstekens zetten
+    fn text(value: &Option<String>) -> String {
+        match value {
+            Some(value) if value.contains([',', '"', '\n']) => format!("\"{}\"", value.replace('"', "\"\"")),
+            Some(value) => value.clone(),
+            None => String::new(),
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    writeln!(writer, "elapsed_ms,timestamp,command,sensor_id,connected,value,width,length,speed,event,severity,message,item_id,verdict")?;
+
+    for row in rows {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            row.elapsed_ms,
+            row.timestamp,
+            row.command,
+            cell(&row.sensor_id),
+            cell(&row.connected.map(u8::from)),
+            cell(&row.value),
+            cell(&row.width),
+            cell(&row.length),
+            cell(&row.speed),
+            text(&row.event),
+            text(&row.severity),
+            text(&row.message),
+            cell(&row.item_id),
+            text(&row.verdict),
+        )?;
+    }
+
+    writer.flush()
+}
+
+fn write_parquet(rows: &[Row], output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("elapsed_ms", DataType::UInt64, false),
+        Field::new("timestamp", DataType::Utf8, false),
+        Field::new("command", DataType::Utf8, false),
+        Field::new("sensor_id", DataType::UInt8, true),
+        Field::new("connected", DataType::Boolean, true),
+        Field::new("value", DataType::UInt16, true),
+        Field::new("width", DataType::Float64, true),
+        Field::new("length", DataType::Float64, true),
+        Field::new("speed", DataType::Float64, true),
+        Field::new("event", DataType::Utf8, true),
+        Field::new("severity", DataType::Utf8, true),
+        Field::new("message", DataType::Utf8, true),
+        Field::new("item_id", DataType::UInt64, true),
+        Field::new("verdict", DataType::Utf8, true),
+    ]));
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.elapsed_ms))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.timestamp.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.command.as_str()))),
+        Arc::new(rows.iter().map(|r| r.sensor_id).collect::<UInt8Array>()),
+        Arc::new(rows.iter().map(|r| r.connected).collect::<BooleanArray>()),
+        Arc::new(rows.iter().map(|r| r.value).collect::<UInt16Array>()),
+        Arc::new(rows.iter().map(|r| r.width).collect::<Float64Array>()),
+        Arc::new(rows.iter().map(|r| r.length).collect::<Float64Array>()),
+        Arc::new(rows.iter().map(|r| r.speed).collect::<Float64Array>()),
+        Arc::new(rows.iter().map(|r| r.event.as_deref()).collect::<StringArray>()),
+        Arc::new(rows.iter().map(|r| r.severity.as_deref()).collect::<StringArray>()),
+        Arc::new(rows.iter().map(|r| r.message.as_deref()).collect::<StringArray>()),
+        Arc::new(rows.iter().map(|r| r.item_id).collect::<UInt64Array>()),
+        Arc::new(rows.iter().map(|r| r.verdict.as_deref()).collect::<StringArray>()),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(File::create(output)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const RECORDING: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/line.rec");
+
+    fn output(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("metalstream-export-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn exports_csv() {
+        let path = output("export.csv");
+        let rows = export(Path::new(RECORDING), &path, ExportFormat::Csv).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        // 1200 metingen, 60 `MET`, een `ERR` en drie producten
+        assert_eq!(rows, 1264);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), rows + 1);
+        assert!(lines[0].starts_with("elapsed_ms,timestamp,command,sensor_id,"));
+        assert_eq!(lines[1], "0,1001,SMS,1,1,100,,,,,,,,");
+        assert!(lines.contains(&"2000,1846,ERR,,,,,,,BROWNOUT,error,\"spanning laag, 4.1V\",,"));
+
+        let items: Vec<&str> = lines.iter().copied().filter(|line| line.split(',').nth(2) == Some("ITEM")).collect();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].ends_with(",200,300,12,,,,1,pass"));
+        assert!(items[1].ends_with(",100,150,12,,,,2,reject"));
+        assert!(items[2].ends_with(",3,pass"));
+    }
+
+    #[test]
+    fn exports_parquet() {
+        let path = output("export.parquet");
+        let rows = export(Path::new(RECORDING), &path, ExportFormat::Parquet).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), rows);
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_columns(), 14);
+
+        let commands = batch.column_by_name("command").unwrap().as_string::<i32>();
+        let verdicts = batch.column_by_name("verdict").unwrap().as_string::<i32>();
+        let items: Vec<(usize, &str)> = (0..rows)
+            .filter(|row| commands.value(*row) == "ITEM")
+            .map(|row| (row, verdicts.value(row)))
+            .collect();
+        assert_eq!(items.iter().map(|(_, verdict)| *verdict).collect::<Vec<_>>(), ["pass", "reject", "pass"]);
+
+        let widths = batch.column_by_name("width").unwrap().as_primitive::<arrow::datatypes::Float64Type>();
+        assert_eq!(widths.value(items[1].0), 100.0);
+        let values = batch.column_by_name("value").unwrap();
+        assert!(values.is_null(items[1].0));
+    }
+}
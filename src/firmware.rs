@@ -0,0 +1,467 @@
+use crate::bootloader::{FlashProgress, Flasher};
+use crate::live::Command;
+use crate::{GlobalState, RenderableTab};
+use metalstream::transport::{SerialLink, BAUDRATE};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+// Opbouw van een ESP-IDF applicatie image, zie "App Image Format" in de ESP-IDF documentatie
+const IMAGE_MAGIC: u8 = 0xE9;
+const HEADER_LEN: usize = 24;
+const SEGMENT_HEADER_LEN: usize = 8;
+const CHECKSUM_SEED: u8 = 0xEF;
+const APP_DESC_MAGIC: u32 = 0xABCD_5432;
+const MAX_SEGMENTS: u8 = 16;
+
+// Chips uit de ESP32 familie, op basis van het chip id in de extended header
+const CHIPS: [(u16, &str); 6] = [
+    (0x0000, "ESP32"),
+    (0x0002, "ESP32-S2"),
+    (0x0005, "ESP32-C3"),
+    (0x0009, "ESP32-S3"),
+    (0x000C, "ESP32-C2"),
+    (0x000D, "ESP32-C6"),
+];
+
+// De chip waarop de hub draait
+pub const HUB_CHIP_ID: u16 = 0x0000;
+// Standaard adres van de applicatie partitie
+pub const APP_OFFSET: u32 = 0x10000;
+// Hoe lang we wachten tot de lees-thread de poort heeft gesloten
+const PORT_RELEASE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Magic(u8),
+    Truncated(&'static str),
+    TooManySegments(u8),
+    Chip(u16),
+    Checksum { expected: u8, actual: u8 },
+    Hash,
+    MissingAppDescription,
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Io(err) => write!(f, "kan image niet lezen: {}", err),
+            ImageError::Magic(magic) => write!(f, "geen ESP image (magic 0x{:02X} in plaats van 0x{:02X})", magic, IMAGE_MAGIC),
+            ImageError::Truncated(part) => write!(f, "image is afgekapt in {}", part),
+            ImageError::TooManySegments(count) => write!(f, "ongeldig aantal segmenten ({})", count),
+            ImageError::Chip(id) => write!(f, "image is voor {} in plaats van {}", chip_name(*id), chip_name(HUB_CHIP_ID)),
+            ImageError::Checksum { expected, actual } => {
+                write!(f, "checksum klopt niet (0x{:02X} verwacht, 0x{:02X} berekend)", expected, actual)
+            }
+            ImageError::Hash => write!(f, "SHA-256 van het image klopt niet"),
+            ImageError::MissingAppDescription => write!(f, "image bevat geen applicatiebeschrijving met versie"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(err: std::io::Error) -> Self {
+        ImageError::Io(err)
+    }
+}
+
+pub fn chip_name(id: u16) -> String {
+    CHIPS
+        .iter()
+        .find(|(chip, _)| *chip == id)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("onbekende chip 0x{:04X}", id))
+}
+
+pub struct Segment {
+    pub load_address: u32,
+    pub length: usize,
+}
+
+// Een gecontroleerd firmware image, klaar om te flashen
+pub struct FirmwareImage {
+    pub data: Vec<u8>,
+    pub chip_id: u16,
+    pub entry_point: u32,
+    pub segments: Vec<Segment>,
+    pub hash_appended: bool,
+    pub project_name: String,
+    pub version: String,
+    pub idf_version: String,
+    pub build_date: String,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+// Een C string uit een veld met vaste lengte
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(CHECKSUM_SEED, |checksum, byte| checksum ^ byte)
+}
+
+impl FirmwareImage {
+    pub fn open(path: &Path) -> Result<Self, ImageError> {
+        Self::parse(std::fs::read(path)?)
+    }
+
+    // Controleer de header, segmenten, checksum, hash en versie-informatie
+    pub fn parse(data: Vec<u8>) -> Result<Self, ImageError> {
+        if data.len() < HEADER_LEN {
+            return Err(ImageError::Truncated("header"));
+        }
+        if data[0] != IMAGE_MAGIC {
+            return Err(ImageError::Magic(data[0]));
+        }
+
+        let segment_count = data[1];
+        if segment_count == 0 || segment_count > MAX_SEGMENTS {
+            return Err(ImageError::TooManySegments(segment_count));
+        }
+        let entry_point = u32_at(&data, 4);
+        let chip_id = u16_at(&data, 12);
+        let hash_appended = data[23] == 1;
+        if chip_id != HUB_CHIP_ID {
+            return Err(ImageError::Chip(chip_id));
+        }
+
+        let mut offset = HEADER_LEN;
+        let mut segments = Vec::new();
+        let mut calculated = CHECKSUM_SEED;
+        for _ in 0..segment_count {
+            if data.len() < offset + SEGMENT_HEADER_LEN {
+                return Err(ImageError::Truncated("segment header"));
+            }
+            let load_address = u32_at(&data, offset);
+            let length = u32_at(&data, offset + 4) as usize;
+            offset += SEGMENT_HEADER_LEN;
+
+            let segment = data.get(offset..offset + length).ok_or(ImageError::Truncated("segment"))?;
+            calculated = segment.iter().fold(calculated, |checksum, byte| checksum ^ byte);
+            segments.push(Segment { load_address, length });
+            offset += length;
+        }
+
+        // De checksum staat op de laatste byte van een blok van 16 bytes
+        let checksum_offset = offset | 0x0F;
+        let expected = *data.get(checksum_offset).ok_or(ImageError::Truncated("checksum"))?;
+        if expected != calculated {
+            return Err(ImageError::Checksum { expected, actual: calculated });
+        }
+
+        let image_end = checksum_offset + 1;
+        if hash_appended {
+            let hash = data.get(image_end..image_end + 32).ok_or(ImageError::Truncated("SHA-256"))?;
+            if Sha256::digest(&data[..image_end]).as_slice() != hash {
+                return Err(ImageError::Hash);
+            }
+        }
+
+        // `esp_app_desc_t` staat aan het begin van het eerste segment
+        let desc = HEADER_LEN + SEGMENT_HEADER_LEN;
+        if segments[0].length < 256 || u32_at(&data, desc) != APP_DESC_MAGIC {
+            return Err(ImageError::MissingAppDescription);
+        }
+        let version = c_string(&data[desc + 16..desc + 48]);
+        if version.is_empty() {
+            return Err(ImageError::MissingAppDescription);
+        }
+
+        Ok(Self {
+            chip_id,
+            entry_point,
+            segments,
+            hash_appended,
+            project_name: c_string(&data[desc + 48..desc + 80]),
+            version,
+            build_date: format!("{} {}", c_string(&data[desc + 96..desc + 112]), c_string(&data[desc + 80..desc + 96])),
+            idf_version: c_string(&data[desc + 112..desc + 144]),
+            data,
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} voor {} ({} bytes, {} segmenten, ESP-IDF {}, gebouwd {})",
+            self.project_name,
+            self.version,
+            chip_name(self.chip_id),
+            self.data.len(),
+            self.segments.len(),
+            self.idf_version,
+            self.build_date,
+        )
+    }
+}
+
+// Voortgang van een update die in een eigen thread loopt
+enum UpdateMessage {
+    Progress(FlashProgress),
+    Finished(Result<(), String>),
+}
+
+// Geef de poort vrij, flash het image en laat de lees-thread daarna weer verbinden
+fn run_update(
+    link: SerialLink,
+    port_path: String,
+    image: Arc<Vec<u8>>,
+    cancel: Arc<AtomicBool>,
+    sender: Sender<UpdateMessage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    link.suspend();
+    let deadline = Instant::now() + PORT_RELEASE_TIMEOUT;
+    while link.is_attached() {
+        if Instant::now() > deadline {
+            return Err("de seriele poort wordt niet vrijgegeven".into());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let port = serialport::new(&port_path, BAUDRATE).timeout(Duration::from_millis(100)).open()?;
+    let mut flasher = Flasher::new(port);
+    flasher.flash(&image, APP_OFFSET, |progress| {
+        sender.send(UpdateMessage::Progress(progress)).ok();
+        !cancel.load(Ordering::Relaxed)
+    })?;
+    Ok(())
+}
+
+// Tab om een nieuwe firmware op de hub te zetten
+pub struct FirmwareTab {
+    path: String,
+    image: Option<Result<Arc<FirmwareImage>, String>>,
+    updates: Option<Receiver<UpdateMessage>>,
+    progress: Option<FlashProgress>,
+    result: Option<Result<(), String>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl FirmwareTab {
+    pub fn new() -> Self {
+        Self {
+            path: "firmware.bin".to_string(),
+            image: None,
+            updates: None,
+            progress: None,
+            result: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn start(&mut self, state: &GlobalState, image: &FirmwareImage) {
+        let (sender, receiver) = channel();
+        let link = state.serial_link.clone();
+        let port_path = state.engine.serial_port_path.clone();
+        let data = Arc::new(image.data.clone());
+        self.cancel = Arc::new(AtomicBool::new(false));
+        let cancel = self.cancel.clone();
+        let live = state.live.clone();
+        let version = image.version.clone();
+
+        re_log::info!("Firmware update naar {} op {}", image.version, port_path);
+        std::thread::spawn(move || {
+            let result = run_update(link.clone(), port_path.clone(), data, cancel, sender.clone());
+            link.resume();
+            if result.is_ok() {
+                live.send_command(Command::FirmwareFlashed { port_path, version });
+            }
+            sender.send(UpdateMessage::Finished(result.map_err(|err| err.to_string()))).ok();
+        });
+
+        self.updates = Some(receiver);
+        self.progress = None;
+        self.result = None;
+    }
+
+    fn poll(&mut self) {
+        let Some(updates) = &self.updates else {
+            return;
+        };
+        for message in updates.try_iter() {
+            match message {
+                UpdateMessage::Progress(progress) => self.progress = Some(progress),
+                UpdateMessage::Finished(result) => {
+                    match &result {
+                        Ok(()) => re_log::info!("Firmware update geslaagd"),
+                        Err(err) => re_log::error!("Firmware update mislukt: {}", err),
+                    }
+                    self.result = Some(result);
+                }
+            }
+        }
+        if self.result.is_some() {
+            self.updates = None;
+        }
+    }
+}
+
+impl RenderableTab for FirmwareTab {
+    fn title(&self) -> &str {
+        "Firmware"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.poll();
+        let running = self.updates.is_some();
+
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Image");
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(250.0));
+                if ui.button("Controleer").clicked() {
+                    self.image = Some(FirmwareImage::open(Path::new(&self.path)).map(Arc::new).map_err(|err| err.to_string()));
+                    self.result = None;
+                }
+            });
+        });
+
+        let image = match &self.image {
+            Some(Ok(image)) => image.clone(),
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+                return;
+            }
+            None => {
+                ui.label("Kies een firmware image van de hub (.bin) en controleer het.");
+                return;
+            }
+        };
+
+        egui::Grid::new("firmware_image").num_columns(2).show(ui, |ui| {
+            for (label, value) in [
+                ("Project", image.project_name.clone()),
+                ("Versie", image.version.clone()),
+                ("Chip", chip_name(image.chip_id)),
+                ("ESP-IDF", image.idf_version.clone()),
+                ("Gebouwd", image.build_date.clone()),
+                ("Grootte", format!("{} bytes", image.data.len())),
+                (
+                    "Segmenten",
+                    image
+                        .segments
+                        .iter()
+                        .map(|segment| format!("0x{:08X} ({} bytes)", segment.load_address, segment.length))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                ("Entry point", format!("0x{:08X}", image.entry_point)),
+                ("Integriteit", if image.hash_appended { "checksum en SHA-256 in orde" } else { "checksum in orde" }.to_string()),
+            ] {
+                ui.label(label);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        let connected = state.engine.connected;
+        ui.horizontal(|ui| {
+            let can_flash = !running && connected && !state.engine.serial_port_path.is_empty();
+            if ui.add_enabled(can_flash, egui::Button::new("Flash naar hub")).clicked() {
+                self.start(state, &image);
+            }
+            if running && ui.button("Afbreken").clicked() {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            if !connected && !running {
+                ui.weak("Geen hub verbonden");
+            }
+        });
+
+        if let Some(progress) = self.progress {
+            ui.add(egui::ProgressBar::new(progress.fraction()).text(progress.label()).animate(running));
+        }
+
+        match &self.result {
+            Some(Ok(())) if connected => {
+                ui.label(format!("Firmware {} staat op de hub en de hub is weer verbonden.", image.version));
+            }
+            Some(Ok(())) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Firmware geschreven, wachten tot de hub weer verbindt...");
+                });
+            }
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("Update mislukt: {}", err));
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Een minimaal ESP32 image: een segment met `esp_app_desc_t`, de checksum en de SHA-256
+    fn image() -> Vec<u8> {
+        let mut segment = vec![0u8; 256];
+        segment[..4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        segment[16..21].copy_from_slice(b"1.2.3");
+        segment[48..53].copy_from_slice(b"metal");
+
+        let mut data = vec![0u8; HEADER_LEN];
+        data[0] = IMAGE_MAGIC;
+        data[1] = 1;
+        data[4..8].copy_from_slice(&0x4008_0000u32.to_le_bytes());
+        data[12..14].copy_from_slice(&HUB_CHIP_ID.to_le_bytes());
+        data[23] = 1;
+        data.extend(0x3F40_0020u32.to_le_bytes());
+        data.extend((segment.len() as u32).to_le_bytes());
+        data.extend(&segment);
+
+        data.resize(data.len() | 0x0F, 0);
+        data.push(checksum(&segment));
+        let hash = Sha256::digest(&data);
+        data.extend(hash);
+        data
+    }
+
+    #[test]
+    fn parses_valid_image() {
+        let image = FirmwareImage::parse(image()).unwrap();
+        assert_eq!(image.version, "1.2.3");
+        assert_eq!(image.project_name, "metal");
+        assert_eq!(image.segments.len(), 1);
+        assert!(image.hash_appended);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut data = image();
+        // Een byte in het segment veranderen laat de checksum niet meer kloppen
+        data[HEADER_LEN + SEGMENT_HEADER_LEN + 100] ^= 0x01;
+        assert!(matches!(FirmwareImage::parse(data), Err(ImageError::Checksum { .. })));
+    }
+
+    #[test]
+    fn rejects_bad_hash() {
+        let mut data = image();
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+        assert!(matches!(FirmwareImage::parse(data), Err(ImageError::Hash)));
+    }
+
+    #[test]
+    fn rejects_other_chip() {
+        let mut data = image();
+        data[12] = 0x09;
+        assert!(matches!(FirmwareImage::parse(data), Err(ImageError::Chip(0x09))));
+    }
+}
@@ -0,0 +1,84 @@
+use egui::{Color32, ColorImage, Rect, TextureHandle, TextureOptions};
+
+// Nearest zodat de bovenste en onderste rij bij de overgang in de ringbuffer niet
+// in elkaar overlopen; repeat zodat de UV offset rond kan lopen
+const OPTIONS: TextureOptions = TextureOptions::NEAREST_REPEAT;
+
+// Een scrollende heatmap als ringbuffer van rijen. Een nieuwe rij overschrijft de
+// oudste; alleen de gewijzigde rijen gaan naar de GPU (`set_partial`) en het
+// scrollen gebeurt met een UV offset, zodat er niets verschoven of gekopieerd wordt.
+pub struct ScrollingHeatmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+    // Rij waarin de volgende rij komt, en dus de oudste rij in beeld
+    head: usize,
+    // Aantal rijen voor `head` dat nog niet naar de texture is gestuurd
+    dirty: usize,
+    texture: Option<TextureHandle>,
+}
+
+impl ScrollingHeatmap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color32::BLACK; width * height],
+            head: 0,
+            dirty: 0,
+            texture: None,
+        }
+    }
+
+    // Voeg onderaan een rij toe; een te korte rij wordt met zwart aangevuld
+    pub fn push_row(&mut self, row: &[Color32]) {
+        let start = self.head * self.width;
+        let target = &mut self.pixels[start..start + self.width];
+        let length = row.len().min(self.width);
+        target[..length].copy_from_slice(&row[..length]);
+        target[length..].fill(Color32::BLACK);
+
+        self.head = (self.head + 1) % self.height;
+        self.dirty = (self.dirty + 1).min(self.height);
+    }
+
+    fn patch(&self, first_row: usize, rows: usize) -> ColorImage {
+        ColorImage {
+            size: [self.width, rows],
+            pixels: self.pixels[first_row * self.width..(first_row + rows) * self.width].to_vec(),
+        }
+    }
+
+    // Stuur de gewijzigde rijen naar de texture; hoogstens twee patches omdat de
+    // gewijzigde rijen over het einde van de buffer heen kunnen lopen
+    pub fn upload(&mut self, ctx: &egui::Context, name: &str) -> &TextureHandle {
+        match &mut self.texture {
+            None => {
+                let image = self.patch(0, self.height);
+                self.texture = Some(ctx.load_texture(name, image, OPTIONS));
+            }
+            Some(_) if self.dirty == self.height => {
+                let image = self.patch(0, self.height);
+                self.texture.as_mut().unwrap().set(image, OPTIONS);
+            }
+            Some(_) if self.dirty > 0 => {
+                let start = (self.head + self.height - self.dirty) % self.height;
+                let first = self.dirty.min(self.height - start);
+                let patches = [(start, first), (0, self.dirty - first)];
+                for (row, rows) in patches.into_iter().filter(|(_, rows)| *rows > 0) {
+                    let image = self.patch(row, rows);
+                    self.texture.as_mut().unwrap().set_partial([0, row], image, OPTIONS);
+                }
+            }
+            Some(_) => {}
+        }
+        self.dirty = 0;
+        self.texture.as_ref().unwrap()
+    }
+
+    // UV rechthoek met de oudste rij bovenaan en de nieuwste onderaan
+    pub fn uv(&self) -> Rect {
+        let offset = self.head as f32 / self.height as f32;
+        Rect::from_min_max(egui::pos2(0.0, offset), egui::pos2(1.0, offset + 1.0))
+    }
+}
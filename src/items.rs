@@ -0,0 +1,438 @@
+use crate::batch::format_time;
+use crate::{GlobalState, RenderableTab};
+use egui_extras::{Column, TableBuilder};
+use metalstream::processing::Detection;
+use metalstream::protocol::LineMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// Relatieve afwijking van breedte of lengte waarboven een `MET` bij een nieuw product hoort
+const ITEM_TOLERANCE: f64 = 0.05;
+// Hoe lang een product na de laatste `MET` nog detecties kan krijgen; de sensoren
+// zitten achter het meetpunt, dus een detectie kan iets later afgelopen zijn
+const DETECTION_GRACE_MS: u64 = 500;
+// Aantal producten dat in de tabel blijft staan
+const HISTORY_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    Reject,
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Verdict::Pass => "Goed",
+            Verdict::Reject => "Afgekeurd",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Pass => "pass",
+            Verdict::Reject => "reject",
+        }
+    }
+}
+
+// Een product op de band, opgebouwd uit opeenvolgende `MET` berichten
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub id: u64,
+    // Timestamp van de hub bij de eerste `MET`
+    pub timestamp: String,
+    // Tijd in milliseconden; unix tijd, of sinds het begin van de opname bij een export
+    pub started_at: u64,
+    pub ended_at: u64,
+    // Gemiddelden over de `MET` berichten van dit product
+    pub width: f64,
+    pub length: f64,
+    pub speed: f64,
+    pub samples: u32,
+    pub verdict: Verdict,
+    pub detections: u32,
+    pub peak_sensor: Option<u8>,
+    pub peak_deviation: f64,
+}
+
+impl Item {
+    fn new(id: u64, time: u64, timestamp: &str) -> Self {
+        Self {
+            id,
+            timestamp: timestamp.to_string(),
+            started_at: time,
+            ended_at: time,
+            width: 0.0,
+            length: 0.0,
+            speed: 0.0,
+            samples: 0,
+            verdict: Verdict::Pass,
+            detections: 0,
+            peak_sensor: None,
+            peak_deviation: 0.0,
+        }
+    }
+
+    fn add(&mut self, time: u64, metrics: &LineMetrics) {
+        self.samples += 1;
+        let weight = 1.0 / self.samples as f64;
+        self.width += (metrics.width - self.width) * weight;
+        self.length += (metrics.length - self.length) * weight;
+        self.speed += (metrics.speed - self.speed) * weight;
+        self.ended_at = time;
+    }
+
+    fn matches(&self, metrics: &LineMetrics) -> bool {
+        let deviation = |expected: f64, actual: f64| (actual - expected).abs() / expected.max(1.0);
+        deviation(self.width, metrics.width) <= ITEM_TOLERANCE && deviation(self.length, metrics.length) <= ITEM_TOLERANCE
+    }
+
+    fn add_detection(&mut self, detection: &Detection) {
+        self.detections += 1;
+        self.verdict = Verdict::Reject;
+        if detection.peak_deviation > self.peak_deviation {
+            self.peak_deviation = detection.peak_deviation;
+            self.peak_sensor = Some(detection.peak_sensor);
+        }
+    }
+}
+
+// Zet `MET` berichten om in losse producten. Opeenvolgende berichten met dezelfde
+// afmetingen horen bij een product; een `MET` zonder afmetingen of met andere
+// afmetingen sluit het af. Een afgesloten product wordt pas doorgegeven als er
+// geen detecties meer bij kunnen komen.
+#[derive(Default)]
+pub struct ItemTracker {
+    next_id: u64,
+    current: Option<Item>,
+    // Afgesloten product dat nog op detecties wacht, met het moment van afsluiten
+    pending: Option<(Item, u64)>,
+}
+
+impl ItemTracker {
+    pub fn current(&self) -> Option<&Item> {
+        self.current.as_ref()
+    }
+
+    // Verwerk een `MET`; geeft een product terug als dat klaar is
+    pub fn on_met(&mut self, time: u64, timestamp: &str, metrics: &LineMetrics) -> Option<Item> {
+        let mut finished = self.poll(time);
+        let present = metrics.width > 0.0 && metrics.length > 0.0;
+
+        if self.current.as_ref().is_some_and(|item| !present || !item.matches(metrics)) {
+            let closed = self.current.take().unwrap();
+            if let Some((previous, _)) = self.pending.replace((closed, time)) {
+                finished = Some(previous);
+            }
+        }
+
+        if present {
+            let item = self.current.get_or_insert_with(|| {
+                self.next_id += 1;
+                Item::new(self.next_id, time, timestamp)
+            });
+            item.add(time, metrics);
+        }
+        finished
+    }
+
+    // Koppel een detectie aan het product onder de sensoren. De sensoren zitten achter
+    // het meetpunt: zolang een afgesloten product nog in de wachttijd zit (`poll` haalt
+    // het daarna weg), hoort de detectie bij dat product en niet bij het volgende
+    pub fn on_detection(&mut self, detection: &mut Detection) {
+        let item = match (&mut self.pending, &mut self.current) {
+            (Some((item, _)), _) | (None, Some(item)) => item,
+            (None, None) => return,
+        };
+        item.add_detection(detection);
+        detection.item = Some(item.id);
+    }
+
+    // Geef een afgesloten product door zodra er geen detecties meer bij kunnen komen
+    pub fn poll(&mut self, time: u64) -> Option<Item> {
+        match &self.pending {
+            Some((_, closed_at)) if time >= closed_at + DETECTION_GRACE_MS => self.pending.take().map(|(item, _)| item),
+            _ => None,
+        }
+    }
+
+    // Sluit alles af, bijv. aan het einde van een opname
+    pub fn finish(&mut self) -> Vec<Item> {
+        self.pending.take().map(|(item, _)| item).into_iter().chain(self.current.take()).collect()
+    }
+}
+
+// De laatst afgeronde producten voor de tabel
+#[derive(Default)]
+pub struct ItemHistory {
+    items: VecDeque<Item>,
+    // Wordt opgehoogd bij elke wijziging, zodat de tab weet wanneer hij opnieuw moet sorteren
+    generation: u64,
+}
+
+impl ItemHistory {
+    pub fn push(&mut self, item: Item) {
+        if self.items.len() == HISTORY_CAPACITY {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+        self.generation += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.generation += 1;
+    }
+
+    pub fn last(&self) -> Option<&Item> {
+        self.items.back()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Id,
+    Width,
+    Length,
+    Speed,
+    Verdict,
+    Detections,
+    Peak,
+}
+
+const COLUMNS: [(&str, SortKey); 8] = [
+    ("ID", SortKey::Id),
+    ("Tijd", SortKey::Id),
+    ("Breedte", SortKey::Width),
+    ("Lengte", SortKey::Length),
+    ("Snelheid", SortKey::Speed),
+    ("Oordeel", SortKey::Verdict),
+    ("Detecties", SortKey::Detections),
+    ("Piek", SortKey::Peak),
+];
+
+// Tab met een sorteerbare tabel van de laatste producten
+pub struct ItemsTab {
+    sort: SortKey,
+    descending: bool,
+    only_rejects: bool,
+    // Gesorteerde kopie van de geschiedenis en de generatie waarvoor die geldt
+    rows: Vec<Item>,
+    generation: Option<u64>,
+}
+
+impl ItemsTab {
+    pub fn new() -> Self {
+        Self {
+            sort: SortKey::Id,
+            descending: true,
+            only_rejects: false,
+            rows: Vec::new(),
+            generation: None,
+        }
+    }
+
+    fn update_rows(&mut self, history: &ItemHistory) {
+        if self.generation == Some(history.generation) {
+            return;
+        }
+        self.rows = history
+            .items
+            .iter()
+            .filter(|item| !self.only_rejects || item.verdict == Verdict::Reject)
+            .cloned()
+            .collect();
+
+        let sort = self.sort;
+        self.rows.sort_by(|a, b| match sort {
+            SortKey::Id => a.id.cmp(&b.id),
+            SortKey::Width => a.width.total_cmp(&b.width),
+            SortKey::Length => a.length.total_cmp(&b.length),
+            SortKey::Speed => a.speed.total_cmp(&b.speed),
+            SortKey::Verdict => (a.verdict as u8).cmp(&(b.verdict as u8)),
+            SortKey::Detections => a.detections.cmp(&b.detections),
+            SortKey::Peak => a.peak_deviation.total_cmp(&b.peak_deviation),
+        });
+        if self.descending {
+            self.rows.reverse();
+        }
+        self.generation = Some(history.generation);
+    }
+}
+
+impl RenderableTab for ItemsTab {
+    fn title(&self) -> &str {
+        "Producten"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.only_rejects, "Alleen afgekeurd").changed() {
+                self.generation = None;
+            }
+            if ui.button("Wissen").clicked() {
+                state.items.clear();
+            }
+            match state.engine.current_item.as_ref() {
+                Some(item) => ui.label(format!("Onder de sensoren: #{} ({:.0} x {:.0} mm)", item.id, item.width, item.length)),
+                None => ui.weak("Geen product onder de sensoren"),
+            };
+        });
+        self.update_rows(&state.items);
+
+        let visuals = ui.visuals().clone();
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .column(Column::auto().at_least(50.0))
+            .column(Column::auto().at_least(130.0))
+            .columns(Column::auto().at_least(70.0), 5)
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for (title, key) in COLUMNS {
+                    header.col(|ui| {
+                        let arrow = match (self.sort == key && title != "Tijd", self.descending) {
+                            (true, true) => " ⏷",
+                            (true, false) => " ⏶",
+                            (false, _) => "",
+                        };
+                        if ui.add(egui::Label::new(egui::RichText::new(format!("{}{}", title, arrow)).strong()).sense(egui::Sense::click())).clicked() {
+                            self.descending = if self.sort == key { !self.descending } else { true };
+                            self.sort = key;
+                            self.generation = None;
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(18.0, self.rows.len(), |mut row| {
+                    let item = &self.rows[row.index()];
+                    let peak = item.peak_sensor.map(|sensor| format!("S0{} ({:.0})", sensor, item.peak_deviation)).unwrap_or_default();
+                    let cells = [
+                        format!("#{}", item.id),
+                        format_time(item.started_at),
+                        format!("{:.0} mm", item.width),
+                        format!("{:.0} mm", item.length),
+                        format!("{:.1} cm/s", item.speed),
+                    ];
+                    for cell in cells {
+                        row.col(|ui| {
+                            ui.label(cell);
+                        });
+                    }
+                    row.col(|ui| {
+                        match item.verdict {
+                            Verdict::Pass => ui.label(item.verdict.label()),
+                            Verdict::Reject => ui.colored_label(visuals.warn_fg_color, item.verdict.label()),
+                        };
+                    });
+                    row.col(|ui| {
+                        ui.label(item.detections.to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(peak);
+                    });
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(width: f64, length: f64) -> LineMetrics {
+        LineMetrics { width, length, speed: 0.5 }
+    }
+
+    fn detection(peak_sensor: u8, peak_deviation: f64) -> Detection {
+        Detection {
+            start_timestamp: "1000".to_string(),
+            end_timestamp: "1040".to_string(),
+            sensors: vec![peak_sensor],
+            peak_sensor,
+            peak_deviation,
+            samples: 4,
+            recipe: None,
+            reject_delay_ms: 0,
+            batch: None,
+            item: None,
+        }
+    }
+
+    #[test]
+    fn splits_items_on_other_dimensions() {
+        let mut tracker = ItemTracker::default();
+        assert!(tracker.on_met(0, "1", &metrics(200.0, 300.0)).is_none());
+        // Binnen de tolerantie: hetzelfde product
+        assert!(tracker.on_met(50, "2", &metrics(205.0, 300.0)).is_none());
+        assert_eq!(tracker.current().unwrap().samples, 2);
+
+        // Andere afmetingen: het eerste product wacht nog op detecties
+        assert!(tracker.on_met(100, "3", &metrics(100.0, 150.0)).is_none());
+        assert_eq!(tracker.current().unwrap().id, 2);
+        let first = tracker.on_met(600, "4", &metrics(100.0, 150.0)).unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(first.samples, 2);
+        assert_eq!(first.width, 202.5);
+        assert_eq!((first.started_at, first.ended_at), (0, 50));
+        assert_eq!(first.timestamp, "1");
+        assert_eq!(first.verdict, Verdict::Pass);
+
+        // Een lege `MET` sluit af; een volgend product verdringt het wachtende product
+        assert!(tracker.on_met(650, "5", &metrics(0.0, 0.0)).is_none());
+        assert!(tracker.current().is_none());
+        assert!(tracker.on_met(700, "6", &metrics(200.0, 300.0)).is_none());
+        let second = tracker.on_met(750, "7", &metrics(300.0, 300.0)).unwrap();
+        assert_eq!((second.id, second.samples), (2, 2));
+        assert!(tracker.poll(1249).is_none());
+        assert_eq!(tracker.poll(1250).unwrap().id, 3);
+    }
+
+    #[test]
+    fn detections_in_the_grace_window_go_to_the_closed_item() {
+        let mut tracker = ItemTracker::default();
+        let mut early = detection(1, 10.0);
+        tracker.on_detection(&mut early);
+        assert_eq!(early.item, None);
+
+        tracker.on_met(0, "1", &metrics(200.0, 300.0));
+        tracker.on_met(100, "2", &metrics(100.0, 150.0));
+
+        // Het eerste product ligt nog onder de sensoren
+        let mut late = detection(2, 40.0);
+        tracker.on_detection(&mut late);
+        assert_eq!(late.item, Some(1));
+        let mut later = detection(3, 20.0);
+        tracker.on_detection(&mut later);
+        assert_eq!(later.item, Some(1));
+        assert_eq!(tracker.current().unwrap().verdict, Verdict::Pass);
+
+        let first = tracker.poll(600).unwrap();
+        assert_eq!(first.verdict, Verdict::Reject);
+        assert_eq!(first.detections, 2);
+        assert_eq!((first.peak_sensor, first.peak_deviation), (Some(2), 40.0));
+
+        // Na de wachttijd hoort een detectie bij het product onder het meetpunt
+        let mut next = detection(1, 15.0);
+        tracker.on_detection(&mut next);
+        assert_eq!(next.item, Some(2));
+        assert_eq!(tracker.current().unwrap().verdict, Verdict::Reject);
+    }
+
+    #[test]
+    fn finish_returns_the_waiting_and_the_open_item() {
+        let mut tracker = ItemTracker::default();
+        assert!(tracker.finish().is_empty());
+
+        tracker.on_met(0, "1", &metrics(200.0, 300.0));
+        tracker.on_met(100, "2", &metrics(100.0, 150.0));
+        let items = tracker.finish();
+        assert_eq!(items.iter().map(|item| item.id).collect::<Vec<_>>(), [1, 2]);
+        assert!(tracker.current().is_none());
+        assert!(tracker.poll(10_000).is_none());
+        assert!(tracker.finish().is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::batch::{BatchInfo, BatchSummary};
+use crate::items::Item;
+use crate::recipes::Recipe;
+use metalstream::processing::{Alarm, Detection};
+use metalstream::protocol::{HubConfig, HubEvent, Measurement, ParsedMessage};
+use serde::Serialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+
+// Momentopname van de applicatie die met externe koppelingen wordt gedeeld
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Snapshot {
+    pub connected: bool,
+    pub port_path: Option<String>,
+    pub running: bool,
+    pub speed: f64,
+    pub width: f64,
+    pub length: f64,
+    pub measurements: Vec<Measurement>,
+    // Sensoren waarvan de baseline is opgebouwd
+    pub calibrated: Vec<u8>,
+    pub alarms: Vec<Alarm>,
+    pub detections: u64,
+    pub last_detection: Option<Detection>,
+    // Naam van het actieve recept
+    pub recipe: Option<String>,
+    // Lotnummer van de lopende batch
+    pub batch: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorHealth {
+    Ok,
+    Uncalibrated,
+    Disconnected,
+    NoData,
+}
+
+impl SensorHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensorHealth::Ok => "ok",
+            SensorHealth::Uncalibrated => "uncalibrated",
+            SensorHealth::Disconnected => "disconnected",
+            SensorHealth::NoData => "no_data",
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn sensor(&self, id: u8) -> Option<&Measurement> {
+        self.measurements.iter().find(|measurement| measurement.id == id)
+    }
+
+    pub fn is_calibrated(&self, id: u8) -> bool {
+        self.calibrated.contains(&id)
+    }
+
+    pub fn sensor_health(&self, id: u8) -> SensorHealth {
+        match self.sensor(id) {
+            _ if !self.connected => SensorHealth::NoData,
+            None => SensorHealth::NoData,
+            Some(measurement) if !measurement.connected => SensorHealth::Disconnected,
+            Some(_) if !self.is_calibrated(id) => SensorHealth::Uncalibrated,
+            Some(_) => SensorHealth::Ok,
+        }
+    }
+}
+
+// Gebeurtenissen die live naar koppelingen worden gestuurd
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Message(ParsedMessage),
+    Detection(Detection),
+    Item(Item),
+    Alarm(Alarm),
+    HubEvent(HubEvent),
+}
+
+// Opdrachten die koppelingen en de GUI aan de engine kunnen geven. Waar de GUI een
+// antwoord nodig heeft, stuurt ze een `Sender` mee.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Start,
+    Stop,
+    Calibrate,
+    AcknowledgeAlarms,
+    SelectRecipe(Option<String>),
+    // `previous` is de naam waaronder het recept eerder was opgeslagen
+    SaveRecipe {
+        recipe: Recipe,
+        previous: Option<String>,
+        reply: Sender<Result<(), String>>,
+    },
+    RemoveRecipe {
+        name: String,
+        reply: Sender<Result<(), String>>,
+    },
+    // Het recept, de poort en de firmware van de hub vult de engine zelf in
+    StartBatch {
+        info: BatchInfo,
+        reply: Sender<Result<(), String>>,
+    },
+    EndBatch {
+        reply: Sender<Option<BatchSummary>>,
+    },
+    ReadHubConfig,
+    // Alleen de gewijzigde parameters
+    WriteHubConfig(HubConfig),
+    // Een firmware update is gelukt; de hub meldt zelf geen versie
+    FirmwareFlashed {
+        port_path: String,
+        version: String,
+    },
+}
+
+// Gedeelde toegang tot de live data. Goedkoop te clonen, een kopie per koppeling.
+#[derive(Clone)]
+pub struct LiveHandle {
+    snapshot: Arc<RwLock<Snapshot>>,
+    subscribers: Arc<Mutex<Vec<Sender<LiveEvent>>>>,
+    commands: Sender<Command>,
+}
+
+pub fn channel_pair() -> (LiveHandle, Receiver<Command>) {
+    let (commands, receiver) = channel();
+    let handle = LiveHandle {
+        snapshot: Default::default(),
+        subscribers: Default::default(),
+        commands,
+    };
+    (handle, receiver)
+}
+
+impl LiveHandle {
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    pub fn set_snapshot(&self, snapshot: Snapshot) {
+        *self.snapshot.write().unwrap() = snapshot;
+    }
+
+    pub fn subscribe(&self) -> Receiver<LiveEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: LiveEvent) {
+        // Abonnees die niet meer luisteren worden opgeruimd
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    pub fn send_command(&self, command: Command) {
+        self.commands.send(command).ok();
+    }
+
+    // Een opdracht waarop een antwoord komt; de GUI kijkt elke frame of het er al is
+    pub fn request<T>(&self, command: impl FnOnce(Sender<T>) -> Command) -> Receiver<T> {
+        let (reply, receiver) = channel();
+        self.send_command(command(reply));
+        receiver
+    }
+}
@@ -0,0 +1,429 @@
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use egui_extras::{Column, TableBuilder};
+use metalstream::protocol::{ParsedMessage, NUM_SENSORS};
+use regex::Regex;
+use std::collections::{BTreeSet, VecDeque};
+
+pub const DEFAULT_LOG_CAPACITY: usize = 10_000;
+const MAX_LOG_CAPACITY: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    fn label(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+
+    fn color(&self, visuals: &egui::Visuals) -> egui::Color32 {
+        match self {
+            LogLevel::Info => visuals.text_color(),
+            LogLevel::Warning => visuals.warn_fg_color,
+            LogLevel::Error => visuals.error_fg_color,
+        }
+    }
+}
+
+// Een enkel bericht van de hub zoals het in de logs wordt getoond
+pub struct LogEntry {
+    pub seq: u64,
+    pub received: DateTime<Local>,
+    pub source: String,
+    pub timestamp: String,
+    pub command: String,
+    pub fields: Vec<(String, String)>,
+    pub level: LogLevel,
+    // Velden als tekst, eenmalig opgebouwd voor weergave en filteren
+    fields_text: String,
+}
+
+impl LogEntry {
+    pub fn from_message(message: &ParsedMessage, source: &str, level: LogLevel) -> Self {
+        let mut fields: Vec<_> = message.fields.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        fields.sort();
+        let fields_text = fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Self {
+            seq: 0,
+            received: Local::now(),
+            source: source.to_string(),
+            timestamp: message.timestamp.clone(),
+            command: message.command.clone(),
+            fields,
+            level,
+            fields_text,
+        }
+    }
+
+    pub fn sensor_id(&self) -> Option<u8> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == "ID")
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    fn time_text(&self) -> String {
+        self.received.format("%H:%M:%S%.3f").to_string()
+    }
+
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {:<5} [{}] {} {}",
+            self.time_text(),
+            self.source,
+            self.level.label(),
+            self.timestamp,
+            self.command,
+            self.fields_text
+        )
+    }
+}
+
+// Ringbuffer met de laatste `capacity` logregels. Elke regel krijgt een oplopend
+// volgnummer zodat views kunnen bijhouden wat ze al gezien hebben.
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    next_seq: u64,
+    // Verhoogd bij wissen zodat views hun cache weggooien
+    generation: u64,
+    commands: BTreeSet<String>,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_LOG_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity.min(DEFAULT_LOG_CAPACITY)),
+            capacity,
+            next_seq: 0,
+            generation: 0,
+            commands: BTreeSet::new(),
+        }
+    }
+
+    pub fn push(&mut self, mut entry: LogEntry) {
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+
+        if !self.commands.contains(&entry.command) {
+            self.commands.insert(entry.command.clone());
+        }
+
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.generation += 1;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.clamp(1, MAX_LOG_CAPACITY);
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn first_seq(&self) -> u64 {
+        self.entries.front().map(|entry| entry.seq).unwrap_or(self.next_seq)
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&LogEntry> {
+        let index = seq.checked_sub(self.first_seq())?;
+        self.entries.get(index as usize)
+    }
+
+    fn since(&self, seq: u64) -> impl Iterator<Item = &LogEntry> {
+        let skip = seq.saturating_sub(self.first_seq()) as usize;
+        self.entries.iter().skip(skip)
+    }
+}
+
+#[derive(Clone, PartialEq, Default)]
+struct Filter {
+    command: Option<String>,
+    sensor: Option<u8>,
+    text: String,
+    use_regex: bool,
+}
+
+#[derive(Default)]
+enum TextMatcher {
+    #[default]
+    None,
+    Plain(String),
+    Regex(Regex),
+    Invalid,
+}
+
+impl Filter {
+    fn matcher(&self) -> TextMatcher {
+        if self.text.is_empty() {
+            TextMatcher::None
+        } else if self.use_regex {
+            Regex::new(&self.text).map(TextMatcher::Regex).unwrap_or(TextMatcher::Invalid)
+        } else {
+            TextMatcher::Plain(self.text.to_lowercase())
+        }
+    }
+
+    fn matches(&self, matcher: &TextMatcher, entry: &LogEntry) -> bool {
+        if self.command.as_ref().is_some_and(|command| *command != entry.command) {
+            return false;
+        }
+        if self.sensor.is_some() && entry.sensor_id() != self.sensor {
+            return false;
+        }
+        match matcher {
+            TextMatcher::None => true,
+            TextMatcher::Plain(text) => entry.to_line().to_lowercase().contains(text),
+            TextMatcher::Regex(regex) => regex.is_match(&entry.to_line()),
+            TextMatcher::Invalid => false,
+        }
+    }
+}
+
+// Volgnummers van de regels die door het filter komen. Alleen nieuwe regels
+// worden gefilterd, tenzij het filter of de buffer verandert.
+#[derive(Default)]
+struct FilterCache {
+    filter: Filter,
+    matcher: TextMatcher,
+    generation: u64,
+    scanned_until: u64,
+    matched: VecDeque<u64>,
+}
+
+impl FilterCache {
+    fn update(&mut self, filter: &Filter, buffer: &LogBuffer) {
+        if *filter != self.filter || buffer.generation != self.generation {
+            self.filter = filter.clone();
+            self.matcher = filter.matcher();
+            self.generation = buffer.generation;
+            self.scanned_until = 0;
+            self.matched.clear();
+        }
+
+        for entry in buffer.since(self.scanned_until) {
+            if filter.matches(&self.matcher, entry) {
+                self.matched.push_back(entry.seq);
+            }
+        }
+        self.scanned_until = buffer.next_seq;
+
+        // Regels die uit de ringbuffer zijn gevallen
+        let first_seq = buffer.first_seq();
+        while self.matched.front().is_some_and(|seq| *seq < first_seq) {
+            self.matched.pop_front();
+        }
+    }
+}
+
+pub struct LogsTab {
+    filter: Filter,
+    cache: FilterCache,
+    paused: bool,
+    selection: BTreeSet<u64>,
+    save_path: String,
+    status: Option<String>,
+}
+
+impl LogsTab {
+    pub fn new() -> Self {
+        Self {
+            filter: Filter::default(),
+            cache: FilterCache::default(),
+            paused: false,
+            selection: BTreeSet::new(),
+            save_path: "logs.txt".to_string(),
+            status: None,
+        }
+    }
+
+    fn selected_lines(&self, buffer: &LogBuffer) -> String {
+        self.selection
+            .iter()
+            .filter_map(|seq| buffer.get(*seq))
+            .map(|entry| entry.to_line())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn toolbar_ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        ui.horizontal_wrapped(|ui| {
+            egui::ComboBox::from_id_salt("log_command_filter")
+                .selected_text(self.filter.command.as_deref().unwrap_or("Alle commando's"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.command, None, "Alle commando's");
+                    for command in &state.logs.commands {
+                        ui.selectable_value(&mut self.filter.command, Some(command.clone()), command);
+                    }
+                });
+
+            egui::ComboBox::from_id_salt("log_sensor_filter")
+                .selected_text(self.filter.sensor.map(|id| format!("Sensor S0{}", id)).unwrap_or("Alle sensoren".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.sensor, None, "Alle sensoren");
+                    for id in 1..=NUM_SENSORS as u8 {
+                        ui.selectable_value(&mut self.filter.sensor, Some(id), format!("Sensor S0{}", id));
+                    }
+                });
+
+            let invalid = matches!(self.cache.matcher, TextMatcher::Invalid);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter.text)
+                    .hint_text("Zoeken")
+                    .desired_width(150.0)
+                    .text_color_opt(invalid.then_some(ui.visuals().error_fg_color)),
+            );
+            ui.checkbox(&mut self.filter.use_regex, "Regex");
+
+            ui.separator();
+
+            ui.toggle_value(&mut self.paused, "Pauzeer scrollen");
+
+            let mut capacity = state.logs.capacity();
+            if ui
+                .add(egui::DragValue::new(&mut capacity).range(100..=MAX_LOG_CAPACITY).speed(100).prefix("Max: "))
+                .changed()
+            {
+                state.logs.set_capacity(capacity);
+            }
+
+            if ui.button("Wissen").clicked() {
+                state.logs.clear();
+                self.selection.clear();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} / {} regels", self.cache.matched.len(), state.logs.len()));
+
+            ui.add_enabled_ui(!self.selection.is_empty(), |ui| {
+                if ui.button(format!("Kopieer selectie ({})", self.selection.len())).clicked() {
+                    ui.ctx().copy_text(self.selected_lines(&state.logs));
+                }
+
+                ui.add(egui::TextEdit::singleline(&mut self.save_path).desired_width(150.0));
+                if ui.button("Bewaar selectie").clicked() {
+                    self.status = Some(match std::fs::write(&self.save_path, self.selected_lines(&state.logs) + "\n") {
+                        Ok(()) => format!("Opgeslagen in {}", self.save_path),
+                        Err(err) => format!("Opslaan mislukt: {}", err),
+                    });
+                }
+            });
+
+            if let Some(status) = &self.status {
+                ui.label(status);
+            }
+        });
+    }
+}
+
+impl RenderableTab for LogsTab {
+    fn title(&self) -> &str {
+        "Logs"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.toolbar_ui(ui, state);
+        ui.separator();
+
+        self.cache.update(&self.filter, &state.logs);
+        self.selection.retain(|seq| state.logs.get(*seq).is_some());
+
+        let row_height = egui::TextStyle::Monospace.resolve(ui.style()).size + 4.0;
+        let ctrl = ui.input(|input| input.modifiers.command);
+        let visuals = ui.visuals().clone();
+
+        // Alleen de zichtbare rijen worden opgebouwd, dus ook 100k regels blijven vlot
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .stick_to_bottom(!self.paused)
+            .sense(egui::Sense::click())
+            .column(Column::auto().at_least(90.0))
+            .column(Column::auto().at_least(60.0))
+            .column(Column::auto().at_least(50.0))
+            .column(Column::auto().at_least(60.0))
+            .column(Column::auto().at_least(50.0))
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for title in ["Tijd", "Bron", "Niveau", "Hub tijd", "Commando", "Velden"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, self.cache.matched.len(), |mut row| {
+                    let seq = self.cache.matched[row.index()];
+                    let Some(entry) = state.logs.get(seq) else {
+                        return;
+                    };
+
+                    row.set_selected(self.selection.contains(&seq));
+                    let color = entry.level.color(&visuals);
+                    let text = |text: String| egui::RichText::new(text).monospace().color(color);
+
+                    row.col(|ui| {
+                        ui.label(text(entry.time_text()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(entry.source.clone()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(entry.level.label().to_string()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(entry.timestamp.clone()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(entry.command.clone()));
+                    });
+                    row.col(|ui| {
+                        ui.label(text(entry.fields_text.clone()));
+                    });
+
+                    if row.response().clicked() {
+                        if !ctrl {
+                            self.selection.clear();
+                            self.selection.insert(seq);
+                        } else if !self.selection.remove(&seq) {
+                            self.selection.insert(seq);
+                        }
+                    }
+                });
+            });
+    }
+}
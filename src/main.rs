@@ -1,36 +1,64 @@
+mod api;
+mod app_log;
+mod batch;
+mod bootloader;
+mod cli;
+mod config;
+mod engine;
+mod events;
+mod export;
+mod firmware;
+mod heatmap;
+mod items;
+mod live;
+mod logs;
+mod metrics;
+mod modbus;
+mod mqtt;
+mod opc_ua;
+mod pdf;
+mod png;
+mod recipes;
+mod recording;
+mod report;
+mod serial_monitor;
+mod snapshots;
+mod statistics;
+mod tabs;
+mod visualization;
+
+use clap::Parser;
 use eframe::{egui, CreationContext};
-use egui::accesskit::Point;
-use egui::Id;
+use batch::BatchTab;
+use config::ConfigTab;
+use engine::{Engine, UiFeed, UiSnapshot, UiUpdate};
+use events::{EventsTab, HubEvents};
+use items::{ItemHistory, ItemsTab};
+use live::LiveHandle;
+use logs::{LogBuffer, LogsTab};
+use metalstream::protocol::NUM_SENSORS;
+use metalstream::transport::SerialLink;
+use metrics::METRICS;
+use recipes::RecipesTab;
 use re_ui::UiExt;
-use serialport::{available_ports, SerialPortType};
-use std::collections::{BTreeMap, HashMap, VecDeque};
-use std::sync::atomic::{AtomicBool, Ordering};
+use serial_monitor::{SerialMonitor, SerialMonitorTab};
+use snapshots::{Gallery, GalleryTab};
+use statistics::{Statistics, StatisticsTab};
+use tabs::{Pane, TabRegistry, Workspace, PRESETS};
+use visualization::{SensorHistory, VisualizationTab};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::sync::mpsc::{Receiver, channel};
 
 const TARGET_FRAME_RATE: usize = 60;
-// Wordt gebruikt voor het scannen naar de Metalshare Hub
-const KNOWN_MANUFACTURER: &str = "Espressif";
-// Aantal sensoren die worden gebruikt
-const NUM_SENSORS: usize = 8;
-
-#[derive(Clone)]
-struct ConnectionInfo {
-    port_path: String,
-    baudrate: u32,
-}
 
-impl ConnectionInfo {
-    fn new(port_path: String, baudrate: u32) -> Self {
-        ConnectionInfo {
-            port_path,
-            baudrate,
-        }
+fn main() -> eframe::Result {
+    // Met een subcommando draait de applicatie zonder GUI
+    let cli = cli::Cli::parse();
+    app_log::init();
+
+    if let Some(command) = cli.command {
+        std::process::exit(cli::run(command));
     }
-}
 
-fn main() -> eframe::Result {
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_app_id("metalstream")
@@ -50,266 +78,166 @@ fn main() -> eframe::Result {
         native_options,
         Box::new(move |cc| {
             re_ui::apply_style_and_install_loaders(&cc.egui_ctx);
-            Ok(Box::new(MyApp::new(cc)))
+            Ok(Box::new(MyApp::new(cc, &cli)))
         }),
     )
 }
 
-#[derive(Debug, Clone)]
-struct ParsedMessage {
-    timestamp: String,
-    command: String,
-    fields: HashMap<String, String>,
+// Wat de tabs te zien krijgen: de laatste snapshot van de engine, de weergaven
+// die de GUI zelf bijhoudt uit de updates van de engine, en de weg terug naar de
+// engine via commando's
+pub struct GlobalState {
+    engine: Arc<UiSnapshot>,
+    live: LiveHandle,
+    // Voor de Serial Monitor en de firmware update, die zelf naar de hub schrijven
+    serial_link: SerialLink,
+    logs: LogBuffer,
+    serial_monitor: SerialMonitor,
+    // Heatmaps van de laatste detecties
+    gallery: Gallery,
+    items: ItemHistory,
+    statistics: Statistics,
+    hub_events: HubEvents,
+    // Scans van de sessie voor de heatmaps
+    sensor_history: SensorHistory,
 }
 
-// Formatter voor logs
-impl std::fmt::Display for ParsedMessage {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "[{}] {} {}",
-            self.timestamp,
-            self.command,
-            self.fields
-                .iter()
-                .map(|(key, value)| format!("{}={}", key, value))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )
+impl GlobalState {
+    fn new(live: LiveHandle, serial_link: SerialLink) -> Self {
+        let serial_monitor = SerialMonitor::new(serial_link.tap());
+
+        Self {
+            engine: Arc::default(),
+            live,
+            serial_link,
+            logs: LogBuffer::default(),
+            serial_monitor,
+            gallery: Gallery::default(),
+            items: ItemHistory::default(),
+            statistics: Statistics::live(),
+            hub_events: HubEvents::default(),
+            sensor_history: SensorHistory::new(),
+        }
     }
-}
 
-#[derive(Default, Clone, Copy)]
-pub struct Measurement {
-    id: u8,
-    connected: bool,
-    value: u16,
-}
+    fn apply(&mut self, update: UiUpdate) {
+        match update {
+            UiUpdate::Log(entry) => self.logs.push(entry),
+            UiUpdate::Measurement { time, timestamp, measurement, speed, baseline } => {
+                self.sensor_history.add_sensor_value(time, &timestamp, measurement, speed, baseline);
+                self.statistics.on_measurement(time, &measurement);
+            }
+            UiUpdate::Met { time, metrics } => self.statistics.on_met(time, &metrics),
+            UiUpdate::Detection { time, snapshot } => {
+                self.statistics.on_detection(time);
+                self.gallery.push(snapshot);
+            }
+            UiUpdate::Item(item) => {
+                self.statistics.on_item(&item);
+                self.items.push(item);
+            }
+            UiUpdate::HubEvent { timestamp, event } => self.hub_events.push(&timestamp, event),
+        }
+    }
 
-// Globale applicatie state
-pub struct GlobalState {
-    is_connected: Arc<AtomicBool>,
-    connection_info: Arc<Mutex<Option<ConnectionInfo>>>,
-    logs: VecDeque<String>,
-    serial_port_path: String,
-    
-    connection_states: [bool; 8],
-    thread_spawned: bool,
-    dimensions: Point,
-    speed: f64,
-    measurements: BTreeMap<u8, Measurement>,
+    // Keuze van het recept, met een voorstel op basis van de gemeten afmetingen
+    fn recipe_ui(&mut self, ui: &mut egui::Ui) {
+        let recipes = &self.engine.recipes;
+        let active = recipes.active().map(|recipe| recipe.name.clone());
+        let mut selected = active.clone();
+
+        ui.horizontal(|ui| {
+            ui.label("Recept");
+            egui::ComboBox::from_id_salt("recipe")
+                .selected_text(selected.as_deref().unwrap_or("Standaard"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "Standaard");
+                    for recipe in recipes.recipes() {
+                        ui.selectable_value(&mut selected, Some(recipe.name.clone()), &recipe.name);
+                    }
+                });
+        });
 
-    show_side_panel: bool,
-}
+        let dimensions = self.engine.line.dimensions;
+        let suggestion = recipes
+            .suggest(dimensions.width, dimensions.length)
+            .map(|recipe| recipe.name.clone())
+            .filter(|name| Some(name) != active.as_ref());
+        if let Some(name) = suggestion {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(egui::RichText::new(format!("Product lijkt op {}", name)).color(ui.visuals().warn_fg_color));
+                if ui.small_button("Gebruik").clicked() {
+                    selected = Some(name);
+                }
+            });
+        }
 
-impl Default for GlobalState {
-    fn default() -> Self {
-        Self {
-            is_connected: Arc::new(AtomicBool::new(false)),
-            connection_info: Arc::new(Mutex::new(None)),
-            logs: VecDeque::new(),
-            serial_port_path: String::new(),
-            connection_states: [false; 8],
-            thread_spawned: false,
-            dimensions: Point::default(),
-            speed: 0.0,
-            measurements: BTreeMap::new(),
-            show_side_panel: true,
+        if selected != active {
+            self.live.send_command(live::Command::SelectRecipe(selected));
         }
     }
 }
 
 struct MyApp {
-    tree: egui_tiles::Tree<Tab>,
+    workspace: Workspace,
+    // Wat de GUI tekent; de engine verwerkt de berichten en is de enige eigenaar
+    // van de toestand
     state: GlobalState,
-    log_receiver: Option<Receiver<ParsedMessage>>,
-    visualization_tab: Arc<Mutex<VisualizationTab>>,
+    feed: UiFeed,
+    show_side_panel: bool,
 }
 
 impl MyApp {
-    fn new(cc: &CreationContext) -> Self {
+    fn new(cc: &CreationContext, cli: &cli::Cli) -> Self {
         egui_extras::install_image_loaders(&cc.egui_ctx);
         re_ui::apply_style_and_install_loaders(&cc.egui_ctx);
         egui_material_icons::initialize(&cc.egui_ctx);
 
-        let visualization_tab = Arc::new(Mutex::new(VisualizationTab::new()));
-
-        let tabs: Vec<Tab> = vec![
-            Arc::new(Mutex::new(ResultsTab)),
-            visualization_tab.clone(),
-            Arc::new(Mutex::new(LogsTab)),
-        ];
-        
+        let workspace = Workspace::new(tab_registry(), &PRESETS[1]);
 
-        let tree = egui_tiles::Tree::new_vertical(Id::new("bla"), tabs);
-        
-        Self {
-            tree,
-            state: Default::default(),
-            log_receiver: Default::default(),
-            visualization_tab,
+        let (live, commands) = live::channel_pair();
+        if cli.api.api {
+            if let Err(err) = api::start(&cli.api, live.clone()) {
+                re_log::error!("Kan de API niet starten: {}", err);
+            }
+        }
+        if let Err(err) = mqtt::start(&cli.mqtt, live.clone()) {
+            re_log::error!("Kan MQTT niet starten: {}", err);
+        }
+        if let Err(err) = modbus::start(&cli.modbus, live.clone()) {
+            re_log::error!("Kan de Modbus server niet starten: {}", err);
+        }
+        if let Err(err) = opc_ua::start(&cli.opcua, live.clone()) {
+            re_log::error!("Kan de OPC UA server niet starten: {}", err);
+        }
+        if let Err(err) = metrics::start(&cli.metrics, live.clone()) {
+            re_log::error!("Kan het metrics endpoint niet starten: {}", err);
         }
-    }
 
-    fn spawn_serial_thread(&mut self) {
-        let (sender, receiver) = channel();
-
-        let port_path = Arc::new(self.state.serial_port_path.clone());
-        let connection_info = self.state.connection_info.clone();
-        let is_connected_clone = self.state.is_connected.clone();
-
-        // Handel de seriele communicatie in een aparte thread om de GUI niet te blokkeren
-        std::thread::spawn(move || {
-            loop {
-                let port_result = serialport::new(&*port_path, 115200)
-                    .timeout(std::time::Duration::from_secs(1))
-                    .open();
-
-                match port_result {
-                    Ok(mut port) => {
-                        is_connected_clone.store(true, Ordering::Relaxed);
-                        *connection_info.lock().unwrap() = Some(ConnectionInfo::new((*port_path).clone(), 115200));
-
-                        let mut buffer = vec![0; 1024];
-                        loop {
-                            match port.read(&mut buffer) {
-                                Ok(size) if size > 0 => {
-                                    let received_chunk = String::from_utf8_lossy(&buffer[..size]).to_string();
-
-                                    // Check of het bericht compleet is
-                                    if received_chunk.contains('$') && received_chunk.contains('#') {
-                                        if let Some(start) = received_chunk.find('$') {
-                                            if let Some(end) = received_chunk[start..].find('#') {
-                                                let full_message = &received_chunk[start..=start + end];
-
-                                                // Split de berichten in delen
-                                                let parts: Vec<&str> = full_message[1..full_message.len() - 1].split(':').collect();
-                                                if parts.len() >= 2 {
-                                                    let timestamp = parts[0].to_string();
-                                                    let command = parts[1].to_string();
-
-                                                    let mut fields = std::collections::HashMap::new();
-
-                                                    for field in parts.iter().skip(2) {
-                                                        if let Some((key, value)) = field.split_once('=') {
-                                                            // Toevoegen aan het veld
-                                                            fields.insert(key.to_string(), value.to_string());
-                                                        }
-                                                    }
-
-                                                    // Maak een ParsedMessage
-                                                    let parsed_message = ParsedMessage {
-                                                        timestamp,
-                                                        command,
-                                                        fields,
-                                                    };
-
-                                                    sender.send(parsed_message).ok();
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                Ok(_) => {
-                                    continue;
-                                }
-                                Err(err) => match err.kind() {
-                                    // Negeer timeouts
-                                    std::io::ErrorKind::TimedOut => {
-                                        continue;
-                                    }
-                                    _ => {
-                                        println!("Serial error {}", err);
-                                        // Zet de verbinding naar false
-                                        is_connected_clone.store(false, Ordering::Relaxed);
-                                        break;
-                                    }
-                                },
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        println!("Error: handle serial thread error {}", err.to_string());
-                        std::thread::sleep(Duration::from_secs(1));
-                    }
-                }
-            }
-        });
+        let serial_link = SerialLink::default();
+        let state = GlobalState::new(live.clone(), serial_link.clone());
+        let feed = Engine::spawn(live, commands, serial_link);
 
-        self.log_receiver = Some(receiver);
+        Self {
+            workspace,
+            state,
+            feed,
+            show_side_panel: true,
+        }
     }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let is_connected = self.state.is_connected.load(Ordering::Relaxed);
-
-        if !self.state.thread_spawned && self.state.serial_port_path.len() > 0 {
-            self.spawn_serial_thread();
-            self.state.thread_spawned = true;
-        }
-
-        if !is_connected {
-            let ports = available_ports().unwrap();
-
-            for port in ports {
-                if let SerialPortType::UsbPort(usb) = &port.port_type {
-                    if let Some(manufacturer) = &usb.manufacturer {
-                        if manufacturer == KNOWN_MANUFACTURER {
-                            self.state.serial_port_path = port.port_name;
-                        }
-                    }
-                }
-            }
-        }
-        
-        if let Some(receiver) = &self.log_receiver {
-        for log_message in receiver.try_iter() {
-            self.state.logs.push_back(log_message.to_string());
-
-            if self.state.logs.len() > 100 {
-                self.state.logs.pop_front();
-            }
-
-            match log_message.command.as_str()
-            {
-                "SMS" => {
-                    if let Some(id) = log_message.fields.get("ID") {
-                        let index = id.parse::<usize>().unwrap()-1;
-
-                        if let (Some(Ok(id)), Some(Ok(connected_parsed)), Some(Ok(value))) = (
-                            log_message.fields.get("ID").map(|t| t.parse::<u8>()),
-                            log_message.fields.get("C").map(|v| v.parse::<u8>()),
-                            log_message.fields.get("V").map(|v| v.parse::<u16>()),
-                        ) {
-                            let connected = connected_parsed != 0;
-                            self.state.connection_states[index] = connected;
-
-                            let measurement = Measurement { id, connected, value };
-                            self.state.measurements.insert(id, measurement);
-
-                            let mut tab = self.visualization_tab.lock().unwrap();
-                            tab.add_sensor_value(measurement.clone());
-
-                        }
-                    };
-                },
-                "MET" => {
-                    println!("MET message: {:?}", log_message.fields);
-                    if let (Some(Ok(width)), Some(Ok(length)), Some(Ok(speed))) = (
-                        log_message.fields.get("W").map(|t| t.parse::<f64>()),
-                        log_message.fields.get("L").map(|v| v.parse::<f64>()),
-                        log_message.fields.get("S").map(|v| v.parse::<f64>()),
-                    ) {
-                        self.state.dimensions = Point::new(width, length);
-                        self.state.speed = speed;
-                        println!("{:?}", self.state.dimensions);
-                    }
-                },
-                _ => println!("else"),
-            }
-        }
+        let frame_started = std::time::Instant::now();
+        let state = &mut self.state;
+        for update in self.feed.updates.try_iter() {
+            state.apply(update);
         }
+        state.serial_monitor.drain();
+        state.engine = self.feed.snapshot();
+        let engine = state.engine.clone();
+        let is_connected = engine.connected;
 
         egui::TopBottomPanel::top("top_bar")
             .frame(re_ui::DesignTokens::top_panel_frame())
@@ -319,12 +247,15 @@ impl eframe::App for MyApp {
                         ui.menu_button("File", |ui| {
                             ui.add(egui::Button::new("Quit"))
                         });
+                        ui.menu_button("View", |ui| {
+                            self.workspace.menu_ui(ui);
+                        });
                     });
     
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.medium_icon_toggle_button(
                             &re_ui::icons::LEFT_PANEL_TOGGLE,
-                            &mut self.state.show_side_panel,
+                            &mut self.show_side_panel,
                         );
                     });    
                 })
@@ -335,16 +266,22 @@ impl eframe::App for MyApp {
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     if is_connected {
-                        if let Some(ref connection_info) = self.state.connection_info.lock().unwrap().as_ref() {
+                        if let Some(connection_info) = &engine.connection_info {
                             if ui.button(egui::RichText::new(format!("{} {}", egui_material_icons::icons::ICON_POWER, connection_info.port_path)).size(10.0)).clicked() {
                                 // Acties wanneer de knop wordt geklikt
-                                println!("USB icon button clicked!");
+                                re_log::debug!("USB icon button clicked!");
                             }
                             let _ = ui.label(egui::RichText::new(connection_info.baudrate.to_string()).size(10.0));
                         }
                     } else {
                         ui.add(egui::Spinner::new());
                     }
+                    // De weergave mist dan metingen en logregels; de verwerking zelf niet
+                    if engine.skipped_updates > 0 {
+                        ui.label(egui::RichText::new(format!("{} updates niet getoond", engine.skipped_updates))
+                            .size(10.0)
+                            .color(ui.visuals().warn_fg_color));
+                    }
                 });
             });
 
@@ -357,23 +294,44 @@ impl eframe::App for MyApp {
                 inner_margin: egui::Margin::same(5.0),
                 ..Default::default()
             })
-            .show_animated(ctx, self.state.show_side_panel, |ui| {
+            .show_animated(ctx, self.show_side_panel, |ui| {
                 ui.vertical_centered(|ui| {
                     ui.add(egui::Image::new(egui::include_image!("../assets/Logo_Full_White_Transparent.png")).max_width(100.0));
                 });
 
                 ui.horizontal_wrapped(|ui| {
-                    ui.button("Start");
-                    ui.button("Stop");
+                    if ui.add_enabled(!engine.running, egui::Button::new("Start")).clicked() {
+                        state.live.send_command(live::Command::Start);
+                    }
+                    if ui.add_enabled(engine.running, egui::Button::new("Stop")).clicked() {
+                        state.live.send_command(live::Command::Stop);
+                    }
                     if ui.button("Calibrate").clicked() {
-
+                        state.live.send_command(live::Command::Calibrate);
                     };
                 });
 
+                state.recipe_ui(ui);
+
+                let alarms = &engine.alarms;
+                if !alarms.is_empty() {
+                    re_ui::list_item::list_item_scope(ui, "alarms", |ui| {
+                    ui.section_collapsing_header("Alarmen")
+                        .show(ui, |ui| {
+                            for alarm in alarms {
+                                ui.label(egui::RichText::new(&alarm.message).color(ui.visuals().warn_fg_color));
+                            }
+                            if engine.has_latched_alarm && ui.button("Bevestig").clicked() {
+                                state.live.send_command(live::Command::AcknowledgeAlarms);
+                            }
+                        });
+                    });
+                }
+
                 re_ui::list_item::list_item_scope(ui, "sensor_states", |ui| {
                 ui.section_collapsing_header("Sensoren & Status")
                     .show(ui, |ui| {
-                        for measurement_hash in self.state.measurements.iter() {
+                        for measurement_hash in engine.line.measurements.iter() {
                             let (id, measurement) = measurement_hash;
 
                             ui.horizontal(|ui| {
@@ -400,15 +358,12 @@ impl eframe::App for MyApp {
                 fill: ctx.style().visuals.panel_fill,
                 ..Default::default()
             }).show(ctx, |ui| {
-                self.tree.ui(&mut self.state, ui);
+                self.workspace.ui(state, ui);
             });
 
 
             // Laat een los window zien als de hoofdapplicatie niet kan verbinden met het master board
             if !is_connected {
-                self.state.speed = 0.0;
-                self.state.dimensions = Point::new(0.0, 0.0);
-
                 ctx.show_viewport_immediate(
                     egui::ViewportId::from_hash_of("connection_window"),
                     egui::ViewportBuilder::default()
@@ -423,8 +378,12 @@ impl eframe::App for MyApp {
                 );
             }
             
+            state.hub_events.show_notifications(ctx);
+
             // Repaint TARGET_FRAME_RATE frames per seconde
             ctx.request_repaint_after(std::time::Duration::from_millis((1000/TARGET_FRAME_RATE).try_into().unwrap()));
+
+            METRICS.frame_time.observe(frame_started.elapsed().as_secs_f64());
     }
 }
 
@@ -432,28 +391,35 @@ impl eframe::App for MyApp {
 pub trait RenderableTab {
     fn title(&self) -> &str;
     fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState);
+
+    // Een kopie met dezelfde instellingen, voor "Dupliceren"; zonder kopie opent
+    // er een nieuwe tab van dezelfde soort
+    fn duplicate(&self) -> Option<Tab> {
+        None
+    }
 }
 
 pub type Tab = Arc<Mutex<dyn RenderableTab>>;
 
-pub struct LogsTab;
-
-impl RenderableTab for LogsTab {
-    fn title(&self) -> &str {
-        "Logs"
-    }
-
-    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
-    // Laat de seriele communicatie zien in een scrollable area.
-    eframe::egui::ScrollArea::vertical()
-    .auto_shrink(false)
-    .show(ui, |ui| {
-        for log in &state.logs {
-            ui.label(egui::RichText::new(log).monospace());
-        }
-        ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
-    });
-    }
+// Alle soorten tabs die vanuit het View menu te openen zijn
+fn tab_registry() -> TabRegistry {
+    let mut registry = TabRegistry::default();
+    registry
+        .register("results", "Results", || ResultsTab)
+        .register("visualization", "Visualization", VisualizationTab::new)
+        .register("gallery", "Detecties", GalleryTab::new)
+        .register("items", "Producten", ItemsTab::new)
+        .register("statistics", "Statistiek", StatisticsTab::new)
+        .register("logs", "Logs", LogsTab::new)
+        .register("serial_monitor", "Serial Monitor", SerialMonitorTab::new)
+        .register("events", "Events", EventsTab::new)
+        .register("config", "Configuratie", ConfigTab::new)
+        .register("recipes", "Recepten", RecipesTab::new)
+        .register("batch", "Batches", BatchTab::new)
+        .register("report", "Rapporten", report::ReportTab::new)
+        .register("firmware", "Firmware", firmware::FirmwareTab::new)
+        .register("app_log", "Application Log", app_log::AppLogTab::new);
+    registry
 }
 
 pub struct ResultsTab;
@@ -465,117 +431,43 @@ impl RenderableTab for ResultsTab {
 
     fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
         // Geef de breedte, lengte en snelheid weer in de GUI.
-        ui.label(format!("Width: {} mm", state.dimensions.x));
-        ui.label(format!("Length: {} mm", state.dimensions.y));
-        ui.label(format!("Snelheid: {} cm/s", state.speed));
+        let line = &state.engine.line;
+        ui.label(format!("Width: {} mm", line.dimensions.width));
+        ui.label(format!("Length: {} mm", line.dimensions.length));
+        ui.label(format!("Snelheid: {} cm/s", line.speed));
 
         for i in 0..NUM_SENSORS {
-            if let Some(measurement) = state.measurements.get(&((i+1) as u8)) {
+            if let Some(measurement) = line.measurements.get(&((i+1) as u8)) {
                 ui.label(format!("Sensor S0{}: {}", measurement.id, measurement.value));
             }
         }
-    }
-}
-
-// // 250 => samples per seconds * (band lengte (cm) / band snelheid (cm/s)) => 10*(100\4)
-const SAMPLE_BUF_SIZE: usize = 10*(100/4);
-
-// even nummer
-const VP_WIDTH: usize = 100;
-const VP_HEIGHT: usize = 250;
-
-const PX_PER_SENSOR: usize = VP_WIDTH / NUM_SENSORS;
 
-
-pub struct VisualizationTab {
-    cache: Cache,
-    frame_counter: usize,
-    sensor_buffer: VecDeque<Measurement>,
-}
-
-impl VisualizationTab {
-    pub fn new() -> Self {
-        Self {
-            cache: Cache::default(),
-            frame_counter: 0,
-            sensor_buffer: VecDeque::with_capacity(SAMPLE_BUF_SIZE),
+        if let Some(item) = state.items.last() {
+            ui.separator();
+            ui.label(format!(
+                "Laatste product #{}: {:.0} x {:.0} mm, {}",
+                item.id,
+                item.width,
+                item.length,
+                item.verdict.label()
+            ));
         }
     }
-
-    pub fn add_sensor_value(&mut self, measurement: Measurement) {
-        if self.sensor_buffer.len() >= SAMPLE_BUF_SIZE {
-            self.sensor_buffer.pop_front();
-        }
-        self.sensor_buffer.push_back(measurement);
-    }
 }
 
-impl RenderableTab for VisualizationTab {
-    fn title(&self) -> &str {
-        "Visualization"
-    }
-
-    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
-        let size = egui::Vec2::new(VP_WIDTH as f32, VP_HEIGHT as f32);
-
-        self.cache.resize(size);
-        // self.cache.pixels.fill(egui::Color32::BLACK);
-
-        for y in 1..VP_HEIGHT {
-            let src_start = y * VP_WIDTH;
-            let dest_start = (y - 1) * VP_WIDTH;
-            self.cache.pixels.copy_within(src_start..src_start + VP_WIDTH, dest_start);
-        }
-
-        let mut new_row = vec![0.0; VP_WIDTH];
-
-        for (sensor_idx, measurement) in state.measurements.values().enumerate() {
-            if sensor_idx >= NUM_SENSORS {
-                continue;
-            }
-
-            let intensity = (measurement.value as f32 / 2000.0).clamp(0.0, 1.0);
-
-            let start_x = sensor_idx * PX_PER_SENSOR;
-            let end_x = start_x + PX_PER_SENSOR;
-
-            for x in start_x..end_x {
-                let weight = 1.0 - ((x - start_x) as f32 / PX_PER_SENSOR as f32);
-                new_row[x] += intensity * weight;
-            }
-        }
-
-        // **Stap 4: Nieuwe rij pixels invullen onderaan**
-        let last_row_idx = (VP_HEIGHT - 1) * VP_WIDTH;
-        for (x, &intensity) in new_row.iter().enumerate() {
-            self.cache.pixels[last_row_idx + x] = egui::Color32::from_gray((intensity * 255.0) as u8);
-        }
-
-        // **Stap 5: Texture genereren en renderen**
-        let texture = egui::ColorImage {
-            size: [VP_WIDTH, VP_HEIGHT],
-            pixels: self.cache.pixels.clone(),
-        };
-
-        let handle = ui.ctx().load_texture("sensor", texture, Default::default());
-        ui.add(egui::Image::new(&handle).fit_to_exact_size(size));
-    }
-}
-
-impl egui_tiles::Behavior<Tab> for GlobalState {
-    fn tab_title_for_pane(&mut self, tab: &Tab) -> egui::WidgetText {
-        let locked_tab = tab.lock().unwrap();
-        locked_tab.title().into()
+impl egui_tiles::Behavior<Pane> for GlobalState {
+    fn tab_title_for_pane(&mut self, pane: &Pane) -> egui::WidgetText {
+        pane.title().into()
     }
 
     fn pane_ui(
         &mut self,
         ui: &mut egui::Ui,
         _tile_id: egui_tiles::TileId,
-        tab: &mut Tab,
+        pane: &mut Pane,
     ) -> egui_tiles::UiResponse {
         egui::Frame::default().inner_margin(re_ui::DesignTokens::view_padding()).show(ui, |ui| {
-            let mut locked_tab = tab.lock().unwrap();
+            let mut locked_tab = pane.tab.lock().unwrap();
             locked_tab.ui(ui, self);
         });
 
@@ -585,13 +477,17 @@ impl egui_tiles::Behavior<Tab> for GlobalState {
     fn tab_outline_stroke(
         &self,
         _visuals: &egui::Visuals,
-        _tiles: &egui_tiles::Tiles<Tab>,
+        _tiles: &egui_tiles::Tiles<Pane>,
         _tile_id: egui_tiles::TileId,
         _tab_state: &egui_tiles::TabState,
     ) -> egui::Stroke {
         egui::Stroke::NONE
     }
 
+    fn is_tab_closable(&self, _tiles: &egui_tiles::Tiles<Pane>, _tile_id: egui_tiles::TileId) -> bool {
+        true
+    }
+
     fn tab_bar_height(&self, _style: &egui::Style) -> f32 {
         re_ui::DesignTokens::title_bar_height()
     }
@@ -603,23 +499,3 @@ impl egui_tiles::Behavior<Tab> for GlobalState {
         }
     }
 }
-
-
-#[derive(Default)]
-struct Cache {
-    pixels: Vec<egui::Color32>,
-    size: egui::Vec2,
-}
-
-impl Cache {
-    fn resize(&mut self, size: egui::Vec2) {
-        if size == self.size {
-            return;
-        }
-
-        self.pixels = Vec::new();
-        self.pixels
-            .resize((size.x*size.y) as usize, egui::Color32::default());
-        self.size = size;
-    }
-}
@@ -0,0 +1,182 @@
+use crate::live::{LiveHandle, SensorHealth, Snapshot};
+use metalstream::protocol::NUM_SENSORS;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use tiny_http::{Header, Response, Server};
+
+#[derive(clap::Args)]
+pub struct MetricsArgs {
+    /// Start een Prometheus `/metrics` endpoint op dit adres, bijv. `0.0.0.0:9464`
+    #[arg(long)]
+    pub metrics: Option<SocketAddr>,
+}
+
+// Alle Prometheus metrics van de applicatie. Tellers worden bijgewerkt waar het
+// gebeurt; de toestand (verbinding, sensoren, snelheid) wordt bij elke scrape uit
+// de snapshot gehaald.
+pub struct Metrics {
+    registry: Registry,
+    pub frames: IntCounterVec,
+    pub parse_errors: IntCounterVec,
+    pub detections: IntCounter,
+    pub items: IntCounterVec,
+    pub alarms: IntCounterVec,
+    pub hub_events: IntCounterVec,
+    pub frame_time: Histogram,
+    pub processing_time: Histogram,
+    connected: IntGauge,
+    running: IntGauge,
+    speed: Gauge,
+    width: Gauge,
+    length: Gauge,
+    sensor_value: IntGaugeVec,
+    sensor_connected: IntGaugeVec,
+    sensor_health: IntGaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+const HEALTH_STATES: [SensorHealth; 4] = [
+    SensorHealth::Ok,
+    SensorHealth::Uncalibrated,
+    SensorHealth::Disconnected,
+    SensorHealth::NoData,
+];
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("metalstream".to_string()), None).unwrap();
+
+        fn register<T: prometheus::core::Collector + Clone + 'static>(registry: &Registry, collector: T) -> T {
+            registry.register(Box::new(collector.clone())).unwrap();
+            collector
+        }
+
+        Self {
+            frames: register(
+                &registry,
+                IntCounterVec::new(Opts::new("frames_received_total", "Ontvangen frames per commando"), &["command"]).unwrap(),
+            ),
+            parse_errors: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("parse_errors_total", "Weggegooide frames en berichten per stap en reden"),
+                    &["stage", "reason"],
+                )
+                .unwrap(),
+            ),
+            detections: register(
+                &registry,
+                IntCounter::new("detections_total", "Aantal metaaldetecties").unwrap(),
+            ),
+            items: register(
+                &registry,
+                IntCounterVec::new(Opts::new("items_total", "Afgeronde producten per oordeel"), &["verdict"]).unwrap(),
+            ),
+            alarms: register(
+                &registry,
+                IntCounterVec::new(Opts::new("alarms_total", "Opgetreden alarmen per soort"), &["kind"]).unwrap(),
+            ),
+            hub_events: register(
+                &registry,
+                IntCounterVec::new(Opts::new("hub_events_total", "Door de hub gemelde fouten en gebeurtenissen"), &["code"]).unwrap(),
+            ),
+            frame_time: register(
+                &registry,
+                Histogram::with_opts(
+                    HistogramOpts::new("gui_frame_time_seconds", "Tijd besteed aan een GUI frame")
+                        .buckets(vec![0.001, 0.002, 0.005, 0.008, 0.016, 0.033, 0.05, 0.1, 0.25]),
+                )
+                .unwrap(),
+            ),
+            processing_time: register(
+                &registry,
+                Histogram::with_opts(
+                    HistogramOpts::new("engine_cycle_seconds", "Tijd besteed aan een verwerkingsronde van de engine")
+                        .buckets(vec![0.0001, 0.0002, 0.0005, 0.001, 0.002, 0.005, 0.01, 0.025, 0.05]),
+                )
+                .unwrap(),
+            ),
+            connected: register(&registry, IntGauge::new("hub_connected", "Verbinding met de hub").unwrap()),
+            running: register(&registry, IntGauge::new("running", "Detectie loopt").unwrap()),
+            speed: register(&registry, Gauge::new("belt_speed_cm_per_second", "Bandsnelheid").unwrap()),
+            width: register(&registry, Gauge::new("item_width_mm", "Breedte van het laatste product").unwrap()),
+            length: register(&registry, Gauge::new("item_length_mm", "Lengte van het laatste product").unwrap()),
+            sensor_value: register(
+                &registry,
+                IntGaugeVec::new(Opts::new("sensor_value", "Huidige waarde per sensor"), &["sensor"]).unwrap(),
+            ),
+            sensor_connected: register(
+                &registry,
+                IntGaugeVec::new(Opts::new("sensor_connected", "Verbinding per sensor"), &["sensor"]).unwrap(),
+            ),
+            sensor_health: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("sensor_health", "1 voor de huidige gezondheid van een sensor"),
+                    &["sensor", "state"],
+                )
+                .unwrap(),
+            ),
+            registry,
+        }
+    }
+
+    fn update(&self, snapshot: &Snapshot) {
+        self.connected.set(snapshot.connected as i64);
+        self.running.set(snapshot.running as i64);
+        self.speed.set(snapshot.speed);
+        self.width.set(snapshot.width);
+        self.length.set(snapshot.length);
+
+        for id in 1..=NUM_SENSORS as u8 {
+            let sensor = id.to_string();
+            if let Some(measurement) = snapshot.sensor(id) {
+                self.sensor_value.with_label_values(&[&sensor]).set(measurement.value as i64);
+                self.sensor_connected.with_label_values(&[&sensor]).set(measurement.connected as i64);
+            }
+
+            let health = snapshot.sensor_health(id);
+            for state in HEALTH_STATES {
+                self.sensor_health
+                    .with_label_values(&[&sensor, state.as_str()])
+                    .set((state == health) as i64);
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).ok();
+        buffer
+    }
+}
+
+pub fn start(args: &MetricsArgs, live: LiveHandle) -> std::io::Result<()> {
+    let Some(addr) = args.metrics else {
+        return Ok(());
+    };
+    let server = Server::http(addr).map_err(std::io::Error::other)?;
+    re_log::info!("Prometheus metrics op http://{}/metrics", addr);
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if request.url() != "/metrics" {
+                request.respond(Response::empty(404)).ok();
+                continue;
+            }
+
+            METRICS.update(&live.snapshot());
+            let response = Response::from_data(METRICS.encode()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], TextEncoder::new().format_type().as_bytes()).unwrap(),
+            );
+            request.respond(response).ok();
+        }
+    });
+
+    Ok(())
+}
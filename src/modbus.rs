@@ -0,0 +1,372 @@
+use crate::live::{Command, LiveHandle, Snapshot};
+use metalstream::processing::AlarmKind;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+#[derive(clap::Args)]
+pub struct ModbusArgs {
+    /// Start een Modbus TCP server op dit adres, bijv. `0.0.0.0:502`
+    #[arg(long)]
+    pub modbus: Option<SocketAddr>,
+}
+
+// Een register dat zowel als input register (FC 4) als holding register (FC 3) te lezen is
+pub struct Register {
+    pub address: u16,
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub description: &'static str,
+    read: fn(&Snapshot) -> u16,
+}
+
+// Een coil (FC 1 lezen, FC 5/15 schrijven); een 1 schrijven voert de opdracht uit
+pub struct Coil {
+    pub address: u16,
+    pub name: &'static str,
+    pub description: &'static str,
+    read: fn(&Snapshot) -> bool,
+    command: Command,
+}
+
+fn flag(value: bool) -> u16 {
+    value as u16
+}
+
+fn tenths(value: f64) -> u16 {
+    (value * 10.0).round().clamp(0.0, u16::MAX as f64) as u16
+}
+
+fn has_alarm(snapshot: &Snapshot, matches: fn(&AlarmKind) -> bool) -> bool {
+    snapshot.alarms.iter().any(|alarm| matches(&alarm.kind))
+}
+
+fn sensor_value<const ID: u8>(snapshot: &Snapshot) -> u16 {
+    snapshot.sensor(ID).map(|measurement| measurement.value).unwrap_or_default()
+}
+
+fn sensor_mask(ids: impl Iterator<Item = u8>) -> u16 {
+    ids.filter(|id| (1..=16).contains(id)).fold(0, |mask, id| mask | 1 << (id - 1))
+}
+
+pub const REGISTERS: &[Register] = &[
+    Register { address: 0, name: "hub_connected", unit: "bool", description: "Verbinding met de Metalstream Hub", read: |s| flag(s.connected) },
+    Register { address: 1, name: "running", unit: "bool", description: "Detectie loopt", read: |s| flag(s.running) },
+    Register {
+        address: 2,
+        name: "sensors_connected",
+        unit: "bitmask",
+        description: "Bit n staat aan als sensor n+1 verbonden is",
+        read: |s| sensor_mask(s.measurements.iter().filter(|m| m.connected).map(|m| m.id)),
+    },
+    Register {
+        address: 3,
+        name: "alarms",
+        unit: "bitmask",
+        description: "Bit 0: hub niet verbonden, bit 1: sensor niet verbonden, bit 2: metaal gedetecteerd (niet bevestigd), bit 3: fout gemeld door de hub (niet bevestigd)",
+        read: |s| {
+            flag(has_alarm(s, |kind| *kind == AlarmKind::HubDisconnected))
+                | flag(has_alarm(s, |kind| matches!(kind, AlarmKind::SensorDisconnected(_)))) << 1
+                | flag(has_alarm(s, |kind| *kind == AlarmKind::MetalDetected)) << 2
+                | flag(has_alarm(s, |kind| kind.is_latched() && *kind != AlarmKind::MetalDetected)) << 3
+        },
+    },
+    Register { address: 4, name: "detections_low", unit: "count", description: "Aantal detecties, laagste 16 bits", read: |s| s.detections as u16 },
+    Register { address: 5, name: "detections_high", unit: "count", description: "Aantal detecties, hoogste 16 bits", read: |s| (s.detections >> 16) as u16 },
+    Register {
+        address: 6,
+        name: "last_detection_sensor",
+        unit: "id",
+        description: "Sensor met de grootste afwijking bij de laatste detectie, 0 als er nog geen is",
+        read: |s| s.last_detection.as_ref().map(|d| d.peak_sensor as u16).unwrap_or_default(),
+    },
+    Register {
+        address: 7,
+        name: "last_detection_peak",
+        unit: "raw",
+        description: "Grootste afwijking van de baseline bij de laatste detectie",
+        read: |s| s.last_detection.as_ref().map(|d| d.peak_deviation.min(u16::MAX as f64) as u16).unwrap_or_default(),
+    },
+    Register {
+        address: 8,
+        name: "last_detection_sensors",
+        unit: "bitmask",
+        description: "Sensoren die bij de laatste detectie boven de drempel kwamen",
+        read: |s| s.last_detection.as_ref().map(|d| sensor_mask(d.sensors.iter().copied())).unwrap_or_default(),
+    },
+    Register { address: 9, name: "speed", unit: "0.1 cm/s", description: "Bandsnelheid", read: |s| tenths(s.speed) },
+    Register { address: 10, name: "width", unit: "0.1 mm", description: "Breedte van het laatste product", read: |s| tenths(s.width) },
+    Register { address: 11, name: "length", unit: "0.1 mm", description: "Lengte van het laatste product", read: |s| tenths(s.length) },
+    Register { address: 12, name: "sensor_1", unit: "raw", description: "Waarde sensor S01", read: sensor_value::<1> },
+    Register { address: 13, name: "sensor_2", unit: "raw", description: "Waarde sensor S02", read: sensor_value::<2> },
+    Register { address: 14, name: "sensor_3", unit: "raw", description: "Waarde sensor S03", read: sensor_value::<3> },
+    Register { address: 15, name: "sensor_4", unit: "raw", description: "Waarde sensor S04", read: sensor_value::<4> },
+    Register { address: 16, name: "sensor_5", unit: "raw", description: "Waarde sensor S05", read: sensor_value::<5> },
+    Register { address: 17, name: "sensor_6", unit: "raw", description: "Waarde sensor S06", read: sensor_value::<6> },
+    Register { address: 18, name: "sensor_7", unit: "raw", description: "Waarde sensor S07", read: sensor_value::<7> },
+    Register { address: 19, name: "sensor_8", unit: "raw", description: "Waarde sensor S08", read: sensor_value::<8> },
+];
+
+pub const COILS: &[Coil] = &[
+    Coil { address: 0, name: "start", description: "Start de detectie; leest 1 als de detectie loopt", read: |s| s.running, command: Command::Start },
+    Coil { address: 1, name: "stop", description: "Stop de detectie; leest 1 als de detectie stilstaat", read: |s| !s.running, command: Command::Stop },
+    Coil { address: 2, name: "calibrate", description: "Bouw de baselines opnieuw op; leest altijd 0", read: |_| false, command: Command::Calibrate },
+    Coil {
+        address: 3,
+        name: "reject_acknowledge",
+        description: "Bevestig alle vergrendelde alarmen: metaal gedetecteerd en fouten gemeld door de hub; leest 1 zolang er een onbevestigd alarm is",
+        read: |s| has_alarm(s, AlarmKind::is_latched),
+        command: Command::AcknowledgeAlarms,
+    },
+];
+
+// Genereert de documentatie van de registermap in Markdown
+pub fn register_map_markdown() -> String {
+    let mut doc = String::from("# Modbus registermap\n\n");
+    doc.push_str("Gegenereerd met `desktop modbus-map`. Alle adressen zijn 0-gebaseerd.\n\n");
+
+    doc.push_str("## Input registers (FC 4) en holding registers (FC 3)\n\n");
+    doc.push_str("Holding registers bevatten dezelfde waarden als de input registers en zijn alleen-lezen.\n\n");
+    doc.push_str("| Adres | Naam | Eenheid | Omschrijving |\n|---|---|---|---|\n");
+    for register in REGISTERS {
+        doc.push_str(&format!(
+            "| {} | `{}` | {} | {} |\n",
+            register.address, register.name, register.unit, register.description
+        ));
+    }
+
+    doc.push_str("\n## Coils (FC 1, FC 5, FC 15)\n\n");
+    doc.push_str("Een 1 schrijven voert de opdracht uit, een 0 schrijven doet niets.\n\n");
+    doc.push_str("| Adres | Naam | Omschrijving |\n|---|---|---|\n");
+    for coil in COILS {
+        doc.push_str(&format!("| {} | `{}` | {} |\n", coil.address, coil.name, coil.description));
+    }
+
+    doc
+}
+
+pub fn start(args: &ModbusArgs, live: LiveHandle) -> std::io::Result<()> {
+    let Some(addr) = args.modbus else {
+        return Ok(());
+    };
+    let listener = TcpListener::bind(addr)?;
+    re_log::info!("Modbus TCP server luistert op {}", addr);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let live = live.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = serve(stream, &live) {
+                    re_log::warn!("Modbus error {}", err);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+// Modbus exception codes
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+fn serve(mut stream: TcpStream, live: &LiveHandle) -> std::io::Result<()> {
+    loop {
+        // MBAP header: transaction id, protocol id, lengte, unit id
+        let mut header = [0u8; 7];
+        match stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if !(2..=254).contains(&length) {
+            return Ok(());
+        }
+        let mut pdu = vec![0u8; length - 1];
+        stream.read_exact(&mut pdu)?;
+
+        let response = handle_pdu(&pdu, live);
+
+        let mut frame = Vec::with_capacity(7 + response.len());
+        frame.extend_from_slice(&header[..4]);
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame)?;
+    }
+}
+
+fn handle_pdu(pdu: &[u8], live: &LiveHandle) -> Vec<u8> {
+    let function = pdu[0];
+    let word = |offset: usize| pdu.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    let result = match (function, word(1), word(3)) {
+        (0x01, Some(start), Some(count)) => read_coils(&live.snapshot(), start, count),
+        (0x03 | 0x04, Some(start), Some(count)) => read_registers(&live.snapshot(), start, count),
+        (0x05, Some(address), Some(value)) => match value {
+            0xFF00 => write_coils(live, address, &[true]),
+            0x0000 => write_coils(live, address, &[false]),
+            _ => Err(ILLEGAL_DATA_VALUE),
+        },
+        (0x0F, Some(start), Some(count)) => {
+            let values = pdu
+                .get(6..)
+                .filter(|bytes| bytes.len() * 8 >= count as usize)
+                .map(|bytes| (0..count as usize).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect::<Vec<_>>());
+            match values {
+                Some(values) => write_coils(live, start, &values),
+                None => Err(ILLEGAL_DATA_VALUE),
+            }
+        }
+        (0x01 | 0x03 | 0x04 | 0x05 | 0x0F, _, _) => Err(ILLEGAL_DATA_VALUE),
+        // Registers zijn alleen-lezen
+        (0x06 | 0x10, _, _) => Err(ILLEGAL_DATA_ADDRESS),
+        _ => Err(ILLEGAL_FUNCTION),
+    };
+
+    match result {
+        // Schrijfopdrachten echoën de aanvraag
+        Ok(None) => pdu[..5].to_vec(),
+        Ok(Some(data)) => {
+            let mut response = vec![function, data.len() as u8];
+            response.extend(data);
+            response
+        }
+        Err(code) => vec![function | 0x80, code],
+    }
+}
+
+fn read_registers(snapshot: &Snapshot, start: u16, count: u16) -> Result<Option<Vec<u8>>, u8> {
+    if count == 0 || count > 125 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+
+    let mut data = Vec::with_capacity(count as usize * 2);
+    for address in start..start.saturating_add(count) {
+        let register = REGISTERS
+            .iter()
+            .find(|register| register.address == address)
+            .ok_or(ILLEGAL_DATA_ADDRESS)?;
+        data.extend_from_slice(&(register.read)(snapshot).to_be_bytes());
+    }
+    Ok(Some(data))
+}
+
+fn read_coils(snapshot: &Snapshot, start: u16, count: u16) -> Result<Option<Vec<u8>>, u8> {
+    if count == 0 || count > 2000 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+
+    let mut data = vec![0u8; (count as usize).div_ceil(8)];
+    for (i, address) in (start..start.saturating_add(count)).enumerate() {
+        let coil = COILS.iter().find(|coil| coil.address == address).ok_or(ILLEGAL_DATA_ADDRESS)?;
+        if (coil.read)(snapshot) {
+            data[i / 8] |= 1 << (i % 8);
+        }
+    }
+    Ok(Some(data))
+}
+
+fn write_coils(live: &LiveHandle, start: u16, values: &[bool]) -> Result<Option<Vec<u8>>, u8> {
+    let coils = (start..start.saturating_add(values.len() as u16))
+        .map(|address| COILS.iter().find(|coil| coil.address == address).ok_or(ILLEGAL_DATA_ADDRESS))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (coil, value) in coils.iter().zip(values) {
+        if *value {
+            live.send_command(coil.command.clone());
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live;
+    use metalstream::protocol::Measurement;
+    use std::sync::mpsc::Receiver;
+
+    fn handle() -> (LiveHandle, Receiver<Command>) {
+        let (live, commands) = live::channel_pair();
+        live.set_snapshot(Snapshot {
+            connected: true,
+            running: true,
+            speed: 12.34,
+            measurements: vec![
+                Measurement { id: 1, connected: true, value: 512 },
+                Measurement { id: 3, connected: false, value: 0 },
+            ],
+            ..Default::default()
+        });
+        (live, commands)
+    }
+
+    #[test]
+    fn reads_registers() {
+        let (live, _commands) = handle();
+        // FC 4 vanaf adres 0: verbonden, loopt, alleen S01 verbonden
+        assert_eq!(handle_pdu(&[0x04, 0, 0, 0, 3], &live), [0x04, 6, 0, 1, 0, 1, 0, 0b1]);
+        // FC 3 geeft dezelfde waarden: snelheid in 0.1 cm/s en S01
+        assert_eq!(handle_pdu(&[0x03, 0, 9, 0, 1], &live), [0x03, 2, 0, 123]);
+        assert_eq!(handle_pdu(&[0x04, 0, 12, 0, 1], &live), [0x04, 2, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn reads_coils() {
+        let (live, _commands) = handle();
+        // Start leest 1 (loopt), stop 0, calibrate 0, geen onbevestigd alarm
+        assert_eq!(handle_pdu(&[0x01, 0, 0, 0, 4], &live), [0x01, 1, 0b0001]);
+    }
+
+    #[test]
+    fn writes_coils() {
+        let (live, commands) = handle();
+        let request = [0x05, 0, 2, 0xFF, 0x00];
+        assert_eq!(handle_pdu(&request, &live), request);
+        assert!(matches!(commands.try_recv(), Ok(Command::Calibrate)));
+
+        // Een 0 schrijven doet niets
+        assert_eq!(handle_pdu(&[0x05, 0, 0, 0, 0], &live), [0x05, 0, 0, 0, 0]);
+        assert!(commands.try_recv().is_err());
+
+        // FC 15: stop (adres 1) en acknowledge (adres 3), calibrate niet
+        assert_eq!(handle_pdu(&[0x0F, 0, 1, 0, 3, 1, 0b101], &live), [0x0F, 0, 1, 0, 3]);
+        assert!(matches!(commands.try_recv(), Ok(Command::Stop)));
+        assert!(matches!(commands.try_recv(), Ok(Command::AcknowledgeAlarms)));
+        assert!(commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn answers_with_exceptions() {
+        let (live, commands) = handle();
+        let last = REGISTERS.len() as u16;
+        // Adressen buiten de map, ook als alleen het einde erbuiten valt
+        assert_eq!(handle_pdu(&[0x04, 0, last as u8, 0, 1], &live), [0x84, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(handle_pdu(&[0x03, 0, last as u8 - 1, 0, 2], &live), [0x83, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(handle_pdu(&[0x01, 0, 3, 0, 2], &live), [0x81, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(handle_pdu(&[0x0F, 0, 3, 0, 2, 1, 0b11], &live), [0x8F, ILLEGAL_DATA_ADDRESS]);
+        // Een coil buiten de map voert ook de geldige coils ervoor niet uit
+        assert!(commands.try_recv().is_err());
+
+        // Aantallen en waarden die niet mogen, of een te korte aanvraag
+        assert_eq!(handle_pdu(&[0x04, 0, 0, 0, 0], &live), [0x84, ILLEGAL_DATA_VALUE]);
+        assert_eq!(handle_pdu(&[0x04, 0, 0, 0, 126], &live), [0x84, ILLEGAL_DATA_VALUE]);
+        assert_eq!(handle_pdu(&[0x05, 0, 0, 0x12, 0x34], &live), [0x85, ILLEGAL_DATA_VALUE]);
+        assert_eq!(handle_pdu(&[0x0F, 0, 0, 0, 9, 1, 0xFF], &live), [0x8F, ILLEGAL_DATA_VALUE]);
+        assert_eq!(handle_pdu(&[0x03, 0], &live), [0x83, ILLEGAL_DATA_VALUE]);
+
+        // Registers zijn alleen-lezen, andere functies bestaan niet
+        assert_eq!(handle_pdu(&[0x06, 0, 0, 0, 1], &live), [0x86, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(handle_pdu(&[0x10, 0, 0, 0, 1, 2, 0, 1], &live), [0x90, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(handle_pdu(&[0x2B, 0x0E, 1, 0], &live), [0xAB, ILLEGAL_FUNCTION]);
+    }
+
+    #[test]
+    fn documentation_is_up_to_date() {
+        // Bijwerken met `desktop modbus-map > docs/modbus.md`
+        assert_eq!(include_str!("../docs/modbus.md"), register_map_markdown());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::live::{Command, LiveEvent, LiveHandle};
+use metalstream::protocol::Message;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+#[derive(clap::Args)]
+pub struct MqttArgs {
+    /// MQTT broker om naar te publiceren, bijv. `localhost:1883`
+    #[arg(long)]
+    pub mqtt: Option<String>,
+    /// Begin van alle topics
+    #[arg(long, default_value = "metalstream")]
+    pub mqtt_topic_prefix: String,
+    /// Topic waarop opdrachten (start, stop, calibrate, acknowledge) binnenkomen
+    #[arg(long)]
+    pub mqtt_command_topic: Option<String>,
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub mqtt_qos: u8,
+    #[arg(long, default_value = "metalstream-desktop")]
+    pub mqtt_client_id: String,
+    #[arg(long, env = "METALSTREAM_MQTT_USERNAME")]
+    pub mqtt_username: Option<String>,
+    #[arg(long, env = "METALSTREAM_MQTT_PASSWORD")]
+    pub mqtt_password: Option<String>,
+}
+
+// Topics waarop wordt gepubliceerd, allemaal onder dezelfde prefix
+struct Topics {
+    online: String,
+    status: String,
+    sensors: String,
+    line: String,
+    detections: String,
+    items: String,
+    events: String,
+    alarms: String,
+    active_alarms: String,
+    command: String,
+}
+
+impl Topics {
+    fn new(args: &MqttArgs) -> Self {
+        let prefix = args.mqtt_topic_prefix.trim_end_matches('/');
+        Self {
+            online: format!("{}/online", prefix),
+            status: format!("{}/status", prefix),
+            sensors: format!("{}/sensors", prefix),
+            line: format!("{}/line", prefix),
+            detections: format!("{}/detections", prefix),
+            items: format!("{}/items", prefix),
+            events: format!("{}/events", prefix),
+            alarms: format!("{}/alarms", prefix),
+            active_alarms: format!("{}/alarms/active", prefix),
+            command: args
+                .mqtt_command_topic
+                .clone()
+                .unwrap_or_else(|| format!("{}/command", prefix)),
+        }
+    }
+
+    // Elke sensor heeft een eigen topic, bijv. `metalstream/sensors/3`
+    fn sensor(&self, id: u8) -> String {
+        format!("{}/{}", self.sensors, id)
+    }
+}
+
+#[derive(Serialize, PartialEq)]
+struct Status {
+    connected: bool,
+    running: bool,
+    port_path: Option<String>,
+}
+
+// Verbind met de broker en publiceer de live data in twee threads:
+// een voor de verbinding (en binnenkomende opdrachten) en een voor het publiceren.
+pub fn start(args: &MqttArgs, live: LiveHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(broker) = &args.mqtt else {
+        return Ok(());
+    };
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => (host.to_string(), port.parse()?),
+        None => (broker.clone(), 1883),
+    };
+
+    let qos = rumqttc::qos(args.mqtt_qos)?;
+    let topics = Topics::new(args);
+
+    let mut options = MqttOptions::new(&args.mqtt_client_id, host, port);
+    options.set_keep_alive(Duration::from_secs(5));
+    // Zonder nette afmelding zet de broker ons zelf op offline
+    options.set_last_will(LastWill::new(&topics.online, "false", QoS::AtLeastOnce, true));
+    if let (Some(username), Some(password)) = (&args.mqtt_username, &args.mqtt_password) {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut connection) = Client::new(options, 100);
+
+    let command_topic = topics.command.clone();
+    let online_topic = topics.online.clone();
+    let connection_client = client.clone();
+    let command_live = live.clone();
+    std::thread::spawn(move || {
+        for event in connection.iter() {
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // Na elke (her)verbinding opnieuw aanmelden en abonneren
+                    connection_client.publish(&online_topic, QoS::AtLeastOnce, true, "true").ok();
+                    connection_client.subscribe(&command_topic, QoS::AtLeastOnce).ok();
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == command_topic => {
+                    match parse_command(&publish.payload) {
+                        Some(command) => command_live.send_command(command),
+                        None => re_log::warn!("MQTT: onbekende opdracht {:?}", publish.payload),
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    re_log::warn!("MQTT error {}", err);
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    });
+
+    std::thread::spawn(move || publish_loop(client, topics, qos, live));
+
+    Ok(())
+}
+
+fn parse_command(payload: &[u8]) -> Option<Command> {
+    match String::from_utf8_lossy(payload).trim().to_lowercase().as_str() {
+        "start" => Some(Command::Start),
+        "stop" => Some(Command::Stop),
+        "calibrate" => Some(Command::Calibrate),
+        "acknowledge" => Some(Command::AcknowledgeAlarms),
+        _ => None,
+    }
+}
+
+fn publish_loop(client: Client, topics: Topics, qos: QoS, live: LiveHandle) {
+    let events = live.subscribe();
+    let mut last_status = None;
+    let mut last_alarms = None;
+
+    loop {
+        match events.recv_timeout(Duration::from_secs(1)) {
+            Ok(LiveEvent::Message(message)) => match message.decode() {
+                Ok(Message::Sms(measurement)) => {
+                    publish_json(&client, &topics.sensor(measurement.id), qos, false, &measurement);
+                }
+                Ok(Message::Met(metrics)) => publish_json(&client, &topics.line, qos, false, &metrics),
+                Ok(_) | Err(_) => {}
+            },
+            Ok(LiveEvent::Detection(detection)) => publish_json(&client, &topics.detections, qos, false, &detection),
+            Ok(LiveEvent::Item(item)) => publish_json(&client, &topics.items, qos, false, &item),
+            Ok(LiveEvent::Alarm(alarm)) => publish_json(&client, &topics.alarms, qos, false, &alarm),
+            Ok(LiveEvent::HubEvent(event)) => publish_json(&client, &topics.events, qos, false, &event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Status en actieve alarmen worden retained gepubliceerd zodra ze veranderen
+        let snapshot = live.snapshot();
+        let status = Status {
+            connected: snapshot.connected,
+            running: snapshot.running,
+            port_path: snapshot.port_path,
+        };
+        if last_status.as_ref() != Some(&status) {
+            publish_json(&client, &topics.status, qos, true, &status);
+            last_status = Some(status);
+        }
+
+        let alarms = serde_json::to_string(&snapshot.alarms).unwrap_or_default();
+        if last_alarms.as_ref() != Some(&alarms) {
+            client.publish(&topics.active_alarms, qos, true, alarms.clone()).ok();
+            last_alarms = Some(alarms);
+        }
+    }
+
+    client.publish(&topics.online, QoS::AtLeastOnce, true, "false").ok();
+    client.disconnect().ok();
+}
+
+fn publish_json(client: &Client, topic: &str, qos: QoS, retain: bool, payload: &impl Serialize) {
+    if let Ok(payload) = serde_json::to_vec(payload) {
+        client.publish(topic, qos, retain, payload).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(prefix: &str, command_topic: Option<&str>) -> MqttArgs {
+        MqttArgs {
+            mqtt: Some("localhost:1883".to_string()),
+            mqtt_topic_prefix: prefix.to_string(),
+            mqtt_command_topic: command_topic.map(str::to_string),
+            mqtt_qos: 0,
+            mqtt_client_id: "test".to_string(),
+            mqtt_username: None,
+            mqtt_password: None,
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse_command(b"start"), Some(Command::Start)));
+        assert!(matches!(parse_command(b" STOP\n"), Some(Command::Stop)));
+        assert!(matches!(parse_command(b"Calibrate"), Some(Command::Calibrate)));
+        assert!(matches!(parse_command(b"acknowledge"), Some(Command::AcknowledgeAlarms)));
+
+        assert!(parse_command(b"").is_none());
+        assert!(parse_command(b"reboot").is_none());
+        assert!(parse_command(b"start now").is_none());
+        assert!(parse_command(&[0xff, 0xfe]).is_none());
+    }
+
+    #[test]
+    fn topics_under_prefix() {
+        let topics = Topics::new(&args("fabriek/lijn1/", None));
+        assert_eq!(topics.online, "fabriek/lijn1/online");
+        assert_eq!(topics.status, "fabriek/lijn1/status");
+        assert_eq!(topics.sensor(3), "fabriek/lijn1/sensors/3");
+        assert_eq!(topics.line, "fabriek/lijn1/line");
+        assert_eq!(topics.detections, "fabriek/lijn1/detections");
+        assert_eq!(topics.items, "fabriek/lijn1/items");
+        assert_eq!(topics.events, "fabriek/lijn1/events");
+        assert_eq!(topics.alarms, "fabriek/lijn1/alarms");
+        assert_eq!(topics.active_alarms, "fabriek/lijn1/alarms/active");
+        assert_eq!(topics.command, "fabriek/lijn1/command");
+
+        // Een eigen topic voor opdrachten valt niet onder de prefix
+        let topics = Topics::new(&args("metalstream", Some("plc/metalstream/cmd")));
+        assert_eq!(topics.command, "plc/metalstream/cmd");
+        assert_eq!(topics.sensor(8), "metalstream/sensors/8");
+    }
+}
@@ -0,0 +1,327 @@
+use crate::live::{LiveEvent, LiveHandle, Snapshot};
+use metalstream::processing::{AlarmKind, Detection};
+use metalstream::protocol::NUM_SENSORS;
+use opcua::server::prelude::*;
+use std::sync::Arc;
+
+#[derive(clap::Args)]
+pub struct OpcUaArgs {
+    /// Start een OPC UA server op deze poort, bijv. `4840`
+    #[arg(long)]
+    pub opcua: Option<u16>,
+    /// Hostnaam waarop de OPC UA server bereikbaar is
+    #[arg(long, default_value = "127.0.0.1")]
+    pub opcua_host: String,
+    /// Map voor de certificaten van de OPC UA server
+    #[arg(long, default_value = "pki")]
+    pub opcua_pki_dir: String,
+}
+
+const NAMESPACE_URI: &str = "urn:metalstream:desktop";
+
+// Hoe vaak de variabelen worden bijgewerkt vanuit de snapshot
+const POLLING_INTERVAL_MS: u64 = 250;
+// Detectie-events blijven zo lang in de address space staan; daarna worden ze
+// opgeruimd, anders groeit het geheugen zolang de server draait
+const EVENT_RETENTION_MINUTES: i64 = 10;
+const EVENT_PURGE_INTERVAL_MS: u64 = 60_000;
+
+// NodeIds van alle variabelen in het informatiemodel
+struct Nodes {
+    namespace: u16,
+    // Het Metalstream object: bron en ouder van de detectie-events
+    root: NodeId,
+    connected: NodeId,
+    port_path: NodeId,
+    running: NodeId,
+    speed: NodeId,
+    width: NodeId,
+    length: NodeId,
+    sensors: Vec<SensorNodes>,
+    detection_count: NodeId,
+    last_detection_start: NodeId,
+    last_detection_end: NodeId,
+    last_detection_peak_sensor: NodeId,
+    last_detection_peak_deviation: NodeId,
+    active_alarms: NodeId,
+    metal_detected: NodeId,
+}
+
+struct SensorNodes {
+    id: u8,
+    value: NodeId,
+    connected: NodeId,
+    health: NodeId,
+    calibrated: NodeId,
+}
+
+// Het informatiemodel onder Objects:
+//
+// Metalstream
+// ├── Hub (Connected, PortPath, Running)
+// ├── Line (Speed, Width, Length)
+// ├── Sensors
+// │   └── S01..S08 (Value, Connected, Health, Calibrated)
+// ├── Detections (Count, LastStart, LastEnd, LastPeakSensor, LastPeakDeviation)
+// └── Alarms (Active, MetalDetected)
+//
+// Detecties worden daarnaast als event op het Metalstream object gemeld en na
+// EVENT_RETENTION_MINUTES weer verwijderd.
+fn build_model(address_space: &mut AddressSpace) -> Nodes {
+    let ns = address_space.register_namespace(NAMESPACE_URI).unwrap();
+    let id = |path: &str| NodeId::new(ns, path.to_string());
+
+    let object = |address_space: &mut AddressSpace, path: &str, name: &str, parent: &NodeId| {
+        let node_id = id(path);
+        ObjectBuilder::new(&node_id, name, name)
+            .organized_by(parent.clone())
+            .event_notifier(EventNotifier::SUBSCRIBE_TO_EVENTS)
+            .insert(address_space);
+        node_id
+    };
+
+    fn variable(
+        address_space: &mut AddressSpace,
+        node_id: NodeId,
+        name: &str,
+        data_type: DataTypeId,
+        value: impl Into<Variant>,
+        parent: &NodeId,
+    ) -> NodeId {
+        VariableBuilder::new(&node_id, name, name)
+            .data_type(data_type)
+            .value(value)
+            .organized_by(parent.clone())
+            .insert(address_space);
+        node_id
+    }
+
+    let root = object(address_space, "Metalstream", "Metalstream", &NodeId::objects_folder_id());
+
+    let hub = object(address_space, "Metalstream.Hub", "Hub", &root);
+    let connected = variable(address_space, id("Metalstream.Hub.Connected"), "Connected", DataTypeId::Boolean, false, &hub);
+    let port_path = variable(address_space, id("Metalstream.Hub.PortPath"), "PortPath", DataTypeId::String, "", &hub);
+    let running = variable(address_space, id("Metalstream.Hub.Running"), "Running", DataTypeId::Boolean, false, &hub);
+
+    let line = object(address_space, "Metalstream.Line", "Line", &root);
+    let speed = variable(address_space, id("Metalstream.Line.Speed"), "Speed", DataTypeId::Double, 0.0, &line);
+    let width = variable(address_space, id("Metalstream.Line.Width"), "Width", DataTypeId::Double, 0.0, &line);
+    let length = variable(address_space, id("Metalstream.Line.Length"), "Length", DataTypeId::Double, 0.0, &line);
+
+    let sensors_folder = object(address_space, "Metalstream.Sensors", "Sensors", &root);
+    let sensors = (1..=NUM_SENSORS as u8)
+        .map(|sensor_id| {
+            let name = format!("S0{}", sensor_id);
+            let path = format!("Metalstream.Sensors.{}", name);
+            let sensor = object(address_space, &path, &name, &sensors_folder);
+            SensorNodes {
+                id: sensor_id,
+                value: variable(address_space, id(&format!("{}.Value", path)), "Value", DataTypeId::UInt16, 0u16, &sensor),
+                connected: variable(address_space, id(&format!("{}.Connected", path)), "Connected", DataTypeId::Boolean, false, &sensor),
+                health: variable(address_space, id(&format!("{}.Health", path)), "Health", DataTypeId::String, "no_data", &sensor),
+                calibrated: variable(address_space, id(&format!("{}.Calibrated", path)), "Calibrated", DataTypeId::Boolean, false, &sensor),
+            }
+        })
+        .collect();
+
+    let detections = object(address_space, "Metalstream.Detections", "Detections", &root);
+    let detection_count = variable(address_space, id("Metalstream.Detections.Count"), "Count", DataTypeId::UInt64, 0u64, &detections);
+    let last_detection_start = variable(address_space, id("Metalstream.Detections.LastStart"), "LastStart", DataTypeId::String, "", &detections);
+    let last_detection_end = variable(address_space, id("Metalstream.Detections.LastEnd"), "LastEnd", DataTypeId::String, "", &detections);
+    let last_detection_peak_sensor = variable(address_space, id("Metalstream.Detections.LastPeakSensor"), "LastPeakSensor", DataTypeId::Byte, 0u8, &detections);
+    let last_detection_peak_deviation = variable(address_space, id("Metalstream.Detections.LastPeakDeviation"), "LastPeakDeviation", DataTypeId::Double, 0.0, &detections);
+
+    let alarms = object(address_space, "Metalstream.Alarms", "Alarms", &root);
+    let active_alarms = variable(address_space, id("Metalstream.Alarms.Active"), "Active", DataTypeId::String, "[]", &alarms);
+    let metal_detected = variable(address_space, id("Metalstream.Alarms.MetalDetected"), "MetalDetected", DataTypeId::Boolean, false, &alarms);
+
+    Nodes {
+        namespace: ns,
+        root,
+        connected,
+        port_path,
+        running,
+        speed,
+        width,
+        length,
+        sensors,
+        detection_count,
+        last_detection_start,
+        last_detection_end,
+        last_detection_peak_sensor,
+        last_detection_peak_deviation,
+        active_alarms,
+        metal_detected,
+    }
+}
+
+fn update_model(address_space: &mut AddressSpace, nodes: &Nodes, snapshot: &Snapshot) {
+    let now = DateTime::now();
+    let mut set = |node_id: &NodeId, value: Variant| {
+        address_space.set_variable_value(node_id.clone(), value, &now, &now);
+    };
+
+    set(&nodes.connected, snapshot.connected.into());
+    set(&nodes.port_path, snapshot.port_path.clone().unwrap_or_default().into());
+    set(&nodes.running, snapshot.running.into());
+    set(&nodes.speed, snapshot.speed.into());
+    set(&nodes.width, snapshot.width.into());
+    set(&nodes.length, snapshot.length.into());
+
+    for sensor in &nodes.sensors {
+        let measurement = snapshot.sensor(sensor.id);
+        set(&sensor.value, measurement.map(|m| m.value).unwrap_or_default().into());
+        set(&sensor.connected, measurement.is_some_and(|m| m.connected).into());
+        set(&sensor.health, snapshot.sensor_health(sensor.id).as_str().into());
+        set(&sensor.calibrated, snapshot.is_calibrated(sensor.id).into());
+    }
+
+    set(&nodes.detection_count, snapshot.detections.into());
+    if let Some(detection) = &snapshot.last_detection {
+        set(&nodes.last_detection_start, detection.start_timestamp.clone().into());
+        set(&nodes.last_detection_end, detection.end_timestamp.clone().into());
+        set(&nodes.last_detection_peak_sensor, detection.peak_sensor.into());
+        set(&nodes.last_detection_peak_deviation, detection.peak_deviation.into());
+    }
+
+    set(&nodes.active_alarms, serde_json::to_string(&snapshot.alarms).unwrap_or_default().into());
+    set(
+        &nodes.metal_detected,
+        snapshot.alarms.iter().any(|alarm| alarm.kind == AlarmKind::MetalDetected).into(),
+    );
+}
+
+fn raise_detection(address_space: &mut AddressSpace, nodes: &Nodes, detection: &Detection, time: DateTime) -> Result<NodeId, ()> {
+    BaseEventType::new(
+        NodeId::next_numeric(nodes.namespace),
+        ObjectTypeId::BaseEventType,
+        "DetectionEvent",
+        "DetectionEvent",
+        nodes.root.clone(),
+        time,
+    )
+    .source_node(nodes.root.clone())
+    .source_name("Metalstream")
+    .severity(800)
+    .message(format!(
+        "Metaal gedetecteerd op {} (piek S0{}, sensoren {:?}, recept {})",
+        detection.start_timestamp,
+        detection.peak_sensor,
+        detection.sensors,
+        detection.recipe.as_deref().unwrap_or("standaard")
+    ))
+    .raise(address_space)
+}
+
+// Verwijder de detectie-events die ouder zijn dan EVENT_RETENTION_MINUTES
+fn purge_events(address_space: &mut AddressSpace, nodes: &Nodes, now: DateTimeUtc) -> usize {
+    let happened_before = now - chrono::Duration::minutes(EVENT_RETENTION_MINUTES);
+    opcua::server::events::event::purge_events(nodes.root.clone(), ObjectTypeId::BaseEventType, address_space, &happened_before)
+}
+
+pub fn start(args: &OpcUaArgs, live: LiveHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(port) = args.opcua else {
+        return Ok(());
+    };
+
+    let endpoint_url = format!("opc.tcp://{}:{}/", args.opcua_host, port);
+    let mut server = ServerBuilder::new_anonymous("Metalstream")
+        .application_uri(NAMESPACE_URI)
+        .product_uri(NAMESPACE_URI)
+        .host_and_port(args.opcua_host.clone(), port)
+        .discovery_urls(vec![endpoint_url.clone()])
+        .pki_dir(&args.opcua_pki_dir)
+        .create_sample_keypair(true)
+        .server()
+        .ok_or("ongeldige OPC UA configuratie")?;
+
+    let address_space = server.address_space();
+    let nodes = Arc::new(build_model(&mut address_space.write()));
+
+    // Variabelen periodiek bijwerken; abonnementen van clients pikken de wijzigingen op
+    {
+        let address_space = address_space.clone();
+        let nodes = nodes.clone();
+        let live = live.clone();
+        server.add_polling_action(POLLING_INTERVAL_MS, move || {
+            update_model(&mut address_space.write(), &nodes, &live.snapshot());
+        });
+    }
+
+    {
+        let address_space = address_space.clone();
+        let nodes = nodes.clone();
+        server.add_polling_action(EVENT_PURGE_INTERVAL_MS, move || {
+            let purged = purge_events(&mut address_space.write(), &nodes, chrono::Utc::now());
+            if purged > 0 {
+                re_log::debug!("OPC UA: {} oude detectie-events verwijderd", purged);
+            }
+        });
+    }
+
+    // Detecties als OPC UA events melden
+    let events = live.subscribe();
+    std::thread::spawn(move || {
+        for event in events {
+            let LiveEvent::Detection(detection) = event else {
+                continue;
+            };
+
+            let result = raise_detection(&mut address_space.write(), &nodes, &detection, DateTime::now());
+            if result.is_err() {
+                re_log::warn!("OPC UA: kan detectie-event niet melden");
+            }
+        }
+    });
+
+    re_log::info!("OPC UA server luistert op {}", endpoint_url);
+    std::thread::spawn(move || server.run());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opcua::server::events::event::events_for_object;
+
+    fn detection(start: &str) -> Detection {
+        Detection {
+            start_timestamp: start.to_string(),
+            end_timestamp: start.to_string(),
+            sensors: vec![2, 3],
+            peak_sensor: 2,
+            peak_deviation: 350.0,
+            samples: 4,
+            recipe: None,
+            reject_delay_ms: 0,
+            batch: None,
+            item: None,
+        }
+    }
+
+    #[test]
+    fn purges_old_detection_events() {
+        let mut address_space = AddressSpace::new();
+        let nodes = build_model(&mut address_space);
+        assert_eq!(nodes.sensors.len(), NUM_SENSORS);
+        assert!(address_space.find_node(&nodes.sensors[7].calibrated).is_some());
+        assert!(address_space.find_node(&nodes.metal_detected).is_some());
+
+        let now = chrono::Utc::now();
+        let old = now - chrono::Duration::minutes(EVENT_RETENTION_MINUTES + 1);
+        let recent = now - chrono::Duration::minutes(1);
+        let old_event = raise_detection(&mut address_space, &nodes, &detection("1000"), old.into()).unwrap();
+        let recent_event = raise_detection(&mut address_space, &nodes, &detection("2000"), recent.into()).unwrap();
+        let events = |address_space: &AddressSpace| events_for_object(nodes.root.clone(), address_space, &(old - chrono::Duration::minutes(1)));
+        assert_eq!(events(&address_space).map(|events| events.len()), Some(2));
+
+        assert_eq!(purge_events(&mut address_space, &nodes, now), 1);
+        assert!(address_space.find_node(&old_event).is_none());
+        assert!(address_space.find_node(&recent_event).is_some());
+        assert_eq!(events(&address_space), Some(vec![recent_event]));
+
+        // Niets meer om op te ruimen
+        assert_eq!(purge_events(&mut address_space, &nodes, now), 0);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt::Write;
+
+// Een minimale PDF writer voor rapporten: A4 pagina's met tekst, lijnen en vlakken.
+// Gebruikt alleen de standaard Helvetica fonts, zodat er niets ingebed hoeft te worden.
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(&self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+pub type Color = [u8; 3];
+
+// Geschatte breedte van tekst; Helvetica is gemiddeld iets breder dan een halve em
+pub fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * 0.52
+}
+
+// Tekst naar WinAnsi, met escapes voor een PDF string
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => bytes.extend([b'\\', c as u8]),
+            '\u{20}'..='\u{7E}' | '\u{A0}'..='\u{FF}' => bytes.push(c as u32 as u8),
+            '–' | '—' => bytes.push(0x96),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
+
+fn color_operator(color: Color, operator: &str) -> String {
+    format!(
+        "{:.3} {:.3} {:.3} {}",
+        color[0] as f32 / 255.0,
+        color[1] as f32 / 255.0,
+        color[2] as f32 / 255.0,
+        operator
+    )
+}
+
+// Coordinaten zijn in punten, met de oorsprong linksboven zoals in de GUI
+#[derive(Default)]
+pub struct Pdf {
+    pages: Vec<Vec<u8>>,
+}
+
+impl Pdf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_page(&mut self) {
+        self.pages.push(Vec::new());
+    }
+
+    fn content(&mut self) -> &mut Vec<u8> {
+        if self.pages.is_empty() {
+            self.add_page();
+        }
+        self.pages.last_mut().unwrap()
+    }
+
+    fn push(&mut self, operators: &str) {
+        let content = self.content();
+        content.extend(operators.as_bytes());
+        content.push(b'\n');
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, size: f32, font: Font, color: Color, text: &str) {
+        let mut operators = String::new();
+        write!(
+            operators,
+            "BT /{} {} Tf {} {:.2} {:.2} Td (",
+            font.resource(),
+            size,
+            color_operator(color, "rg"),
+            x,
+            PAGE_HEIGHT - y - size
+        )
+        .ok();
+        let content = self.content();
+        content.extend(operators.as_bytes());
+        content.extend(encode_text(text));
+        content.extend(b") Tj ET\n");
+    }
+
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        self.polyline(&[from, to], width, color);
+    }
+
+    pub fn polyline(&mut self, points: &[(f32, f32)], width: f32, color: Color) {
+        let Some((first, rest)) = points.split_first() else {
+            return;
+        };
+        let mut operators = format!("{} {} w {:.2} {:.2} m", color_operator(color, "RG"), width, first.0, PAGE_HEIGHT - first.1);
+        for (x, y) in rest {
+            write!(operators, " {:.2} {:.2} l", x, PAGE_HEIGHT - y).ok();
+        }
+        operators.push_str(" S");
+        self.push(&operators);
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.push(&format!(
+            "{} {:.2} {:.2} {:.2} {:.2} re f",
+            color_operator(color, "rg"),
+            x,
+            PAGE_HEIGHT - y - height,
+            width,
+            height
+        ));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let pages: Vec<&[u8]> = match self.pages.is_empty() {
+            true => vec![&[]],
+            false => self.pages.iter().map(Vec::as_slice).collect(),
+        };
+
+        // Objecten 1 t/m 4 liggen vast, daarna per pagina een pagina en een content stream
+        let page_ids: Vec<usize> = (0..pages.len()).map(|index| 5 + index * 2).collect();
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+                pages.len()
+            )
+            .into_bytes(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+        ];
+        for (page, id) in pages.iter().zip(&page_ids) {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    PAGE_WIDTH,
+                    PAGE_HEIGHT,
+                    id + 1
+                )
+                .into_bytes(),
+            );
+            let mut stream = format!("<< /Length {} >>\nstream\n", page.len()).into_bytes();
+            stream.extend(*page);
+            stream.extend(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut output = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(output.len());
+            output.extend(format!("{} 0 obj\n", index + 1).as_bytes());
+            output.extend(object);
+            output.extend(b"\nendobj\n");
+        }
+
+        let xref = output.len();
+        output.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            output.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        output.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_text_as_winansi() {
+        assert_eq!(encode_text("Lot (1)"), b"Lot \\(1\\)");
+        assert_eq!(encode_text("a\\b"), b"a\\\\b");
+        assert_eq!(encode_text("beëindigd"), b"be\xEBindigd");
+        assert_eq!(encode_text("1 – 2"), b"1 \x96 2");
+        // Tekens buiten WinAnsi worden een vraagteken
+        assert_eq!(encode_text("5 µm ✓"), b"5 \xB5m ?");
+    }
+
+    #[test]
+    fn xref_points_at_the_objects() {
+        let mut pdf = Pdf::new();
+        pdf.add_page();
+        pdf.text(50.0, 50.0, 12.0, Font::Bold, [0, 0, 0], "Batchrapport (test)");
+        pdf.add_page();
+        pdf.line((0.0, 0.0), (100.0, 100.0), 1.0, [255, 0, 0]);
+        let bytes = pdf.to_bytes();
+        let text = String::from_utf8_lossy(&bytes);
+
+        assert!(bytes.starts_with(b"%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(bytes[startxref..].starts_with(b"xref\n"));
+
+        // Vier vaste objecten en per pagina twee
+        let xref = std::str::from_utf8(&bytes[startxref..]).unwrap();
+        let mut lines = xref.lines().skip(1);
+        assert_eq!(lines.next(), Some("0 9"));
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        for id in 1..9 {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len(), 19);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(bytes[offset..].starts_with(format!("{} 0 obj\n", id).as_bytes()), "object {}", id);
+        }
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/Size 9"));
+    }
+}
@@ -0,0 +1,144 @@
+// Een minimale PNG writer voor snapshots: 8 bit RGB met tekst metadata.
+// De beelddata wordt niet gecomprimeerd (deflate "stored" blokken), zodat er geen
+// compressie library nodig is; de afbeeldingen zijn klein.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Maximale grootte van een ongecomprimeerd deflate blok
+const STORED_BLOCK: usize = 0xFFFF;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend((data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend(kind);
+    output.extend(data);
+    let crc = crc32(&output[start..]);
+    output.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        output.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        output.push(last as u8);
+        output.extend(length.to_le_bytes());
+        output.extend((!length).to_le_bytes());
+        output.extend(block);
+    }
+    output.extend(adler32(data).to_be_bytes());
+    output
+}
+
+// Tekst in een `tEXt` chunk is Latin-1
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '\u{20}'..='\u{7E}' | '\u{A0}'..='\u{FF}' => c as u32 as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+// `pixels` bevat `width * height` RGB waarden, rij voor rij vanaf linksboven
+pub fn encode(width: usize, height: usize, pixels: &[[u8; 3]], metadata: &[(&str, String)]) -> Vec<u8> {
+    let mut output = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // Bitdiepte 8, kleurtype 2 (RGB), standaard compressie, filter en geen interlace
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(&mut output, b"IHDR", &header);
+
+    for (keyword, text) in metadata {
+        let mut data = latin1(keyword);
+        data.push(0);
+        data.extend(latin1(text));
+        chunk(&mut output, b"tEXt", &data);
+    }
+
+    // Elke rij begint met filter type 0 (geen)
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width.max(1)).take(height) {
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+    chunk(&mut output, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn stored_blocks_split_at_65535_bytes() {
+        let data: Vec<u8> = (0..70_000u32).map(|index| index as u8).collect();
+        let zlib = zlib_stored(&data);
+        assert_eq!(zlib.len(), 2 + 5 + STORED_BLOCK + 5 + 4465 + 4);
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+
+        // Eerste blok vol en niet het laatste, dan de rest met BFINAL
+        assert_eq!(zlib[2..7], [0, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(zlib[7..7 + STORED_BLOCK], data[..STORED_BLOCK]);
+        let second = 7 + STORED_BLOCK;
+        assert_eq!(zlib[second..second + 5], [1, 0x71, 0x11, 0x8E, 0xEE]);
+        assert_eq!(zlib[second + 5..zlib.len() - 4], data[STORED_BLOCK..]);
+        assert_eq!(zlib[zlib.len() - 4..], adler32(&data).to_be_bytes());
+
+        // Precies een vol blok, en geen data
+        let full = zlib_stored(&data[..STORED_BLOCK]);
+        assert_eq!(full[2..7], [1, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(full.len(), 2 + 5 + STORED_BLOCK + 4);
+        assert_eq!(zlib_stored(&[]), [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn encodes_chunks() {
+        let png = encode(2, 1, &[[255, 0, 0], [0, 0, 255]], &[("Sensor", "S01 µ".to_string())]);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+
+        let text = b"tEXtSensor\0S01 \xB5";
+        assert!(png.windows(text.len()).any(|window| window == text));
+        assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+}
@@ -0,0 +1,501 @@
+use crate::live::Command;
+use crate::{GlobalState, RenderableTab};
+use metalstream::processing::{Detection, DetectorSettings, DspStage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+// Maximale relatieve afwijking van breedte en lengte om een recept voor te stellen
+const SUGGEST_TOLERANCE: f64 = 0.1;
+
+// Instellingen voor een product op de band
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    // Verwachte afmetingen volgens `MET`, in mm
+    pub width: f64,
+    pub length: f64,
+    // Tijd tussen detectie en het moment dat het product bij de uitwerper is
+    #[serde(default)]
+    pub reject_delay_ms: u64,
+    #[serde(default)]
+    pub detector: DetectorSettings,
+}
+
+impl Recipe {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            width: 0.0,
+            length: 0.0,
+            reject_delay_ms: 0,
+            detector: DetectorSettings::default(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let recipe: Recipe = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        recipe.validate()?;
+        Ok(recipe)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("een recept heeft een naam nodig".to_string());
+        }
+        if [self.width, self.length].iter().any(|size| !size.is_finite() || *size < 0.0) {
+            return Err("breedte en lengte mogen niet negatief zijn".to_string());
+        }
+        if self.detector.threshold.is_nan() || self.detector.threshold <= 0.0 {
+            return Err("de drempel moet groter dan 0 zijn".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.detector.baseline_alpha) {
+            return Err("de baseline factor moet tussen 0 en 1 liggen".to_string());
+        }
+        if !self.detector.sensors.contains(&true) {
+            return Err("er moet minstens een sensor meedoen".to_string());
+        }
+        Ok(())
+    }
+
+    // Bestandsnaam op basis van de naam, zonder tekens die niet in een pad horen
+    fn file_name(&self) -> String {
+        let stem: String = self
+            .name
+            .trim()
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        format!("{}.json", stem)
+    }
+
+    // Grootste relatieve afwijking van de gemeten afmetingen, als het recept die heeft
+    fn deviation(&self, width: f64, length: f64) -> Option<f64> {
+        if self.width <= 0.0 || self.length <= 0.0 {
+            return None;
+        }
+        Some(((width - self.width).abs() / self.width).max((length - self.length).abs() / self.length))
+    }
+
+    // Leg vast met welk recept een detectie is gedaan
+    pub fn tag(&self, detection: &mut Detection) {
+        detection.recipe = Some(self.name.clone());
+        detection.reject_delay_ms = self.reject_delay_ms;
+    }
+}
+
+// `$XDG_CONFIG_HOME/metalstream/recipes`, of `~/.config/metalstream/recipes` als die niet is gezet
+pub fn recipes_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("metalstream").join("recipes"))
+}
+
+// Alle recepten op schijf, een JSON bestand per recept, en het actieve recept
+#[derive(Default, Clone)]
+pub struct RecipeBook {
+    dir: Option<PathBuf>,
+    recipes: Vec<Recipe>,
+    active: Option<String>,
+}
+
+impl RecipeBook {
+    pub fn load() -> Self {
+        let dir = recipes_dir();
+        let mut recipes = Vec::new();
+
+        if let Some(entries) = dir.as_ref().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                match Recipe::load(&path) {
+                    Ok(recipe) => recipes.push(recipe),
+                    Err(err) => re_log::warn!("Recept {} overgeslagen: {}", path.display(), err),
+                }
+            }
+        }
+        recipes.sort_by(|a, b| a.name.cmp(&b.name));
+        re_log::info!("{} recept(en) geladen", recipes.len());
+
+        Self {
+            dir,
+            recipes,
+            active: None,
+        }
+    }
+
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.name == name)
+    }
+
+    pub fn active(&self) -> Option<&Recipe> {
+        self.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    // Maak een recept actief; geeft de instellingen voor de detector terug
+    pub fn select(&mut self, name: Option<&str>) -> DetectorSettings {
+        self.active = name.filter(|name| self.get(name).is_some()).map(str::to_string);
+        match self.active() {
+            Some(recipe) => {
+                re_log::info!("Recept {} actief", recipe.name);
+                recipe.detector.clone()
+            }
+            None => {
+                re_log::info!("Geen recept actief, standaard detectie-instellingen");
+                DetectorSettings::default()
+            }
+        }
+    }
+
+    // Het recept waarvan de afmetingen het best passen bij het gemeten product
+    pub fn suggest(&self, width: f64, length: f64) -> Option<&Recipe> {
+        if width <= 0.0 || length <= 0.0 {
+            return None;
+        }
+        self.recipes
+            .iter()
+            .filter_map(|recipe| Some((recipe, recipe.deviation(width, length)?)))
+            .filter(|(_, deviation)| *deviation <= SUGGEST_TOLERANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(recipe, _)| recipe)
+    }
+
+    // Sla een recept op; `previous` is de naam waaronder het eerder was opgeslagen
+    pub fn save(&mut self, recipe: Recipe, previous: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        recipe.validate()?;
+        let dir = self.dir.as_ref().ok_or("geen map voor recepten gevonden")?;
+        if previous != Some(recipe.name.as_str()) && self.get(&recipe.name).is_some() {
+            return Err(format!("er bestaat al een recept {}", recipe.name).into());
+        }
+        // Verschillende namen kunnen op hetzelfde bestand uitkomen ("Kaas 1" en
+        // "Kaas_1"); dan zou het ene recept het andere overschrijven
+        let file_name = recipe.file_name();
+        if let Some(other) = self
+            .recipes
+            .iter()
+            .find(|existing| Some(existing.name.as_str()) != previous && existing.file_name().eq_ignore_ascii_case(&file_name))
+        {
+            return Err(format!("de naam {} lijkt te veel op recept {}", recipe.name, other.name).into());
+        }
+
+        std::fs::create_dir_all(dir)?;
+        // Alleen de hoofdletters van de bestandsnaam veranderd: eerst het bestand zelf
+        // hernoemen, zodat er op elk bestandssysteem precies één overblijft
+        if let Some(old) = previous.and_then(|previous| self.get(previous)) {
+            let old_file = old.file_name();
+            if old_file != file_name && old_file.eq_ignore_ascii_case(&file_name) {
+                std::fs::rename(dir.join(&old_file), dir.join(&file_name))?;
+            }
+        }
+        std::fs::write(dir.join(&file_name), serde_json::to_string_pretty(&recipe)?)?;
+
+        // Hernoemd: het oude bestand opruimen, tenzij het (op hoofdletters na) hetzelfde is
+        if let Some(index) = self.recipes.iter().position(|existing| Some(existing.name.as_str()) == previous && existing.name != recipe.name) {
+            let old = self.recipes.remove(index);
+            if !old.file_name().eq_ignore_ascii_case(&file_name) {
+                std::fs::remove_file(dir.join(old.file_name())).ok();
+            }
+            if self.active.as_ref() == Some(&old.name) {
+                self.active = Some(recipe.name.clone());
+            }
+        }
+        match self.recipes.iter_mut().find(|existing| existing.name == recipe.name) {
+            Some(existing) => *existing = recipe,
+            None => {
+                self.recipes.push(recipe);
+                self.recipes.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(index) = self.recipes.iter().position(|recipe| recipe.name == name) else {
+            return Ok(());
+        };
+        if let Some(dir) = &self.dir {
+            let path = dir.join(self.recipes[index].file_name());
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        self.recipes.remove(index);
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        Ok(())
+    }
+}
+
+// Tab om recepten aan te maken, aan te passen en te verwijderen
+pub struct RecipesTab {
+    // Naam van het recept dat wordt bewerkt, `None` voor een nieuw recept
+    editing: Option<String>,
+    draft: Recipe,
+    status: Option<Result<String, String>>,
+    // Opslaan of verwijderen waarop de engine nog moet antwoorden
+    pending: Option<(Request, Receiver<Result<(), String>>)>,
+}
+
+enum Request {
+    Save(String),
+    Remove(String),
+}
+
+impl RecipesTab {
+    pub fn new() -> Self {
+        Self {
+            editing: None,
+            draft: Recipe::new("Nieuw recept"),
+            status: None,
+            pending: None,
+        }
+    }
+
+    fn poll(&mut self) {
+        let result = match self.pending.as_ref().map(|(_, reply)| reply.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => return,
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Disconnected)) => Err("engine gestopt".to_string()),
+        };
+        let Some((request, _)) = self.pending.take() else {
+            return;
+        };
+        self.status = Some(match (request, result) {
+            (Request::Save(name), Ok(())) => {
+                self.editing = Some(name.clone());
+                Ok(format!("Recept {} opgeslagen", name))
+            }
+            (Request::Remove(name), Ok(())) => {
+                self.editing = None;
+                Ok(format!("Recept {} verwijderd", name))
+            }
+            (Request::Save(_), Err(err)) => Err(format!("Opslaan mislukt: {}", err)),
+            (Request::Remove(_), Err(err)) => Err(format!("Verwijderen mislukt: {}", err)),
+        });
+    }
+
+    fn list_ui(&mut self, ui: &mut egui::Ui, state: &GlobalState) {
+        ui.vertical(|ui| {
+            ui.set_width(160.0);
+            if ui.button("Nieuw recept").clicked() {
+                self.editing = None;
+                self.draft = Recipe::new("Nieuw recept");
+                self.status = None;
+            }
+            ui.separator();
+
+            let active = state.engine.recipes.active().map(|recipe| recipe.name.clone());
+            for recipe in state.engine.recipes.recipes() {
+                let mut text = egui::RichText::new(&recipe.name);
+                if active.as_ref() == Some(&recipe.name) {
+                    text = text.strong();
+                }
+                if ui.selectable_label(self.editing.as_ref() == Some(&recipe.name), text).clicked() {
+                    self.editing = Some(recipe.name.clone());
+                    self.draft = recipe.clone();
+                    self.status = None;
+                }
+            }
+        });
+    }
+
+    fn dsp_ui(&mut self, ui: &mut egui::Ui) {
+        let mut removed = None;
+        for (index, stage) in self.draft.detector.dsp.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}.", index + 1));
+                match stage {
+                    DspStage::MovingAverage { window } => {
+                        ui.label("Gemiddelde over");
+                        ui.add(egui::DragValue::new(window).range(1..=100));
+                    }
+                    DspStage::Median { window } => {
+                        ui.label("Mediaan over");
+                        ui.add(egui::DragValue::new(window).range(1..=99));
+                    }
+                }
+                ui.label("samples");
+                if ui.small_button(egui_material_icons::icons::ICON_CLOSE).clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+        if let Some(index) = removed {
+            self.draft.detector.dsp.remove(index);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("+ Gemiddelde").clicked() {
+                self.draft.detector.dsp.push(DspStage::MovingAverage { window: 5 });
+            }
+            if ui.button("+ Mediaan").clicked() {
+                self.draft.detector.dsp.push(DspStage::Median { window: 5 });
+            }
+        });
+    }
+
+    fn editor_ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        ui.vertical(|ui| {
+            egui::Grid::new("recipe_editor").num_columns(2).show(ui, |ui| {
+                ui.label("Naam");
+                ui.text_edit_singleline(&mut self.draft.name);
+                ui.end_row();
+
+                ui.label("Breedte");
+                ui.add(egui::DragValue::new(&mut self.draft.width).range(0.0..=f64::MAX).suffix(" mm"));
+                ui.end_row();
+
+                ui.label("Lengte");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.draft.length).range(0.0..=f64::MAX).suffix(" mm"));
+                    let measured = state.engine.line.dimensions;
+                    if ui
+                        .add_enabled(measured.width > 0.0 && measured.length > 0.0, egui::Button::new("Gemeten overnemen"))
+                        .on_hover_text(format!("{} x {} mm", measured.width, measured.length))
+                        .clicked()
+                    {
+                        self.draft.width = measured.width;
+                        self.draft.length = measured.length;
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Drempel");
+                ui.add(egui::DragValue::new(&mut self.draft.detector.threshold).range(1.0..=f64::MAX));
+                ui.end_row();
+
+                ui.label("Baseline factor");
+                ui.add(egui::DragValue::new(&mut self.draft.detector.baseline_alpha).range(0.0..=1.0).speed(0.001));
+                ui.end_row();
+
+                ui.label("Opwarmen");
+                ui.add(egui::DragValue::new(&mut self.draft.detector.warmup_samples).suffix(" samples"));
+                ui.end_row();
+
+                ui.label("Uitwerpvertraging");
+                ui.add(egui::DragValue::new(&mut self.draft.reject_delay_ms).suffix(" ms"));
+                ui.end_row();
+
+                ui.label("Sensoren");
+                ui.horizontal_wrapped(|ui| {
+                    for (index, enabled) in self.draft.detector.sensors.iter_mut().enumerate() {
+                        ui.checkbox(enabled, format!("S0{}", index + 1));
+                    }
+                });
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.label("DSP keten");
+            self.dsp_ui(ui);
+            ui.separator();
+
+            let error = self.draft.validate().err();
+            let idle = self.pending.is_none();
+            ui.horizontal(|ui| {
+                // Een aangepast actief recept gaat de engine direct gebruiken
+                if ui.add_enabled(error.is_none() && idle, egui::Button::new("Opslaan")).clicked() {
+                    let recipe = self.draft.clone();
+                    let previous = self.editing.clone();
+                    let request = Request::Save(recipe.name.clone());
+                    let reply = state.live.request(|reply| Command::SaveRecipe { recipe, previous, reply });
+                    self.pending = Some((request, reply));
+                }
+
+                if let Some(name) = self.editing.clone() {
+                    if ui.add_enabled(idle, egui::Button::new("Verwijderen")).clicked() {
+                        let request = Request::Remove(name.clone());
+                        let reply = state.live.request(|reply| Command::RemoveRecipe { name, reply });
+                        self.pending = Some((request, reply));
+                    }
+                }
+            });
+
+            if let Some(error) = error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            match &self.status {
+                Some(Ok(text)) => {
+                    ui.label(text);
+                }
+                Some(Err(text)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, text);
+                }
+                None => {}
+            }
+        });
+    }
+}
+
+impl RenderableTab for RecipesTab {
+    fn title(&self) -> &str {
+        "Recepten"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.poll();
+        ui.horizontal_top(|ui| {
+            self.list_ui(ui, state);
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| self.editor_ui(ui, state));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(name: &str) -> RecipeBook {
+        let dir = std::env::temp_dir().join(format!("metalstream-recipes-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        RecipeBook {
+            dir: Some(dir),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_names_with_the_same_file() {
+        let mut book = book("collision");
+        book.save(Recipe::new("Kaas 1"), None).unwrap();
+        assert!(book.save(Recipe::new("Kaas_1"), None).is_err());
+        assert!(book.save(Recipe::new("kaas 1"), None).is_err());
+
+        // Hetzelfde recept opnieuw opslaan of hernoemen naar een vrije naam mag wel
+        book.save(Recipe::new("Kaas 1"), Some("Kaas 1")).unwrap();
+        book.save(Recipe::new("Kaas 2"), Some("Kaas 1")).unwrap();
+        book.save(Recipe::new("Kaas_1"), None).unwrap();
+        assert_eq!(book.recipes().len(), 2);
+        std::fs::remove_dir_all(book.dir.unwrap()).ok();
+    }
+
+    #[test]
+    fn rename_keeps_the_file_when_only_the_case_changes() {
+        let mut book = book("case");
+        book.save(Recipe::new("kaas"), None).unwrap();
+        book.save(Recipe::new("Kaas"), Some("kaas")).unwrap();
+        assert_eq!(book.recipes().len(), 1);
+
+        // Het bestand moet blijven bestaan, ook als het bestandssysteem geen onderscheid maakt
+        let dir = book.dir.clone().unwrap();
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files.len(), 1);
+        let reloaded = Recipe::load(&dir.join(&files[0])).unwrap();
+        assert_eq!(reloaded.name, "Kaas");
+
+        // Een echte hernoeming ruimt het oude bestand wel op
+        book.save(Recipe::new("Brood"), Some("Kaas")).unwrap();
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files, vec![std::ffi::OsString::from("Brood.json")]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,132 @@
+use crate::batch::BatchInfo;
+use crate::items::{Item, ItemTracker};
+use crate::recipes::Recipe;
+use metalstream::processing::{Detection, Detector, LineState};
+use metalstream::protocol::{DecodeError, Message};
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+
+pub use metalstream::recording::{RecordedMessage, Recorder};
+
+// Opnames van deze applicatie, met het recept en de batch als eigen types
+pub type RecordingReader = metalstream::recording::RecordingReader<Recipe, BatchInfo>;
+
+// Wat een opname bij het opnieuw doorrekenen oplevert. Tijden zijn milliseconden
+// sinds het begin van de opname.
+pub enum ReplayEvent {
+    Message {
+        elapsed: u64,
+        recorded: RecordedMessage,
+        decoded: Result<Message, DecodeError>,
+    },
+    // Met het recept van dat moment gemarkeerd en aan het product gekoppeld
+    Detection { elapsed: u64, detection: Detection },
+    Item(Item),
+}
+
+// Rekent een opname door zoals de engine dat live doet: berichten decoderen, metaal
+// detecteren met het recept uit de opname en producten volgen. Een product dat al
+// klaar was voor een bericht komt voor dat bericht; een detectie die met een meting
+// afloopt komt erna.
+pub struct Replay {
+    reader: RecordingReader,
+    line: LineState,
+    detector: Detector,
+    recipe: Option<Recipe>,
+    items: ItemTracker,
+    pending: VecDeque<ReplayEvent>,
+    latest: u64,
+    finished: bool,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            reader: RecordingReader::open(path)?,
+            line: LineState::default(),
+            detector: Detector::default(),
+            recipe: None,
+            items: ItemTracker::default(),
+            pending: VecDeque::new(),
+            latest: 0,
+            finished: false,
+        })
+    }
+
+    // Het recept dat actief was bij het laatst gelezen bericht
+    pub fn recipe(&self) -> Option<&Recipe> {
+        self.recipe.as_ref()
+    }
+
+    pub fn detector(&self) -> &Detector {
+        &self.detector
+    }
+
+    // Het product waar de laatste `MET` bij hoort
+    pub fn current_item(&self) -> Option<&Item> {
+        self.items.current()
+    }
+
+    fn detection(&mut self, elapsed: u64, mut detection: Detection) {
+        if let Some(recipe) = &self.recipe {
+            recipe.tag(&mut detection);
+        }
+        self.items.on_detection(&mut detection);
+        self.pending.push_back(ReplayEvent::Detection { elapsed, detection });
+    }
+
+    fn process(&mut self, recorded: RecordedMessage) {
+        let elapsed = recorded.elapsed.as_millis() as u64;
+        self.latest = elapsed;
+        if self.reader.recipe != self.recipe {
+            self.recipe = self.reader.recipe.clone();
+            self.detector.set_settings(self.recipe.as_ref().map(|recipe| recipe.detector.clone()).unwrap_or_default());
+        }
+        self.pending.extend(self.items.poll(elapsed).map(ReplayEvent::Item));
+
+        let decoded = self.line.apply(&recorded.message);
+        let detection = match &decoded {
+            Ok(Message::Sms(measurement)) => self.detector.process(&recorded.message.timestamp, measurement),
+            Ok(Message::Met(metrics)) => {
+                let finished = self.items.on_met(elapsed, &recorded.message.timestamp, metrics);
+                self.pending.extend(finished.map(ReplayEvent::Item));
+                None
+            }
+            _ => None,
+        };
+        self.pending.push_back(ReplayEvent::Message { elapsed, recorded, decoded });
+        if let Some(detection) = detection {
+            self.detection(elapsed, detection);
+        }
+    }
+
+    // Een lopende detectie en de open producten aan het einde van de opname afsluiten
+    fn finish(&mut self) {
+        self.finished = true;
+        if let Some(detection) = self.detector.finish() {
+            self.detection(self.latest, detection);
+        }
+        self.pending.extend(self.items.finish().into_iter().map(ReplayEvent::Item));
+    }
+}
+
+impl Iterator for Replay {
+    type Item = io::Result<ReplayEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.reader.next() {
+                Some(Ok(recorded)) => self.process(recorded),
+                Some(Err(err)) => return Some(Err(err)),
+                None => self.finish(),
+            }
+        }
+    }
+}
@@ -0,0 +1,948 @@
+use crate::batch::{format_time, BatchCounts, BatchSummary};
+use crate::pdf::{text_width, Color, Font, Pdf, PAGE_HEIGHT, PAGE_WIDTH};
+use crate::recording::{Replay, ReplayEvent};
+use crate::snapshots::{cell_color, DetectionSnapshot, SnapshotWindow};
+use crate::{GlobalState, RenderableTab};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use metalstream::processing::{unix_millis, Detection};
+use metalstream::protocol::{EventSeverity, HubEventKind, Measurement, Message, NUM_SENSORS};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+
+// Aantal punten in de trendgrafiek van een sensor
+const TREND_POINTS: usize = 200;
+// Meer detecties worden geteld, maar niet elk met een heatmap getoond
+const MAX_SNAPSHOTS: usize = 50;
+const MAX_TIMELINE: usize = 500;
+
+// Kleuren van de sensoren in grafieken, S01 eerst
+const SENSOR_COLORS: [Color; NUM_SENSORS] = [
+    [31, 119, 180],
+    [255, 127, 14],
+    [44, 160, 44],
+    [214, 39, 40],
+    [148, 103, 189],
+    [140, 86, 75],
+    [227, 119, 194],
+    [23, 190, 207],
+];
+
+// Waar het rapport over gaat: een batch of alle batches in een periode
+#[derive(Debug, Clone)]
+pub enum ReportSource {
+    Batch(String),
+    Range { from: u64, to: u64 },
+}
+
+// Tijd als `2025-01-31`, `2025-01-31 14:00` of `2025-01-31 14:00:30`, in lokale tijd
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .ok_or_else(|| format!("ongeldige tijd {:?}, gebruik JJJJ-MM-DD UU:MM", text))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.timestamp_millis() as u64)
+        .ok_or_else(|| format!("tijd {:?} bestaat niet in de lokale tijdzone", text))
+}
+
+pub struct ReportHeader {
+    pub title: String,
+    pub lots: Vec<String>,
+    pub lines: Vec<String>,
+    pub hubs: Vec<String>,
+    pub firmware: Vec<String>,
+    pub operators: Vec<String>,
+    pub recipes: Vec<String>,
+    pub from: u64,
+    pub to: u64,
+    pub generated: u64,
+}
+
+#[derive(Default)]
+pub struct SensorReport {
+    pub samples: u64,
+    pub disconnected_samples: u64,
+    pub disconnects: u64,
+    pub faults: u64,
+    sum: f64,
+    pub min: Option<u16>,
+    pub max: Option<u16>,
+    pub calibrated: bool,
+    // Uitgeschakeld in het recept, dus niet gebruikt voor de detectie
+    pub masked: bool,
+}
+
+impl SensorReport {
+    pub fn mean(&self) -> Option<f64> {
+        let connected = self.samples - self.disconnected_samples;
+        (connected > 0).then(|| self.sum / connected as f64)
+    }
+
+    pub fn availability(&self) -> Option<f64> {
+        (self.samples > 0).then(|| 1.0 - self.disconnected_samples as f64 / self.samples as f64)
+    }
+
+    pub fn status(&self) -> &'static str {
+        match self.availability() {
+            None => "geen data",
+            Some(_) if self.faults > 0 => "fouten gemeld",
+            Some(availability) if availability < 0.99 => "onderbroken",
+            Some(_) if self.masked => "niet gebruikt",
+            Some(_) if !self.calibrated => "niet gekalibreerd",
+            Some(_) => "in orde",
+        }
+    }
+}
+
+pub struct TimelineEntry {
+    pub time: u64,
+    pub severity: EventSeverity,
+    pub text: String,
+}
+
+// Gemiddelde waarde per tijdvak, per sensor
+struct Trend {
+    from: u64,
+    bucket: u64,
+    sums: Vec<[(f64, u32); NUM_SENSORS]>,
+}
+
+impl Trend {
+    fn new(from: u64, to: u64) -> Self {
+        Self {
+            from,
+            bucket: (to.saturating_sub(from) / TREND_POINTS as u64).max(1),
+            sums: vec![[(0.0, 0); NUM_SENSORS]; TREND_POINTS],
+        }
+    }
+
+    fn add(&mut self, time: u64, index: usize, value: u16) {
+        let bucket = ((time.saturating_sub(self.from) / self.bucket) as usize).min(TREND_POINTS - 1);
+        let (sum, count) = &mut self.sums[bucket][index];
+        *sum += value as f64;
+        *count += 1;
+    }
+
+    // Punten (tijd, gemiddelde) van een sensor
+    fn points(&self, index: usize) -> Vec<(u64, f64)> {
+        self.sums
+            .iter()
+            .enumerate()
+            .filter(|(_, sums)| sums[index].1 > 0)
+            .map(|(bucket, sums)| (self.from + bucket as u64 * self.bucket, sums[index].0 / sums[index].1 as f64))
+            .collect()
+    }
+}
+
+pub struct Report {
+    pub header: ReportHeader,
+    pub counts: BatchCounts,
+    pub sensors: [SensorReport; NUM_SENSORS],
+    pub timeline: Vec<TimelineEntry>,
+    pub snapshots: Vec<DetectionSnapshot>,
+    pub trends: Vec<Vec<(u64, f64)>>,
+    // Opmerkingen over ontbrekende of ingekorte gegevens
+    pub notes: Vec<String>,
+}
+
+fn distinct(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut distinct: Vec<String> = Vec::new();
+    for value in values.filter(|value| !value.is_empty()) {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
+    }
+    distinct
+}
+
+// Een batch opnieuw doorrekenen voor het rapport
+struct BatchReplay<'a> {
+    report: &'a mut Report,
+    trend: &'a mut Trend,
+    from: u64,
+    to: u64,
+    snapshots: SnapshotWindow,
+    connected: [Option<bool>; NUM_SENSORS],
+}
+
+impl BatchReplay<'_> {
+    fn timeline(&mut self, time: u64, severity: EventSeverity, text: String) {
+        if self.report.timeline.len() < MAX_TIMELINE {
+            self.report.timeline.push(TimelineEntry { time, severity, text });
+        }
+    }
+
+    fn detection(&mut self, time: u64, detection: Detection, threshold: f64) {
+        self.timeline(
+            time,
+            EventSeverity::Warning,
+            format!("Metaal gedetecteerd, piek S0{} ({:.0})", detection.peak_sensor, detection.peak_deviation),
+        );
+        if self.report.snapshots.len() < MAX_SNAPSHOTS {
+            self.report.snapshots.push(self.snapshots.capture(&detection, threshold, time));
+        }
+    }
+
+    fn measurement(&mut self, time: u64, measurement: Measurement) {
+        let index = measurement.id as usize - 1;
+        let sensor = &mut self.report.sensors[index];
+        sensor.samples += 1;
+        if measurement.connected {
+            sensor.sum += measurement.value as f64;
+            sensor.min = Some(sensor.min.map_or(measurement.value, |min| min.min(measurement.value)));
+            sensor.max = Some(sensor.max.map_or(measurement.value, |max| max.max(measurement.value)));
+            self.trend.add(time, index, measurement.value);
+        } else {
+            sensor.disconnected_samples += 1;
+        }
+
+        // Overgangen tussen verbonden en niet verbonden horen in de tijdlijn
+        if self.connected[index] == Some(!measurement.connected) {
+            let text = match measurement.connected {
+                true => format!("Sensor S0{} weer verbonden", measurement.id),
+                false => {
+                    self.report.sensors[index].disconnects += 1;
+                    format!("Sensor S0{} niet verbonden", measurement.id)
+                }
+            };
+            let severity = if measurement.connected { EventSeverity::Info } else { EventSeverity::Warning };
+            self.timeline(time, severity, text);
+        }
+        self.connected[index] = Some(measurement.connected);
+    }
+
+    fn run(&mut self, summary: &BatchSummary, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut replay = Replay::open(path)?;
+
+        while let Some(event) = replay.next() {
+            match event? {
+                ReplayEvent::Message { elapsed, recorded, decoded } => {
+                    if let Ok(Message::Sms(measurement)) = &decoded {
+                        self.snapshots.add(&recorded.message.timestamp, measurement, replay.detector());
+                    }
+                    let time = summary.info.started_at + elapsed;
+                    if time < self.from || time > self.to {
+                        continue;
+                    }
+                    match decoded {
+                        Ok(Message::Sms(measurement)) => self.measurement(time, measurement),
+                        Ok(Message::Error(event) | Message::Event(event)) => {
+                            if let (Some(sensor), HubEventKind::SensorFault) = (event.sensor, &event.kind) {
+                                self.report.sensors[sensor as usize - 1].faults += 1;
+                            }
+                            self.timeline(time, event.severity, event.description());
+                        }
+                        Ok(_) | Err(_) => {}
+                    }
+                }
+                ReplayEvent::Detection { elapsed, mut detection } => {
+                    let time = summary.info.started_at + elapsed;
+                    if (self.from..=self.to).contains(&time) {
+                        detection.batch = Some(summary.info.lot.clone());
+                        self.detection(time, detection, replay.detector().threshold());
+                    }
+                }
+                ReplayEvent::Item(_) => {}
+            }
+        }
+
+        for (index, sensor) in self.report.sensors.iter_mut().enumerate() {
+            if sensor.samples > 0 {
+                sensor.calibrated = replay.detector().is_calibrated(index as u8 + 1);
+                sensor.masked = replay.recipe().is_some_and(|recipe| !recipe.detector.sensors[index]);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Report {
+    // Reken de opnames van de gekozen batches opnieuw door
+    pub fn build(history: &[BatchSummary], source: &ReportSource) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut batches: Vec<&BatchSummary> = match source {
+            ReportSource::Batch(lot) => {
+                let summary = history
+                    .iter()
+                    .find(|summary| &summary.info.lot == lot)
+                    .ok_or_else(|| format!("geen afgeronde batch {} gevonden", lot))?;
+                vec![summary]
+            }
+            ReportSource::Range { from, to } => {
+                if from >= to {
+                    return Err("het begin van de periode moet voor het einde liggen".into());
+                }
+                history
+                    .iter()
+                    .filter(|summary| summary.info.started_at < *to && summary.ended_at > *from)
+                    .collect()
+            }
+        };
+        if batches.is_empty() {
+            return Err("geen afgeronde batches in deze periode".into());
+        }
+        batches.sort_by_key(|summary| summary.info.started_at);
+
+        let (from, to) = match source {
+            ReportSource::Batch(_) => (batches[0].info.started_at, batches[0].ended_at),
+            ReportSource::Range { from, to } => (*from, *to),
+        };
+        let title = match source {
+            ReportSource::Batch(lot) => format!("Batchrapport {}", lot),
+            ReportSource::Range { .. } => format!("Ploegrapport {} – {}", format_time(from), format_time(to)),
+        };
+
+        let mut report = Report {
+            header: ReportHeader {
+                title,
+                lots: distinct(batches.iter().map(|summary| summary.info.lot.clone())),
+                lines: distinct(batches.iter().map(|summary| summary.info.line.clone())),
+                hubs: distinct(batches.iter().filter_map(|summary| summary.info.hub.clone())),
+                firmware: distinct(
+                    batches
+                        .iter()
+                        .map(|summary| summary.info.firmware.clone().unwrap_or_else(|| "onbekend".to_string())),
+                ),
+                operators: distinct(batches.iter().map(|summary| summary.info.operator.clone())),
+                recipes: distinct(batches.iter().filter_map(|summary| summary.info.recipe.clone())),
+                from,
+                to,
+                generated: unix_millis(),
+            },
+            counts: BatchCounts::default(),
+            sensors: Default::default(),
+            timeline: Vec::new(),
+            snapshots: Vec::new(),
+            trends: Vec::new(),
+            notes: Vec::new(),
+        };
+        let mut trend = Trend::new(from, to);
+
+        for summary in &batches {
+            // De tellers komen uit de samenvatting, zoals ze tijdens de batch live zijn
+            // bijgehouden; de opname levert alleen de details
+            report.counts.add(&summary.counts);
+            if summary.info.started_at < from || summary.ended_at > to {
+                report.notes.push(format!(
+                    "Batch {} valt deels buiten de periode; de tellers gelden voor de hele batch",
+                    summary.info.lot
+                ));
+            }
+
+            let Some(path) = &summary.recording else {
+                report.notes.push(format!("Batch {} heeft geen opname", summary.info.lot));
+                continue;
+            };
+            let mut replay = BatchReplay {
+                report: &mut report,
+                trend: &mut trend,
+                from,
+                to,
+                snapshots: SnapshotWindow::default(),
+                connected: [None; NUM_SENSORS],
+            };
+            if let Err(err) = replay.run(summary, path) {
+                report.notes.push(format!("Opname van batch {} niet volledig gelezen: {}", summary.info.lot, err));
+            }
+        }
+
+        if report.snapshots.len() == MAX_SNAPSHOTS {
+            report.notes.push(format!("Alleen de eerste {} detecties zijn met heatmap opgenomen", MAX_SNAPSHOTS));
+        }
+        if report.timeline.len() == MAX_TIMELINE {
+            report.notes.push(format!("De tijdlijn is ingekort tot {} regels", MAX_TIMELINE));
+        }
+        report.trends = (0..NUM_SENSORS).map(|index| trend.points(index)).collect();
+        report.timeline.sort_by_key(|entry| entry.time);
+        Ok(report)
+    }
+
+    fn header_rows(&self) -> Vec<(&'static str, String)> {
+        let join = |values: &[String]| if values.is_empty() { "–".to_string() } else { values.join(", ") };
+        vec![
+            ("Lijn", join(&self.header.lines)),
+            ("Hub", join(&self.header.hubs)),
+            ("Firmware", join(&self.header.firmware)),
+            ("Operator", join(&self.header.operators)),
+            ("Lot", join(&self.header.lots)),
+            ("Recept", join(&self.header.recipes)),
+            ("Periode", format!("{} – {}", format_time(self.header.from), format_time(self.header.to))),
+            ("Gemaakt", format_time(self.header.generated)),
+        ]
+    }
+
+    fn count_rows(&self) -> Vec<(&'static str, String)> {
+        let counts = &self.counts;
+        vec![
+            ("Producten goed", counts.items_passed.to_string()),
+            ("Afgekeurd", counts.rejects.to_string()),
+            ("Detecties", counts.detections.to_string()),
+            ("Alarmen", counts.alarms.to_string()),
+            ("Metingen", counts.measurements.to_string()),
+            ("Stilstand", format!("{:.1} min", counts.downtime_ms as f64 / 60_000.0)),
+            ("Verbinding verloren", counts.connection_losses.to_string()),
+        ]
+    }
+
+    fn sensor_rows(&self) -> Vec<[String; 8]> {
+        self.sensors
+            .iter()
+            .enumerate()
+            .map(|(index, sensor)| {
+                [
+                    format!("S0{}", index + 1),
+                    sensor.status().to_string(),
+                    sensor.availability().map_or("–".to_string(), |availability| format!("{:.1}%", availability * 100.0)),
+                    sensor.mean().map_or("–".to_string(), |mean| format!("{:.0}", mean)),
+                    match (sensor.min, sensor.max) {
+                        (Some(min), Some(max)) => format!("{} – {}", min, max),
+                        _ => "–".to_string(),
+                    },
+                    sensor.disconnects.to_string(),
+                    sensor.faults.to_string(),
+                    if sensor.calibrated { "ja" } else { "nee" }.to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    // Een zelfstandige HTML pagina, zonder externe bestanden of scripts
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"nl\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(&self.header.title),
+            HTML_STYLE,
+            escape(&self.header.title)
+        )
+        .ok();
+
+        html.push_str("<table class=\"info\">\n");
+        for (label, value) in self.header_rows() {
+            writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value)).ok();
+        }
+        html.push_str("</table>\n<h2>Resultaat</h2>\n<table class=\"info\">\n");
+        for (label, value) in self.count_rows() {
+            writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, value).ok();
+        }
+        html.push_str("</table>\n");
+
+        for note in &self.notes {
+            writeln!(html, "<p class=\"note\">{}</p>", escape(note)).ok();
+        }
+
+        html.push_str("<h2>Sensoren</h2>\n<table>\n<tr>");
+        for title in SENSOR_COLUMNS {
+            write!(html, "<th>{}</th>", title).ok();
+        }
+        html.push_str("</tr>\n");
+        for row in self.sensor_rows() {
+            html.push_str("<tr>");
+            for cell in row {
+                write!(html, "<td>{}</td>", escape(&cell)).ok();
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n<h2>Trends</h2>\n<div class=\"charts\">\n");
+        for (index, points) in self.trends.iter().enumerate() {
+            writeln!(html, "<figure><figcaption>S0{}</figcaption>{}</figure>", index + 1, self.trend_svg(points, SENSOR_COLORS[index])).ok();
+        }
+        html.push_str("</div>\n");
+
+        writeln!(html, "<h2>Detecties ({})</h2>", self.counts.detections).ok();
+        html.push_str("<div class=\"charts\">\n");
+        for snapshot in &self.snapshots {
+            writeln!(
+                html,
+                "<figure><figcaption>{}</figcaption><img src=\"data:image/png;base64,{}\" alt=\"heatmap\"></figure>",
+                escape(&snapshot.caption()),
+                base64(&snapshot.to_png())
+            )
+            .ok();
+        }
+        html.push_str("</div>\n<h2>Alarmen en meldingen</h2>\n<table>\n<tr><th>Tijd</th><th>Ernst</th><th>Melding</th></tr>\n");
+        for entry in &self.timeline {
+            writeln!(
+                html,
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td></tr>",
+                severity_class(entry.severity),
+                format_time(entry.time),
+                entry.severity.label(),
+                escape(&entry.text)
+            )
+            .ok();
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    fn trend_svg(&self, points: &[(u64, f64)], color: Color) -> String {
+        let (width, height) = (TREND_WIDTH, TREND_HEIGHT);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\"><rect width=\"100%\" height=\"100%\" fill=\"#fafafa\" stroke=\"#ccc\"/>",
+            width, height, width, height
+        );
+        let scaled = scale_trend(points, self.header.from, self.header.to, width, height);
+        if scaled.is_empty() {
+            svg.push_str("<text x=\"8\" y=\"20\" font-size=\"11\" fill=\"#888\">geen data</text>");
+        } else {
+            let coordinates: Vec<String> = scaled.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+            write!(
+                svg,
+                "<polyline fill=\"none\" stroke=\"rgb({},{},{})\" stroke-width=\"1.5\" points=\"{}\"/>",
+                color[0],
+                color[1],
+                color[2],
+                coordinates.join(" ")
+            )
+            .ok();
+            let (min, max) = value_range(points);
+            write!(
+                svg,
+                "<text x=\"4\" y=\"11\" font-size=\"9\" fill=\"#888\">{:.0}</text><text x=\"4\" y=\"{}\" font-size=\"9\" fill=\"#888\">{:.0}</text>",
+                max,
+                height - 3.0,
+                min
+            )
+            .ok();
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let mut layout = PdfLayout::new();
+        layout.heading(&self.header.title, 18.0);
+        layout.key_values(&self.header_rows());
+        layout.heading("Resultaat", 13.0);
+        layout.key_values(&self.count_rows());
+        for note in &self.notes {
+            layout.paragraph(note, [160, 90, 0]);
+        }
+
+        layout.heading("Sensoren", 13.0);
+        let widths = [40.0, 95.0, 60.0, 55.0, 80.0, 55.0, 45.0, 55.0];
+        layout.table_row(&SENSOR_COLUMNS.map(str::to_string), &widths, Font::Bold);
+        for row in self.sensor_rows() {
+            layout.table_row(&row, &widths, Font::Regular);
+        }
+
+        layout.heading("Trends", 13.0);
+        for (index, points) in self.trends.iter().enumerate() {
+            let (width, height) = (TREND_WIDTH * 0.6, TREND_HEIGHT * 0.6);
+            let column = index % 2;
+            if column == 0 {
+                layout.ensure(height + 16.0);
+            }
+            let x = MARGIN + column as f32 * (width + 20.0);
+            let y = layout.y;
+            layout.pdf.text(x, y, 9.0, Font::Bold, BLACK, &format!("S0{}", index + 1));
+            let top = y + 12.0;
+            layout.pdf.rect(x, top, width, height, [245, 245, 245]);
+            let scaled = scale_trend(points, self.header.from, self.header.to, width, height);
+            let shifted: Vec<(f32, f32)> = scaled.iter().map(|(px, py)| (x + px, top + py)).collect();
+            layout.pdf.polyline(&shifted, 1.0, SENSOR_COLORS[index]);
+            if column == 1 || index == self.trends.len() - 1 {
+                layout.y += height + 20.0;
+            }
+        }
+
+        layout.heading(&format!("Detecties ({})", self.counts.detections), 13.0);
+        for snapshot in &self.snapshots {
+            let cell = 6.0;
+            layout.ensure(14.0 + cell * NUM_SENSORS as f32 + 10.0);
+            let y = layout.y;
+            layout.pdf.text(MARGIN, y, 9.0, Font::Regular, BLACK, &snapshot.caption());
+            for (row, intensities) in snapshot.intensities().iter().enumerate() {
+                let top = y + 14.0 + row as f32 * cell;
+                layout.pdf.text(MARGIN, top - 1.0, 5.0, Font::Regular, GREY, &format!("S0{}", row + 1));
+                for (column, intensity) in intensities.iter().enumerate() {
+                    layout.pdf.rect(MARGIN + 18.0 + column as f32 * cell, top, cell, cell, cell_color(*intensity));
+                }
+            }
+            layout.y = y + 14.0 + cell * NUM_SENSORS as f32 + 10.0;
+        }
+
+        layout.heading("Alarmen en meldingen", 13.0);
+        let widths = [110.0, 70.0, 310.0];
+        layout.table_row(&["Tijd".to_string(), "Ernst".to_string(), "Melding".to_string()], &widths, Font::Bold);
+        for entry in &self.timeline {
+            layout.table_row(
+                &[format_time(entry.time), entry.severity.label().to_string(), entry.text.clone()],
+                &widths,
+                Font::Regular,
+            );
+        }
+
+        layout.pdf.to_bytes()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("pdf") => std::fs::write(path, self.to_pdf()),
+            _ => std::fs::write(path, self.to_html()),
+        }
+    }
+}
+
+const SENSOR_COLUMNS: [&str; 8] = ["Sensor", "Status", "Beschikbaar", "Gemiddeld", "Bereik", "Onderbroken", "Fouten", "Gekalibreerd"];
+
+const TREND_WIDTH: f32 = 360.0;
+const TREND_HEIGHT: f32 = 90.0;
+
+fn value_range(points: &[(u64, f64)]) -> (f64, f64) {
+    let min = points.iter().map(|(_, value)| *value).fold(f64::INFINITY, f64::min);
+    let max = points.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+    (min, max)
+}
+
+// Punten van een trend geschaald naar een vlak van `width` x `height`, y naar beneden
+fn scale_trend(points: &[(u64, f64)], from: u64, to: u64, width: f32, height: f32) -> Vec<(f32, f32)> {
+    let (min, max) = value_range(points);
+    let span = (max - min).max(1.0);
+    let duration = to.saturating_sub(from).max(1) as f64;
+
+    points
+        .iter()
+        .map(|(time, value)| {
+            let x = (time.saturating_sub(from) as f64 / duration) as f32 * width;
+            let y = height - 4.0 - ((value - min) / span) as f32 * (height - 8.0);
+            (x, y)
+        })
+        .collect()
+}
+
+fn severity_class(severity: EventSeverity) -> &'static str {
+    match severity {
+        EventSeverity::Info => "info",
+        EventSeverity::Warning => "warning",
+        EventSeverity::Error => "error",
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, byte)| value | (*byte as u32) << (16 - index * 8));
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => output.push(ALPHABET[(value >> (18 - index * 6)) as usize & 0x3F] as char),
+                false => output.push('='),
+            }
+        }
+    }
+    output
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{font-size:1.6em}h2{font-size:1.2em;margin-top:1.5em;border-bottom:1px solid #ccc}\
+table{border-collapse:collapse;font-size:.9em}th,td{padding:3px 10px;text-align:left;border-bottom:1px solid #eee}\
+table.info th{color:#555;font-weight:normal}.note{color:#a05a00}\
+.charts{display:flex;flex-wrap:wrap;gap:12px}figure{margin:0}figcaption{font-size:.8em;color:#555}\
+tr.warning td{color:#a05a00}tr.error td{color:#c00}\
+@media print{h2{break-after:avoid}figure{break-inside:avoid}}";
+
+const MARGIN: f32 = 40.0;
+const BLACK: Color = [0, 0, 0];
+const GREY: Color = [100, 100, 100];
+
+// Schrijft van boven naar beneden en begint een nieuwe pagina als die vol is
+struct PdfLayout {
+    pdf: Pdf,
+    y: f32,
+}
+
+impl PdfLayout {
+    fn new() -> Self {
+        let mut pdf = Pdf::new();
+        pdf.add_page();
+        Self { pdf, y: MARGIN }
+    }
+
+    fn ensure(&mut self, height: f32) {
+        if self.y + height > PAGE_HEIGHT - MARGIN {
+            self.pdf.add_page();
+            self.y = MARGIN;
+        }
+    }
+
+    fn heading(&mut self, text: &str, size: f32) {
+        self.ensure(size * 3.0);
+        self.y += size * 0.6;
+        self.pdf.text(MARGIN, self.y, size, Font::Bold, BLACK, text);
+        self.y += size + 4.0;
+        self.pdf.line((MARGIN, self.y), (PAGE_WIDTH - MARGIN, self.y), 0.5, [200, 200, 200]);
+        self.y += 6.0;
+    }
+
+    fn key_values(&mut self, rows: &[(&str, String)]) {
+        for (label, value) in rows {
+            self.ensure(13.0);
+            self.pdf.text(MARGIN, self.y, 10.0, Font::Regular, GREY, label);
+            self.pdf.text(MARGIN + 120.0, self.y, 10.0, Font::Regular, BLACK, value);
+            self.y += 13.0;
+        }
+    }
+
+    fn paragraph(&mut self, text: &str, color: Color) {
+        self.ensure(13.0);
+        self.pdf.text(MARGIN, self.y, 9.0, Font::Regular, color, text);
+        self.y += 13.0;
+    }
+
+    // Een tabelrij; te lange tekst wordt afgekapt op de kolombreedte
+    fn table_row(&mut self, cells: &[String], widths: &[f32], font: Font) {
+        self.ensure(12.0);
+        let mut x = MARGIN;
+        for (cell, width) in cells.iter().zip(widths) {
+            let mut text = cell.clone();
+            while text_width(&text, 8.0) > width - 4.0 && text.pop().is_some() {}
+            self.pdf.text(x, self.y, 8.0, font, BLACK, &text);
+            x += width;
+        }
+        self.y += 12.0;
+    }
+}
+
+// Tab om een rapport van een batch of periode op te slaan
+pub struct ReportTab {
+    by_batch: bool,
+    lot: String,
+    from: String,
+    to: String,
+    path: String,
+    status: Option<Result<String, String>>,
+    // Rapport dat in een aparte thread wordt gemaakt
+    running: Option<Receiver<Result<String, String>>>,
+}
+
+impl ReportTab {
+    pub fn new() -> Self {
+        let today = Local::now();
+        Self {
+            by_batch: true,
+            lot: String::new(),
+            from: today.format("%Y-%m-%d 00:00").to_string(),
+            to: today.format("%Y-%m-%d %H:%M").to_string(),
+            path: "rapport.html".to_string(),
+            status: None,
+            running: None,
+        }
+    }
+
+    fn source(&self) -> Result<ReportSource, String> {
+        match self.by_batch {
+            true if self.lot.is_empty() => Err("kies een batch".to_string()),
+            true => Ok(ReportSource::Batch(self.lot.clone())),
+            false => Ok(ReportSource::Range {
+                from: parse_time(&self.from)?,
+                to: parse_time(&self.to)?,
+            }),
+        }
+    }
+
+    // Het afspelen van de opnames kan lang duren, dus niet in de GUI-thread
+    fn start(&mut self, history: Arc<Vec<BatchSummary>>, path: PathBuf) {
+        let source = match self.source() {
+            Ok(source) => source,
+            Err(err) => {
+                self.status = Some(Err(format!("Rapport mislukt: {}", err)));
+                return;
+            }
+        };
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let result = generate(&history, &source, &path).map_err(|err| format!("Rapport mislukt: {}", err));
+            let _ = sender.send(result);
+        });
+        self.status = None;
+        self.running = Some(receiver);
+    }
+
+    fn poll(&mut self) {
+        let Some(receiver) = &self.running else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(result) => self.status = Some(result),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => self.status = Some(Err("Rapport mislukt: thread gestopt".to_string())),
+        }
+        self.running = None;
+    }
+}
+
+fn generate(history: &[BatchSummary], source: &ReportSource, path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let report = Report::build(history, source)?;
+    report.save(path)?;
+    Ok(format!("{} opgeslagen in {}", report.header.title, path.display()))
+}
+
+impl RenderableTab for ReportTab {
+    fn title(&self) -> &str {
+        "Rapporten"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.poll();
+        let history = state.engine.batch_history.clone();
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.by_batch, true, "Batch");
+            ui.radio_value(&mut self.by_batch, false, "Periode");
+        });
+
+        if self.by_batch {
+            egui::ComboBox::from_id_salt("report_batch")
+                .selected_text(if self.lot.is_empty() { "Kies een batch" } else { &self.lot })
+                .show_ui(ui, |ui| {
+                    for summary in history.iter() {
+                        let text = format!("{} ({})", summary.info.lot, format_time(summary.info.started_at));
+                        ui.selectable_value(&mut self.lot, summary.info.lot.clone(), text);
+                    }
+                });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Van");
+                ui.add(egui::TextEdit::singleline(&mut self.from).desired_width(130.0));
+                ui.label("tot");
+                ui.add(egui::TextEdit::singleline(&mut self.to).desired_width(130.0));
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Bestand");
+            ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(200.0));
+            let idle = self.running.is_none();
+            for extension in ["html", "pdf"] {
+                let button = egui::Button::new(format!("Opslaan als {}", extension.to_uppercase()));
+                if ui.add_enabled(idle, button).clicked() {
+                    let path = Path::new(&self.path).with_extension(extension);
+                    self.path = path.display().to_string();
+                    self.start(history.clone(), path);
+                }
+            }
+        });
+
+        if self.running.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Rapport wordt gemaakt...");
+            });
+        }
+
+        match &self.status {
+            Some(Ok(text)) => {
+                ui.label(text);
+            }
+            Some(Err(text)) => {
+                ui.colored_label(ui.visuals().error_fg_color, text);
+            }
+            None => {}
+        }
+        if history.is_empty() {
+            ui.weak("Rapporten worden gemaakt van afgeronde batches; er zijn nog geen batches.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{BatchInfo, BatchStatus};
+
+    const RECORDING: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/line.rec");
+    const STARTED: u64 = 1_700_000_000_000;
+
+    fn summary(lot: &str, firmware: Option<&str>) -> BatchSummary {
+        BatchSummary {
+            info: BatchInfo {
+                lot: lot.to_string(),
+                recipe: None,
+                operator: "Jan".to_string(),
+                notes: String::new(),
+                started_at: STARTED,
+                line: "Lijn 1".to_string(),
+                hub: Some("/dev/ttyUSB0".to_string()),
+                firmware: firmware.map(str::to_string),
+            },
+            ended_at: STARTED + 3000,
+            counts: BatchCounts {
+                measurements: 1200,
+                items_passed: 2,
+                rejects: 1,
+                ..Default::default()
+            },
+            recording: Some(PathBuf::from(RECORDING)),
+            status: BatchStatus::Ended,
+        }
+    }
+
+    #[test]
+    fn builds_a_batch_report_from_the_recording() {
+        let report = Report::build(&[summary("L-1", Some("1.2.3"))], &ReportSource::Batch("L-1".to_string())).unwrap();
+        assert_eq!(report.header.title, "Batchrapport L-1");
+        assert_eq!(report.header.firmware, ["1.2.3"]);
+        assert_eq!((report.header.from, report.header.to), (STARTED, STARTED + 3000));
+        assert!(report.notes.is_empty(), "{:?}", report.notes);
+
+        // De tellers komen uit de samenvatting
+        assert_eq!(report.counts.items_passed, 2);
+        assert_eq!(report.counts.rejects, 1);
+
+        // De sensoren uit de opname: S04 is een tijd niet verbonden
+        assert_eq!(report.sensors[0].samples, 300);
+        assert_eq!(report.sensors[0].mean(), Some(100.0));
+        assert_eq!(report.sensors[3].disconnected_samples, 10);
+        assert_eq!(report.sensors[3].disconnects, 1);
+        assert_eq!(report.sensors[3].status(), "onderbroken");
+        assert_eq!(report.sensors[4].status(), "geen data");
+
+        let timeline: Vec<&str> = report.timeline.iter().map(|entry| entry.text.as_str()).collect();
+        assert!(timeline.contains(&"Sensor S04 niet verbonden"), "{:?}", timeline);
+        assert!(timeline.contains(&"Sensor S04 weer verbonden"), "{:?}", timeline);
+        assert!(report.timeline.iter().any(|entry| entry.severity == EventSeverity::Error));
+        assert!(report.timeline.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        assert_eq!(report.trends.len(), NUM_SENSORS);
+    }
+
+    #[test]
+    fn reports_unknown_firmware_and_missing_batches() {
+        let history = [summary("L-1", None)];
+        let report = Report::build(&history, &ReportSource::Range { from: STARTED - 1000, to: STARTED + 5000 }).unwrap();
+        assert_eq!(report.header.firmware, ["onbekend"]);
+        assert!(report.header_rows().contains(&("Firmware", "onbekend".to_string())));
+
+        assert!(Report::build(&history, &ReportSource::Batch("L-2".to_string())).is_err());
+        assert!(Report::build(&history, &ReportSource::Range { from: STARTED + 5000, to: STARTED + 9000 }).is_err());
+        assert!(Report::build(&history, &ReportSource::Range { from: STARTED, to: STARTED }).is_err());
+    }
+
+    #[test]
+    fn base64_matches_known_vectors() {
+        // RFC 4648, paragraaf 10
+        for (input, output) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(input.as_bytes()), output);
+        }
+        assert_eq!(base64(&[0xFF, 0xFE, 0xFD]), "//79");
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use egui::text::{LayoutJob, TextFormat};
+use egui_extras::{Column, TableBuilder};
+use metalstream::protocol::{parse_frame, MAX_BUFFER_LEN};
+use metalstream::transport::{Direction, RawChunk, SerialLink};
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+
+// Aantal regels dat de monitor bewaart
+const MONITOR_CAPACITY: usize = 5_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    // Een volledig `$...#` frame dat goed geparst kon worden
+    Frame,
+    // Een frame dat werd afgebroken, niet geparst kon worden of ongeldige velden heeft
+    Invalid,
+    // Bytes buiten een frame
+    Noise,
+    // Door de gebruiker verstuurde bytes
+    Sent,
+}
+
+// Een regel in de monitor: een frame, ruis tussen frames of verstuurde bytes
+pub struct Segment {
+    pub time: DateTime<Local>,
+    pub direction: Direction,
+    pub kind: SegmentKind,
+    pub bytes: Vec<u8>,
+}
+
+// Verzamelt de ruwe datastroom van de seriele thread en knipt die op in regels
+// per frame, zodat frame grenzen en kapotte frames zichtbaar worden.
+pub struct SerialMonitor {
+    receiver: Receiver<RawChunk>,
+    segments: VecDeque<Segment>,
+    // Nog niet afgesloten stuk van de ontvangen stroom
+    pending: Vec<u8>,
+    pending_time: DateTime<Local>,
+    in_frame: bool,
+    pub invalid_frames: u64,
+    // Wordt opgehoogd als er regels bijkomen of verdwijnen
+    pub generation: u64,
+}
+
+impl SerialMonitor {
+    pub fn new(receiver: Receiver<RawChunk>) -> Self {
+        Self {
+            receiver,
+            segments: VecDeque::new(),
+            pending: Vec::new(),
+            pending_time: Local::now(),
+            in_frame: false,
+            invalid_frames: 0,
+            generation: 0,
+        }
+    }
+
+    // Verwerk alle ruwe data die sinds het vorige frame is binnengekomen
+    pub fn drain(&mut self) {
+        while let Ok(chunk) = self.receiver.try_recv() {
+            match chunk.direction {
+                Direction::Rx => self.receive(chunk.time, &chunk.bytes),
+                Direction::Tx => self.push(Segment {
+                    time: chunk.time,
+                    direction: Direction::Tx,
+                    kind: SegmentKind::Sent,
+                    bytes: chunk.bytes,
+                }),
+            }
+        }
+    }
+
+    fn receive(&mut self, time: DateTime<Local>, bytes: &[u8]) {
+        for &byte in bytes {
+            if byte == b'$' {
+                // Begint er een nieuw frame voordat het huidige is afgesloten? Dan is het huidige kapot.
+                let kind = if self.in_frame { SegmentKind::Invalid } else { SegmentKind::Noise };
+                self.flush(kind);
+                self.in_frame = true;
+            }
+
+            if self.pending.is_empty() {
+                self.pending_time = time;
+            }
+            self.pending.push(byte);
+
+            if byte == b'#' && self.in_frame {
+                let valid = std::str::from_utf8(&self.pending)
+                    .ok()
+                    .and_then(parse_frame)
+                    .is_some_and(|message| message.decode().is_ok());
+                self.flush(if valid { SegmentKind::Frame } else { SegmentKind::Invalid });
+                self.in_frame = false;
+            } else if self.pending.len() > MAX_BUFFER_LEN {
+                let kind = if self.in_frame { SegmentKind::Invalid } else { SegmentKind::Noise };
+                self.flush(kind);
+                self.in_frame = false;
+            }
+        }
+    }
+
+    fn flush(&mut self, kind: SegmentKind) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.pending);
+
+        // Regeleindes achter een frame horen bij dat frame en krijgen geen eigen regel
+        if kind == SegmentKind::Noise && bytes.iter().all(u8::is_ascii_whitespace) {
+            if let Some(last) = self.segments.back_mut().filter(|last| last.direction == Direction::Rx) {
+                last.bytes.extend_from_slice(&bytes);
+                return;
+            }
+        }
+
+        if kind == SegmentKind::Invalid {
+            self.invalid_frames += 1;
+        }
+        self.push(Segment {
+            time: self.pending_time,
+            direction: Direction::Rx,
+            kind,
+            bytes,
+        });
+    }
+
+    fn push(&mut self, segment: Segment) {
+        self.segments.push_back(segment);
+        while self.segments.len() > MONITOR_CAPACITY {
+            self.segments.pop_front();
+        }
+        self.generation += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.invalid_frames = 0;
+        self.generation += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputMode {
+    Text,
+    Hex,
+}
+
+// Zet invoer als `AA 55 01`, `aa5501` of `0xAA, 0x55` om naar bytes
+fn parse_hex(input: &str) -> Result<Vec<u8>, String> {
+    let digits: String = input
+        .split([' ', ',', '\t'])
+        .map(|part| part.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return Err("oneven aantal hex tekens".to_string());
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("ongeldige hex byte op positie {}", i / 2 + 1))
+        })
+        .collect()
+}
+
+fn ascii_text(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect()
+}
+
+// Bouw de hex weergave op, met de frame grenzen `$` en `#` gemarkeerd
+fn hex_layout(bytes: &[u8], color: egui::Color32, visuals: &egui::Visuals, font: egui::FontId) -> LayoutJob {
+    let normal = TextFormat::simple(font.clone(), color);
+    let boundary = TextFormat {
+        font_id: font,
+        color: visuals.strong_text_color(),
+        background: visuals.selection.bg_fill,
+        ..Default::default()
+    };
+
+    let mut job = LayoutJob::default();
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            job.append(" ", 0.0, normal.clone());
+        }
+        let format = if matches!(byte, b'$' | b'#') { &boundary } else { &normal };
+        job.append(&format!("{:02X}", byte), 0.0, format.clone());
+    }
+    job
+}
+
+// Tab met de ruwe seriele datastroom, als vervanging voor minicom
+pub struct SerialMonitorTab {
+    paused: bool,
+    show_noise: bool,
+    mode: InputMode,
+    append_newline: bool,
+    input: String,
+    status: Option<String>,
+    // Indexen van de zichtbare regels, opnieuw opgebouwd als de monitor verandert
+    visible: Vec<usize>,
+    generation: Option<u64>,
+}
+
+impl SerialMonitorTab {
+    pub fn new() -> Self {
+        Self {
+            paused: false,
+            show_noise: true,
+            mode: InputMode::Text,
+            append_newline: true,
+            input: String::new(),
+            status: None,
+            visible: Vec::new(),
+            generation: None,
+        }
+    }
+
+    fn send(&mut self, link: &SerialLink) {
+        let bytes = match self.mode {
+            InputMode::Text => {
+                let mut bytes = self.input.as_bytes().to_vec();
+                if self.append_newline {
+                    bytes.push(b'\n');
+                }
+                Ok(bytes)
+            }
+            InputMode::Hex => parse_hex(&self.input),
+        };
+
+        self.status = match bytes.and_then(|bytes| link.send(&bytes).map_err(|err| err.to_string())) {
+            Ok(()) => {
+                self.input.clear();
+                None
+            }
+            Err(err) => Some(format!("Versturen mislukt: {}", err)),
+        };
+    }
+
+    fn toolbar_ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        ui.horizontal_wrapped(|ui| {
+            ui.toggle_value(&mut self.paused, "Pauzeer scrollen");
+            if ui.checkbox(&mut self.show_noise, "Ruis tonen").changed() {
+                self.generation = None;
+            }
+            if ui.button("Wissen").clicked() {
+                state.serial_monitor.clear();
+            }
+
+            ui.separator();
+            ui.label(format!("{} regels", state.serial_monitor.segments.len()));
+            let invalid = state.serial_monitor.invalid_frames;
+            let color = if invalid > 0 { ui.visuals().error_fg_color } else { ui.visuals().text_color() };
+            ui.colored_label(color, format!("{} ongeldige frames", invalid));
+            let dropped = state.serial_link.dropped_chunks();
+            if dropped > 0 {
+                ui.colored_label(ui.visuals().warn_fg_color, format!("{} stukken overgeslagen", dropped))
+                    .on_hover_text("De weergave liep achter op de hub");
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, InputMode::Text, "Tekst");
+            ui.selectable_value(&mut self.mode, InputMode::Hex, "Hex");
+            ui.add_enabled(self.mode == InputMode::Text, egui::Checkbox::new(&mut self.append_newline, "\\n"));
+
+            let hint = match self.mode {
+                InputMode::Text => "Tekst naar de hub",
+                InputMode::Hex => "Bytes, bijv. 24 50 49 4E 47 23",
+            };
+            let response = ui.add(egui::TextEdit::singleline(&mut self.input).hint_text(hint).desired_width(250.0));
+            let submitted = response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+
+            let connected = state.engine.connected;
+            let clicked = ui.add_enabled(connected, egui::Button::new("Verstuur")).clicked();
+            if (clicked || submitted && connected) && !self.input.is_empty() {
+                self.send(&state.serial_link);
+                response.request_focus();
+            }
+
+            if let Some(status) = &self.status {
+                ui.colored_label(ui.visuals().error_fg_color, status);
+            }
+        });
+    }
+}
+
+impl RenderableTab for SerialMonitorTab {
+    fn title(&self) -> &str {
+        "Serial Monitor"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.toolbar_ui(ui, state);
+        ui.separator();
+
+        let monitor = &state.serial_monitor;
+        if self.generation != Some(monitor.generation) {
+            self.visible = (0..monitor.segments.len())
+                .filter(|i| self.show_noise || monitor.segments[*i].kind != SegmentKind::Noise)
+                .collect();
+            self.generation = Some(monitor.generation);
+        }
+
+        let row_height = egui::TextStyle::Monospace.resolve(ui.style()).size + 4.0;
+        let font = egui::TextStyle::Monospace.resolve(ui.style());
+        let visuals = ui.visuals().clone();
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .stick_to_bottom(!self.paused)
+            .column(Column::auto().at_least(90.0))
+            .column(Column::auto().at_least(30.0))
+            .column(Column::initial(450.0).at_least(100.0).clip(true))
+            .column(Column::remainder().clip(true))
+            .header(20.0, |mut header| {
+                for title in ["Tijd", "", "Hex", "ASCII"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, self.visible.len(), |mut row| {
+                    let Some(segment) = monitor.segments.get(self.visible[row.index()]) else {
+                        return;
+                    };
+
+                    let color = match segment.kind {
+                        SegmentKind::Frame => visuals.text_color(),
+                        SegmentKind::Invalid => visuals.error_fg_color,
+                        SegmentKind::Noise => visuals.weak_text_color(),
+                        SegmentKind::Sent => visuals.hyperlink_color,
+                    };
+                    let text = |text: String| egui::RichText::new(text).monospace().color(color);
+
+                    row.col(|ui| {
+                        ui.label(text(segment.time.format("%H:%M:%S%.3f").to_string()));
+                    });
+                    row.col(|ui| {
+                        let arrow = match segment.direction {
+                            Direction::Rx => "<-",
+                            Direction::Tx => "->",
+                        };
+                        ui.label(text(arrow.to_string()));
+                    });
+                    row.col(|ui| {
+                        ui.label(hex_layout(&segment.bytes, color, &visuals, font.clone()));
+                    });
+                    row.col(|ui| {
+                        let response = ui.label(text(ascii_text(&segment.bytes)));
+                        if segment.kind == SegmentKind::Invalid {
+                            response.on_hover_text("Frame is afgebroken of kon niet worden geparst");
+                        }
+                    });
+                });
+            });
+    }
+}
@@ -0,0 +1,475 @@
+use crate::batch::format_time;
+use crate::{GlobalState, RenderableTab};
+use chrono::{DateTime, Local};
+use metalstream::processing::{Detection, Detector};
+use metalstream::protocol::{Measurement, NUM_SENSORS};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+// Maximaal aantal scans in de heatmap van een detectie; bij een langere detectie
+// blijft het einde in beeld
+const SNAPSHOT_SCANS: usize = 80;
+// Scans voor het begin van de detectie die mee in de heatmap komen
+const SNAPSHOT_MARGIN: usize = 10;
+// Aantal detecties dat in de galerij blijft staan
+const GALLERY_CAPACITY: usize = 100;
+
+// Afmetingen van de afbeelding in pixels
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 12;
+const LABEL_WIDTH: usize = 30;
+const PADDING: usize = 4;
+const SCALE_GAP: usize = 6;
+const SCALE_HEIGHT: usize = 8;
+// Het 3x5 font wordt twee keer zo groot getekend
+const FONT_SCALE: usize = 2;
+const GLYPH_WIDTH: usize = 3 * FONT_SCALE;
+const GLYPH_HEIGHT: usize = 5 * FONT_SCALE;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const TEXT: [u8; 3] = [90, 90, 90];
+// Een sensor zonder afwijking: niet verbonden of nog niet gekalibreerd
+const MISSING: [u8; 3] = [210, 210, 210];
+
+// Van blauw (geen afwijking) via geel naar rood (op of boven de drempel)
+pub fn heat_color(intensity: f32) -> [u8; 3] {
+    let t = intensity.clamp(0.0, 1.0);
+    let lerp = |a: u8, b: u8, t: f32| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    if t < 0.5 {
+        let t = t * 2.0;
+        [lerp(30, 250, t), lerp(40, 220, t), lerp(110, 60, t)]
+    } else {
+        let t = (t - 0.5) * 2.0;
+        [lerp(250, 215, t), lerp(220, 40, t), lerp(60, 30, t)]
+    }
+}
+
+pub fn cell_color(intensity: Option<f32>) -> [u8; 3] {
+    intensity.map_or(MISSING, heat_color)
+}
+
+// Bitmaps van de tekens die in labels voorkomen, per rij drie bits
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().count() * (GLYPH_WIDTH + FONT_SCALE)
+}
+
+// Een RGB afbeelding, rij voor rij vanaf linksboven
+pub struct Heatmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Heatmap {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+        }
+    }
+
+    fn rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for row in y..(y + height).min(self.height) {
+            let start = row * self.width;
+            self.pixels[start + x.min(self.width)..start + (x + width).min(self.width)].fill(color);
+        }
+    }
+
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (index, c) in text.chars().enumerate() {
+            let left = x + index * (GLYPH_WIDTH + FONT_SCALE);
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.rect(left + column * FONT_SCALE, y + row * FONT_SCALE, FONT_SCALE, FONT_SCALE, TEXT);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_color_image(&self) -> egui::ColorImage {
+        let bytes: Vec<u8> = self.pixels.iter().flatten().copied().collect();
+        egui::ColorImage::from_rgb([self.width, self.height], &bytes)
+    }
+}
+
+// Houdt de afwijkingen van de laatste scans bij, zodat de heatmap van een detectie
+// van het begin tot het einde van die detectie loopt
+#[derive(Default)]
+pub struct SnapshotWindow {
+    // Afwijking van de baseline van de detector, per scan en sensor
+    scans: VecDeque<[Option<f32>; NUM_SENSORS]>,
+    // Hub timestamps van de metingen met het nummer van hun scan
+    timestamps: VecDeque<(String, u64)>,
+    // Sensoren die al in de laatste scan zitten
+    scanned: [bool; NUM_SENSORS],
+    // Aantal scans sinds het begin, ook de weggegooide
+    total: u64,
+}
+
+impl SnapshotWindow {
+    // Een sensor die al in de laatste scan zit begint een nieuwe scan, net als in
+    // de heatmap van de Visualization tab
+    pub fn add(&mut self, timestamp: &str, measurement: &Measurement, detector: &Detector) {
+        let index = measurement.id as usize - 1;
+        if self.scans.is_empty() || self.scanned[index] {
+            if self.scans.len() >= SNAPSHOT_SCANS {
+                self.scans.pop_front();
+            }
+            self.scans.push_back([None; NUM_SENSORS]);
+            self.scanned = [false; NUM_SENSORS];
+            self.total += 1;
+        }
+        self.scanned[index] = true;
+        if let Some(scan) = self.scans.back_mut() {
+            scan[index] = detector
+                .baseline(measurement.id)
+                .filter(|_| measurement.connected)
+                .map(|baseline| (measurement.value as f64 - baseline) as f32);
+        }
+
+        if self.timestamps.len() >= SNAPSHOT_SCANS * NUM_SENSORS {
+            self.timestamps.pop_front();
+        }
+        self.timestamps.push_back((timestamp.to_string(), self.total - 1));
+    }
+
+    // Het nummer van de laatste scan met een meting met deze timestamp
+    fn scan_of(&self, timestamp: &str) -> Option<u64> {
+        self.timestamps.iter().rev().find(|(other, _)| other == timestamp).map(|(_, scan)| *scan)
+    }
+
+    // Leg de heatmap van een detectie vast, met wat scans ervoor als marge. Is het
+    // begin al uit het venster gevallen, dan begint de heatmap bij de oudste scan.
+    pub fn capture(&self, detection: &Detection, threshold: f64, time: u64) -> DetectionSnapshot {
+        let first = self.total - self.scans.len() as u64;
+        let end = self.scan_of(&detection.end_timestamp).unwrap_or(self.total.saturating_sub(1)).max(first);
+        let start = self
+            .scan_of(&detection.start_timestamp)
+            .filter(|start| *start <= end)
+            .unwrap_or(first)
+            .saturating_sub(SNAPSHOT_MARGIN as u64)
+            .max(first);
+        let scans: Vec<_> = self.scans.iter().skip((start - first) as usize).take((end + 1 - start) as usize).collect();
+        DetectionSnapshot {
+            detection: detection.clone(),
+            time,
+            threshold,
+            rows: (0..NUM_SENSORS).map(|sensor| scans.iter().map(|scan| scan[sensor]).collect()).collect(),
+        }
+    }
+}
+
+// De scans van alle sensoren tijdens een detectie
+#[derive(Debug, Clone)]
+pub struct DetectionSnapshot {
+    pub detection: Detection,
+    // Unix tijd in milliseconden
+    pub time: u64,
+    pub threshold: f64,
+    // Per sensor de afwijking van de baseline in elke scan; leeg als de sensor
+    // niet verbonden of nog niet gekalibreerd was
+    pub rows: Vec<Vec<Option<f32>>>,
+}
+
+impl DetectionSnapshot {
+    // Afwijking van de baseline als fractie van de drempel (0..1)
+    pub fn intensities(&self) -> Vec<Vec<Option<f32>>> {
+        let threshold = self.threshold.max(1.0) as f32;
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(|deviation| deviation.map(|deviation| (deviation.abs() / threshold).min(1.0))).collect())
+            .collect()
+    }
+
+    pub fn caption(&self) -> String {
+        format!(
+            "{} – piek S0{} ({:.0}), sensoren {}",
+            format_time(self.time),
+            self.detection.peak_sensor,
+            self.detection.peak_deviation,
+            self.detection.sensors.iter().map(|id| format!("S0{}", id)).collect::<Vec<_>>().join(" ")
+        )
+    }
+
+    // Heatmap met een rij per sensor, de nieuwste scans rechts, en een schaal
+    // van de afwijking eronder
+    pub fn render(&self) -> Heatmap {
+        let intensities = self.intensities();
+        let columns = intensities.iter().map(Vec::len).max().unwrap_or_default().max(SNAPSHOT_MARGIN * 2);
+        let map_width = columns * CELL_WIDTH;
+        let scale_top = PADDING + NUM_SENSORS * CELL_HEIGHT + SCALE_GAP;
+        let mut image = Heatmap::new(
+            LABEL_WIDTH + map_width + PADDING,
+            scale_top + SCALE_HEIGHT + FONT_SCALE + GLYPH_HEIGHT + PADDING,
+        );
+
+        for sensor in 0..NUM_SENSORS {
+            let top = PADDING + sensor * CELL_HEIGHT;
+            image.text(PADDING, top + (CELL_HEIGHT - GLYPH_HEIGHT) / 2, &format!("S0{}", sensor + 1));
+            let row = intensities.get(sensor).map(Vec::as_slice).unwrap_or_default();
+            let offset = columns - row.len();
+            for (column, intensity) in row.iter().enumerate() {
+                image.rect(LABEL_WIDTH + (offset + column) * CELL_WIDTH, top, CELL_WIDTH, CELL_HEIGHT, cell_color(*intensity));
+            }
+        }
+
+        for x in 0..map_width {
+            image.rect(LABEL_WIDTH + x, scale_top, 1, SCALE_HEIGHT, heat_color(x as f32 / (map_width - 1) as f32));
+        }
+        let labels_top = scale_top + SCALE_HEIGHT + FONT_SCALE;
+        let threshold = self.threshold.max(1.0);
+        image.text(LABEL_WIDTH, labels_top, "0");
+        let half = format!("{:.0}", threshold / 2.0);
+        image.text(LABEL_WIDTH + (map_width - text_width(&half)) / 2, labels_top, &half);
+        let full = format!("{:.0}", threshold);
+        image.text(LABEL_WIDTH + map_width - text_width(&full), labels_top, &full);
+        image
+    }
+
+    // Gegevens van de detectie als tekst in het PNG bestand
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        let detection = &self.detection;
+        let mut metadata = vec![
+            ("Title", "Metaaldetectie".to_string()),
+            ("Software", format!("Metalstream desktop {}", env!("CARGO_PKG_VERSION"))),
+            ("Creation Time", format_time(self.time)),
+            ("Hub Timestamp", format!("{} - {}", detection.start_timestamp, detection.end_timestamp)),
+            ("Peak Sensor", format!("S0{}", detection.peak_sensor)),
+            ("Peak Deviation", format!("{:.1}", detection.peak_deviation)),
+            ("Sensors", detection.sensors.iter().map(|id| format!("S0{}", id)).collect::<Vec<_>>().join(" ")),
+            ("Samples", detection.samples.to_string()),
+            ("Threshold", format!("{:.1}", self.threshold)),
+        ];
+        metadata.extend(detection.recipe.clone().map(|recipe| ("Recipe", recipe)));
+        metadata.extend(detection.batch.clone().map(|batch| ("Batch", batch)));
+        metadata.extend(detection.item.map(|item| ("Item", item.to_string())));
+        metadata
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let image = self.render();
+        crate::png::encode(image.width, image.height, &image.pixels, &self.metadata())
+    }
+
+    pub fn file_name(&self) -> String {
+        let time = DateTime::from_timestamp_millis(self.time as i64).unwrap_or_default().with_timezone(&Local);
+        format!("detectie-{}-S0{}.png", time.format("%Y%m%d-%H%M%S-%3f"), self.detection.peak_sensor)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_png())
+    }
+}
+
+struct GalleryEntry {
+    id: u64,
+    snapshot: DetectionSnapshot,
+}
+
+// De laatste detecties met hun heatmap
+#[derive(Default)]
+pub struct Gallery {
+    entries: VecDeque<GalleryEntry>,
+    next_id: u64,
+}
+
+impl Gallery {
+    pub fn push(&mut self, snapshot: DetectionSnapshot) {
+        if self.entries.len() == GALLERY_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.next_id += 1;
+        self.entries.push_back(GalleryEntry { id: self.next_id, snapshot });
+    }
+
+    fn get(&self, id: u64) -> Option<&DetectionSnapshot> {
+        self.entries.iter().find(|entry| entry.id == id).map(|entry| &entry.snapshot)
+    }
+
+    // Bewaar alle snapshots als PNG in een map
+    fn export_all(&self, directory: &Path) -> std::io::Result<usize> {
+        std::fs::create_dir_all(directory)?;
+        for entry in &self.entries {
+            entry.snapshot.save(&directory.join(entry.snapshot.file_name()))?;
+        }
+        Ok(self.entries.len())
+    }
+}
+
+// Tab met de heatmaps van de laatste detecties
+pub struct GalleryTab {
+    textures: HashMap<u64, egui::TextureHandle>,
+    selected: Option<u64>,
+    directory: String,
+    status: Option<Result<String, String>>,
+}
+
+impl GalleryTab {
+    pub fn new() -> Self {
+        Self {
+            textures: HashMap::new(),
+            selected: None,
+            directory: String::new(),
+            status: None,
+        }
+    }
+
+    fn texture(&mut self, ctx: &egui::Context, id: u64, snapshot: &DetectionSnapshot) -> egui::TextureHandle {
+        self.textures
+            .entry(id)
+            .or_insert_with(|| ctx.load_texture(format!("snapshot_{}", id), snapshot.render().to_color_image(), Default::default()))
+            .clone()
+    }
+
+    fn export(&self, snapshot: &DetectionSnapshot) -> Result<String, String> {
+        let path = Path::new(&self.directory).join(snapshot.file_name());
+        snapshot
+            .save(&path)
+            .map(|_| format!("Opgeslagen als {}", path.display()))
+            .map_err(|err| format!("Kan {} niet opslaan: {}", path.display(), err))
+    }
+}
+
+impl RenderableTab for GalleryTab {
+    fn title(&self) -> &str {
+        "Detecties"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        // Textures van snapshots die uit de galerij zijn gevallen opruimen
+        self.textures.retain(|id, _| state.gallery.get(*id).is_some());
+
+        ui.horizontal(|ui| {
+            ui.label("Map");
+            ui.add(egui::TextEdit::singleline(&mut self.directory).desired_width(200.0));
+            if ui.button("Alles exporteren").clicked() {
+                self.status = Some(
+                    state
+                        .gallery
+                        .export_all(Path::new(&self.directory))
+                        .map(|count| format!("{} snapshots opgeslagen in {}", count, self.directory))
+                        .map_err(|err| format!("Exporteren mislukt: {}", err)),
+                );
+            }
+        });
+        match &self.status {
+            Some(Ok(text)) => {
+                ui.label(text);
+            }
+            Some(Err(text)) => {
+                ui.colored_label(ui.visuals().error_fg_color, text);
+            }
+            None => {}
+        }
+
+        if state.gallery.entries.is_empty() {
+            ui.weak("Nog geen detecties");
+            return;
+        }
+
+        if let Some((id, snapshot)) = self.selected.and_then(|id| Some((id, state.gallery.get(id)?))) {
+            ui.separator();
+            ui.label(snapshot.caption());
+            let texture = self.texture(ui.ctx(), id, snapshot);
+            ui.add(egui::Image::new(&texture).fit_to_original_size(2.0).texture_options(egui::TextureOptions::NEAREST));
+            egui::Grid::new("snapshot_metadata").num_columns(2).show(ui, |ui| {
+                for (key, value) in snapshot.metadata().into_iter().skip(2) {
+                    ui.label(key);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Exporteer PNG").clicked() {
+                    self.status = Some(self.export(snapshot));
+                }
+                if ui.button("Sluiten").clicked() {
+                    self.selected = None;
+                }
+            });
+        }
+
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for entry in state.gallery.entries.iter().rev() {
+                    let texture = self.texture(ui.ctx(), entry.id, &entry.snapshot);
+                    let response = ui
+                        .add(egui::Image::new(&texture).sense(egui::Sense::click()))
+                        .on_hover_text(entry.snapshot.caption());
+                    if response.clicked() {
+                        self.selected = Some(entry.id);
+                    }
+                    if self.selected == Some(entry.id) {
+                        ui.painter().rect_stroke(response.rect, 0.0, ui.visuals().selection.stroke);
+                    }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scans van alle sensoren op 100, behalve de gegeven waarden; S08 is niet verbonden
+    fn scan(window: &mut SnapshotWindow, detector: &mut Detector, clock: &mut u64, values: &[(u8, u16)]) -> Option<Detection> {
+        let mut detection = None;
+        for id in 1..=NUM_SENSORS as u8 {
+            *clock += 1;
+            let value = values.iter().find(|(sensor, _)| *sensor == id).map_or(100, |(_, value)| *value);
+            let measurement = Measurement { id, connected: id != 8, value };
+            window.add(&clock.to_string(), &measurement, detector);
+            detection = detection.or(detector.process(&clock.to_string(), &measurement));
+        }
+        detection
+    }
+
+    #[test]
+    fn captures_detection_with_margin() {
+        let mut window = SnapshotWindow::default();
+        let mut detector = Detector::default();
+        let mut clock = 0;
+        // Eerst kalibreren
+        for _ in 0..30 {
+            assert!(scan(&mut window, &mut detector, &mut clock, &[]).is_none());
+        }
+        for _ in 0..3 {
+            assert!(scan(&mut window, &mut detector, &mut clock, &[(2, 400)]).is_none());
+        }
+        let detection = scan(&mut window, &mut detector, &mut clock, &[]).unwrap();
+
+        let snapshot = window.capture(&detection, detector.threshold(), 0);
+        let intensities = snapshot.intensities();
+        assert_eq!(intensities.len(), NUM_SENSORS);
+        // De marge voor de detectie en de drie scans met metaal, zonder de scan die hem afsloot
+        assert!(intensities.iter().all(|row| row.len() == SNAPSHOT_MARGIN + 3));
+        assert_eq!(intensities[1][SNAPSHOT_MARGIN..], [Some(1.0); 3]);
+        assert!(intensities[1][..SNAPSHOT_MARGIN].iter().all(|intensity| *intensity == Some(0.0)));
+        assert!(intensities[0].iter().all(|intensity| *intensity == Some(0.0)));
+        assert!(intensities[7].iter().all(Option::is_none));
+    }
+}
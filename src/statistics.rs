@@ -0,0 +1,796 @@
+use crate::items::{Item, Verdict};
+use crate::recording::{Replay, ReplayEvent};
+use crate::{GlobalState, RenderableTab};
+use egui_plot::{Bar, BarChart, HLine, Line, Plot, Points};
+use metalstream::protocol::{LineMetrics, Measurement, Message, NUM_SENSORS};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+// Lengte van een subgroep voor de regelkaarten; per sensor worden de metingen
+// in een subgroep samengevat tot een gemiddelde (baseline) en spreiding (ruis)
+const SUBGROUP_MS: u64 = 1000;
+// Hoe lang live gegevens bewaard blijven; een opname wordt altijd helemaal gebruikt
+const LIVE_RETENTION_MS: u64 = 60 * 60 * 1000;
+const HISTOGRAM_BINS: usize = 20;
+// CUSUM referentiewaarde en beslisgrens, in standaardafwijkingen van het subgroepgemiddelde
+const CUSUM_K: f64 = 0.5;
+const CUSUM_H: f64 = 5.0;
+
+// Keuze van het tijdvenster; `None` is alles wat bewaard is
+pub const WINDOWS: [(&str, Option<u64>); 5] = [
+    ("1 minuut", Some(60_000)),
+    ("5 minuten", Some(5 * 60_000)),
+    ("15 minuten", Some(15 * 60_000)),
+    ("1 uur", Some(60 * 60_000)),
+    ("Alles", None),
+];
+
+struct ItemSample {
+    time: u64,
+    width: f64,
+    length: f64,
+    speed: f64,
+    reject: bool,
+}
+
+// Samenvatting van de metingen van een sensor in een subgroep
+#[derive(Debug, Clone, Copy)]
+pub struct Subgroup {
+    pub time: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub samples: u32,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Accumulator {
+    started_at: u64,
+    samples: u32,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Accumulator {
+    fn add(&mut self, time: u64, value: f64) {
+        if self.samples == 0 {
+            self.started_at = time;
+        }
+        self.samples += 1;
+        self.sum += value;
+        self.sum_squares += value * value;
+    }
+
+    fn take(&mut self) -> Option<Subgroup> {
+        let accumulator = std::mem::take(self);
+        if accumulator.samples < 2 {
+            return None;
+        }
+        let n = accumulator.samples as f64;
+        let mean = accumulator.sum / n;
+        let variance = (accumulator.sum_squares - n * mean * mean) / (n - 1.0);
+        Some(Subgroup {
+            time: accumulator.started_at,
+            mean,
+            stddev: variance.max(0.0).sqrt(),
+            samples: accumulator.samples,
+        })
+    }
+}
+
+// Verdeling van een grootheid, met een histogram over het bereik
+#[derive(Debug, Clone, Default)]
+pub struct Distribution {
+    pub samples: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub bin_width: f64,
+    // Midden van elke bin met het aantal waarden erin
+    pub bins: Vec<(f64, usize)>,
+}
+
+impl Distribution {
+    fn new(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        // Bij een constante waarde toch een zichtbare bin maken
+        let bin_width = match max - min {
+            range if range > 0.0 => range / HISTOGRAM_BINS as f64,
+            _ => 1.0,
+        };
+        let mut counts = vec![0; HISTOGRAM_BINS];
+        for value in values {
+            let bin = ((value - min) / bin_width) as usize;
+            counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        Self {
+            samples: values.len(),
+            mean,
+            stddev: variance.sqrt(),
+            min,
+            max,
+            bin_width,
+            bins: counts
+                .into_iter()
+                .enumerate()
+                .map(|(bin, count)| (min + (bin as f64 + 0.5) * bin_width, count))
+                .collect(),
+        }
+    }
+
+    // Variatiecoefficient in procenten
+    pub fn variation(&self) -> f64 {
+        match self.mean.abs() > f64::EPSILON {
+            true => self.stddev / self.mean.abs() * 100.0,
+            false => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub center: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Limits {
+    pub fn contains(&self, value: f64) -> bool {
+        value >= self.lower && value <= self.upper
+    }
+}
+
+// Shewhart kaarten van baseline (gemiddelde) en ruis (spreiding) en een CUSUM
+// van de baseline, voor een sensor over het gekozen venster
+#[derive(Debug, Clone, Default)]
+pub struct ControlChart {
+    pub subgroups: Vec<Subgroup>,
+    pub mean: Limits,
+    pub noise: Limits,
+    // Bovenste en onderste cumulatieve som per subgroep
+    pub cusum: Vec<(f64, f64)>,
+    pub cusum_limit: f64,
+}
+
+impl ControlChart {
+    fn new(subgroups: Vec<Subgroup>) -> Self {
+        if subgroups.is_empty() {
+            return Self::default();
+        }
+        let n = subgroups.len() as f64;
+        let center = subgroups.iter().map(|group| group.mean).sum::<f64>() / n;
+        let noise = subgroups.iter().map(|group| group.stddev).sum::<f64>() / n;
+        let samples = subgroups.iter().map(|group| group.samples as f64).sum::<f64>() / n;
+
+        // Grenzen op drie sigma, met de gemiddelde spreiding binnen de subgroepen
+        // als schatting; de correctie voor kleine subgroepen wordt weggelaten
+        let sigma_mean = noise / samples.sqrt();
+        let sigma_noise = noise / (2.0 * (samples - 1.0).max(1.0)).sqrt();
+
+        // Tabulaire CUSUM rond de centrale lijn
+        let sigma = sigma_mean.max(f64::EPSILON);
+        let mut upper = 0.0_f64;
+        let mut lower = 0.0_f64;
+        let cusum = subgroups
+            .iter()
+            .map(|group| {
+                upper = (upper + group.mean - center - CUSUM_K * sigma).max(0.0);
+                lower = (lower + center - group.mean - CUSUM_K * sigma).max(0.0);
+                (upper, lower)
+            })
+            .collect();
+
+        Self {
+            subgroups,
+            mean: Limits {
+                center,
+                lower: center - 3.0 * sigma_mean,
+                upper: center + 3.0 * sigma_mean,
+            },
+            noise: Limits {
+                center: noise,
+                lower: (noise - 3.0 * sigma_noise).max(0.0),
+                upper: noise + 3.0 * sigma_noise,
+            },
+            cusum,
+            cusum_limit: CUSUM_H * sigma,
+        }
+    }
+
+    pub fn mean_violations(&self) -> usize {
+        self.subgroups.iter().filter(|group| !self.mean.contains(group.mean)).count()
+    }
+
+    pub fn noise_violations(&self) -> usize {
+        self.subgroups.iter().filter(|group| !self.noise.contains(group.stddev)).count()
+    }
+
+    pub fn cusum_signals(&self) -> usize {
+        self.cusum
+            .iter()
+            .filter(|(upper, lower)| upper.max(*lower) > self.cusum_limit)
+            .count()
+    }
+}
+
+// Kengetallen over een venster
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub span_ms: u64,
+    pub items: usize,
+    pub rejects: usize,
+    pub detections: usize,
+    pub width: Distribution,
+    pub length: Distribution,
+    pub speed: Distribution,
+    // Snelheid in de tijd, in seconden ten opzichte van het einde van het venster
+    pub speed_points: Vec<[f64; 2]>,
+}
+
+impl Summary {
+    fn per_minute(&self, count: usize) -> f64 {
+        match self.span_ms {
+            0 => 0.0,
+            span => count as f64 / (span as f64 / 60_000.0),
+        }
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.per_minute(self.items)
+    }
+
+    pub fn detection_rate(&self) -> f64 {
+        self.per_minute(self.detections)
+    }
+
+    pub fn reject_rate(&self) -> f64 {
+        match self.items {
+            0 => 0.0,
+            items => self.rejects as f64 / items as f64 * 100.0,
+        }
+    }
+}
+
+// Houdt de gegevens bij waaruit de statistieken worden berekend. Tijden zijn in
+// milliseconden: unix tijd voor de live stroom, tijd sinds de start bij een opname.
+pub struct Statistics {
+    retention: Option<u64>,
+    first: Option<u64>,
+    latest: u64,
+    items: VecDeque<ItemSample>,
+    detections: VecDeque<u64>,
+    speeds: VecDeque<(u64, f64)>,
+    subgroups: Vec<VecDeque<Subgroup>>,
+    open: [Accumulator; NUM_SENSORS],
+}
+
+impl Statistics {
+    fn new(retention: Option<u64>) -> Self {
+        Self {
+            retention,
+            first: None,
+            latest: 0,
+            items: VecDeque::new(),
+            detections: VecDeque::new(),
+            speeds: VecDeque::new(),
+            subgroups: vec![VecDeque::new(); NUM_SENSORS],
+            open: [Accumulator::default(); NUM_SENSORS],
+        }
+    }
+
+    pub fn live() -> Self {
+        Self::new(Some(LIVE_RETENTION_MS))
+    }
+
+    // Reken een opname door met het recept uit de opname
+    pub fn from_recording(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut statistics = Self::new(None);
+        for event in Replay::open(path)? {
+            match event? {
+                ReplayEvent::Message { elapsed, decoded, .. } => match decoded {
+                    Ok(Message::Sms(measurement)) => statistics.on_measurement(elapsed, &measurement),
+                    Ok(Message::Met(metrics)) => statistics.on_met(elapsed, &metrics),
+                    _ => {}
+                },
+                ReplayEvent::Detection { elapsed, .. } => statistics.on_detection(elapsed),
+                ReplayEvent::Item(item) => statistics.on_item(&item),
+            }
+        }
+        statistics.close_subgroups();
+        Ok(statistics)
+    }
+
+    fn advance(&mut self, time: u64) {
+        self.first.get_or_insert(time);
+        self.latest = self.latest.max(time);
+
+        let Some(retention) = self.retention else {
+            return;
+        };
+        let oldest = self.latest.saturating_sub(retention);
+        while self.items.front().is_some_and(|item| item.time < oldest) {
+            self.items.pop_front();
+        }
+        while self.detections.front().is_some_and(|time| *time < oldest) {
+            self.detections.pop_front();
+        }
+        while self.speeds.front().is_some_and(|(time, _)| *time < oldest) {
+            self.speeds.pop_front();
+        }
+        for subgroups in &mut self.subgroups {
+            while subgroups.front().is_some_and(|group| group.time < oldest) {
+                subgroups.pop_front();
+            }
+        }
+    }
+
+    pub fn on_measurement(&mut self, time: u64, measurement: &Measurement) {
+        self.advance(time);
+        if !measurement.connected {
+            return;
+        }
+        let index = measurement.id as usize - 1;
+        let open = &mut self.open[index];
+        if open.samples > 0 && time >= open.started_at + SUBGROUP_MS {
+            if let Some(group) = open.take() {
+                self.subgroups[index].push_back(group);
+            }
+        }
+        self.open[index].add(time, measurement.value as f64);
+    }
+
+    pub fn on_met(&mut self, time: u64, metrics: &LineMetrics) {
+        self.advance(time);
+        if metrics.speed > 0.0 {
+            self.speeds.push_back((time, metrics.speed));
+        }
+    }
+
+    pub fn on_detection(&mut self, time: u64) {
+        self.advance(time);
+        self.detections.push_back(time);
+    }
+
+    pub fn on_item(&mut self, item: &Item) {
+        self.advance(item.ended_at);
+        self.items.push_back(ItemSample {
+            time: item.ended_at,
+            width: item.width,
+            length: item.length,
+            speed: item.speed,
+            reject: item.verdict == Verdict::Reject,
+        });
+    }
+
+    // Neem ook de laatste, nog niet volle subgroepen mee
+    fn close_subgroups(&mut self) {
+        for (index, open) in self.open.iter_mut().enumerate() {
+            if let Some(group) = open.take() {
+                self.subgroups[index].push_back(group);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.retention);
+    }
+
+    fn start(&self, window: Option<u64>) -> u64 {
+        window.map_or(0, |window| self.latest.saturating_sub(window))
+    }
+
+    fn relative(&self, time: u64) -> f64 {
+        (time as f64 - self.latest as f64) / 1000.0
+    }
+
+    pub fn summary(&self, window: Option<u64>) -> Summary {
+        let start = self.start(window);
+        let items: Vec<&ItemSample> = self.items.iter().filter(|item| item.time >= start).collect();
+        let speeds: Vec<(u64, f64)> = self.speeds.iter().copied().filter(|(time, _)| *time >= start).collect();
+        let observed = self.first.map_or(0, |first| self.latest - first);
+
+        Summary {
+            span_ms: window.map_or(observed, |window| window.min(observed)),
+            items: items.len(),
+            rejects: items.iter().filter(|item| item.reject).count(),
+            detections: self.detections.iter().filter(|time| **time >= start).count(),
+            width: Distribution::new(&items.iter().map(|item| item.width).collect::<Vec<_>>()),
+            length: Distribution::new(&items.iter().map(|item| item.length).collect::<Vec<_>>()),
+            speed: Distribution::new(&speeds.iter().map(|(_, speed)| *speed).collect::<Vec<_>>()),
+            speed_points: speeds.iter().map(|(time, speed)| [self.relative(*time), *speed]).collect(),
+        }
+    }
+
+    pub fn control_chart(&self, sensor: u8, window: Option<u64>) -> ControlChart {
+        let start = self.start(window);
+        ControlChart::new(
+            self.subgroups[sensor as usize - 1]
+                .iter()
+                .copied()
+                .filter(|group| group.time >= start)
+                .collect(),
+        )
+    }
+
+    // Gemiddelde snelheid per product, naast de snelheid uit alle `MET` berichten
+    fn speed_of_items(&self, window: Option<u64>) -> Distribution {
+        let start = self.start(window);
+        Distribution::new(&self.items.iter().filter(|item| item.time >= start).map(|item| item.speed).collect::<Vec<_>>())
+    }
+}
+
+// Tab met kengetallen, verdelingen en regelkaarten, live of van een opname
+pub struct StatisticsTab {
+    live: bool,
+    path: String,
+    recording: Option<Statistics>,
+    status: Option<Result<String, String>>,
+    // Opname die in een aparte thread wordt afgespeeld
+    loading: Option<Receiver<Result<Statistics, String>>>,
+    window: usize,
+    sensor: u8,
+}
+
+impl StatisticsTab {
+    pub fn new() -> Self {
+        Self {
+            live: true,
+            path: String::new(),
+            recording: None,
+            status: None,
+            loading: None,
+            window: 1,
+            sensor: 1,
+        }
+    }
+
+    fn load(&mut self) {
+        let (sender, receiver) = channel();
+        let path = PathBuf::from(&self.path);
+        std::thread::spawn(move || {
+            let _ = sender.send(Statistics::from_recording(&path).map_err(|err| err.to_string()));
+        });
+        self.status = None;
+        self.loading = Some(receiver);
+    }
+
+    fn poll(&mut self) {
+        let Some(receiver) = &self.loading else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(Ok(statistics)) => {
+                self.recording = Some(statistics);
+                self.status = Some(Ok(format!("{} geladen", self.path)));
+            }
+            Ok(Err(err)) => self.status = Some(Err(format!("Kan {} niet laden: {}", self.path, err))),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => self.status = Some(Err(format!("Kan {} niet laden", self.path))),
+        }
+        self.loading = None;
+    }
+}
+
+fn histogram(ui: &mut egui::Ui, id: &str, title: &str, distribution: &Distribution) {
+    ui.vertical(|ui| {
+        ui.label(format!(
+            "{}: {:.1} ± {:.1} mm (min {:.0}, max {:.0}, {} producten)",
+            title, distribution.mean, distribution.stddev, distribution.min, distribution.max, distribution.samples
+        ));
+        let bars = distribution
+            .bins
+            .iter()
+            .map(|(center, count)| Bar::new(*center, *count as f64).width(distribution.bin_width * 0.9))
+            .collect();
+        Plot::new(id)
+            .height(140.0)
+            .width(ui.available_width().min(400.0))
+            .allow_scroll(false)
+            .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars).name(title)));
+    });
+}
+
+// Een regelkaart met centrale lijn en grenzen; punten buiten de grenzen worden rood
+fn control_plot(ui: &mut egui::Ui, id: &str, points: Vec<[f64; 2]>, limits: Limits) {
+    let warn = ui.visuals().warn_fg_color;
+    let outside: Vec<[f64; 2]> = points.iter().copied().filter(|[_, value]| !limits.contains(*value)).collect();
+    Plot::new(id).height(130.0).allow_scroll(false).show(ui, |plot_ui| {
+        plot_ui.hline(HLine::new(limits.center).name("CL"));
+        plot_ui.hline(HLine::new(limits.upper).name("UCL").color(warn));
+        plot_ui.hline(HLine::new(limits.lower).name("LCL").color(warn));
+        plot_ui.line(Line::new(points));
+        plot_ui.points(Points::new(outside).radius(3.0).color(egui::Color32::RED));
+    });
+}
+
+impl RenderableTab for StatisticsTab {
+    fn title(&self) -> &str {
+        "Statistiek"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        self.poll();
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.live, true, "Live");
+            ui.radio_value(&mut self.live, false, "Opname");
+            if self.live {
+                if ui.button("Wissen").clicked() {
+                    state.statistics.clear();
+                }
+            } else {
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(200.0));
+                if ui.add_enabled(self.loading.is_none(), egui::Button::new("Laden")).clicked() {
+                    self.load();
+                }
+                if self.loading.is_some() {
+                    ui.spinner();
+                }
+            }
+
+            ui.separator();
+            egui::ComboBox::from_id_salt("statistics_window")
+                .selected_text(WINDOWS[self.window].0)
+                .show_ui(ui, |ui| {
+                    for (index, (label, _)) in WINDOWS.iter().enumerate() {
+                        ui.selectable_value(&mut self.window, index, *label);
+                    }
+                });
+        });
+
+        if !self.live {
+            match &self.status {
+                Some(Ok(text)) => {
+                    ui.label(text);
+                }
+                Some(Err(text)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, text);
+                }
+                None => {}
+            }
+        }
+
+        let statistics = match (self.live, &self.recording) {
+            (true, _) => &state.statistics,
+            (false, Some(recording)) => recording,
+            (false, None) => {
+                ui.weak("Laad een opname om de statistieken te zien");
+                return;
+            }
+        };
+        let window = WINDOWS[self.window].1;
+        let summary = statistics.summary(window);
+        let item_speed = statistics.speed_of_items(window);
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("statistics_summary").num_columns(2).striped(true).show(ui, |ui| {
+                let rows = [
+                    ("Producten", format!("{} ({} afgekeurd)", summary.items, summary.rejects)),
+                    ("Doorvoer", format!("{:.1} producten/min", summary.throughput())),
+                    ("Detecties", format!("{} ({:.2}/min)", summary.detections, summary.detection_rate())),
+                    ("Afkeur", format!("{:.1} %", summary.reject_rate())),
+                    (
+                        "Bandsnelheid",
+                        format!(
+                            "{:.1} ± {:.2} cm/s (variatie {:.1} %, {:.1} – {:.1})",
+                            summary.speed.mean,
+                            summary.speed.stddev,
+                            summary.speed.variation(),
+                            summary.speed.min,
+                            summary.speed.max
+                        ),
+                    ),
+                    ("Snelheid per product", format!("{:.1} ± {:.2} cm/s", item_speed.mean, item_speed.stddev)),
+                ];
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                histogram(ui, "statistics_width", "Breedte", &summary.width);
+                histogram(ui, "statistics_length", "Lengte", &summary.length);
+            });
+
+            ui.label("Bandsnelheid (cm/s) over de tijd (s)");
+            Plot::new("statistics_speed")
+                .height(120.0)
+                .allow_scroll(false)
+                .show(ui, |plot_ui| plot_ui.line(Line::new(summary.speed_points.clone())));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.strong("Regelkaarten");
+                egui::ComboBox::from_id_salt("statistics_sensor")
+                    .selected_text(format!("S0{}", self.sensor))
+                    .show_ui(ui, |ui| {
+                        for sensor in 1..=NUM_SENSORS as u8 {
+                            ui.selectable_value(&mut self.sensor, sensor, format!("S0{}", sensor));
+                        }
+                    });
+            });
+
+            let chart = statistics.control_chart(self.sensor, window);
+            if chart.subgroups.is_empty() {
+                ui.weak("Nog geen metingen van deze sensor");
+                return;
+            }
+            let times: Vec<f64> = chart.subgroups.iter().map(|group| statistics.relative(group.time)).collect();
+
+            ui.label(format!(
+                "Baseline (X̄): CL {:.1}, grenzen {:.1} – {:.1}, {} buiten de grenzen",
+                chart.mean.center,
+                chart.mean.lower,
+                chart.mean.upper,
+                chart.mean_violations()
+            ));
+            let means = times.iter().zip(&chart.subgroups).map(|(time, group)| [*time, group.mean]).collect();
+            control_plot(ui, "statistics_mean", means, chart.mean);
+
+            ui.label(format!(
+                "Ruis (s): CL {:.2}, grenzen {:.2} – {:.2}, {} buiten de grenzen",
+                chart.noise.center,
+                chart.noise.lower,
+                chart.noise.upper,
+                chart.noise_violations()
+            ));
+            let noise = times.iter().zip(&chart.subgroups).map(|(time, group)| [*time, group.stddev]).collect();
+            control_plot(ui, "statistics_noise", noise, chart.noise);
+
+            ui.label(format!(
+                "CUSUM van de baseline: beslisgrens {:.1}, {} signalen",
+                chart.cusum_limit,
+                chart.cusum_signals()
+            ));
+            let upper: Vec<[f64; 2]> = times.iter().zip(&chart.cusum).map(|(time, (upper, _))| [*time, *upper]).collect();
+            let lower: Vec<[f64; 2]> = times.iter().zip(&chart.cusum).map(|(time, (_, lower))| [*time, -*lower]).collect();
+            let warn = ui.visuals().warn_fg_color;
+            Plot::new("statistics_cusum").height(130.0).allow_scroll(false).show(ui, |plot_ui| {
+                plot_ui.hline(HLine::new(chart.cusum_limit).name("H").color(warn));
+                plot_ui.hline(HLine::new(-chart.cusum_limit).name("-H").color(warn));
+                plot_ui.line(Line::new(upper).name("C+"));
+                plot_ui.line(Line::new(lower).name("C-"));
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subgroup(time: u64, mean: f64, stddev: f64) -> Subgroup {
+        Subgroup { time, mean, stddev, samples: 4 }
+    }
+
+    fn item(ended_at: u64) -> Item {
+        Item {
+            id: ended_at,
+            timestamp: String::new(),
+            started_at: ended_at,
+            ended_at,
+            width: 200.0,
+            length: 300.0,
+            speed: 0.5,
+            samples: 1,
+            verdict: Verdict::Pass,
+            detections: 0,
+            peak_sensor: None,
+            peak_deviation: 0.0,
+        }
+    }
+
+    #[test]
+    fn distribution_of_values() {
+        let distribution = Distribution::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(distribution.samples, 8);
+        assert_eq!(distribution.mean, 5.0);
+        // Steekproef standaardafwijking, gedeeld door n - 1
+        assert!((distribution.stddev - (32.0_f64 / 7.0).sqrt()).abs() < 1e-9);
+        assert_eq!((distribution.min, distribution.max), (2.0, 9.0));
+        assert!((distribution.bin_width - 0.35).abs() < 1e-9);
+        assert_eq!(distribution.bins.len(), HISTOGRAM_BINS);
+        assert_eq!(distribution.bins.iter().map(|(_, count)| count).sum::<usize>(), 8);
+        // Het maximum valt in de laatste bin
+        assert_eq!(distribution.bins[0].1, 1);
+        assert_eq!(distribution.bins[HISTOGRAM_BINS - 1].1, 1);
+        assert!((distribution.variation() - distribution.stddev / 5.0 * 100.0).abs() < 1e-9);
+
+        let constant = Distribution::new(&[3.0, 3.0, 3.0]);
+        assert_eq!((constant.stddev, constant.bin_width), (0.0, 1.0));
+        assert_eq!(constant.bins[0], (3.5, 3));
+
+        let empty = Distribution::new(&[]);
+        assert_eq!(empty.samples, 0);
+        assert!(empty.bins.is_empty());
+        assert_eq!(empty.variation(), 0.0);
+    }
+
+    #[test]
+    fn control_chart_limits_and_violations() {
+        let mut subgroups: Vec<Subgroup> = (0..19).map(|index| subgroup(index * 1000, 100.0, 2.0)).collect();
+        subgroups.push(subgroup(19_000, 110.0, 2.0));
+        let chart = ControlChart::new(subgroups);
+
+        // Sigma van het gemiddelde is 2 / √4 = 1, de grenzen liggen op drie sigma
+        assert!((chart.mean.center - 100.5).abs() < 1e-9);
+        assert!((chart.mean.lower - 97.5).abs() < 1e-9);
+        assert!((chart.mean.upper - 103.5).abs() < 1e-9);
+        assert_eq!(chart.mean_violations(), 1);
+
+        let sigma_noise = 2.0 / 6.0_f64.sqrt();
+        assert!((chart.noise.center - 2.0).abs() < 1e-9);
+        assert!((chart.noise.upper - (2.0 + 3.0 * sigma_noise)).abs() < 1e-9);
+        assert_eq!(chart.noise.lower, 0.0);
+        assert_eq!(chart.noise_violations(), 0);
+
+        let mut noisy: Vec<Subgroup> = (0..9).map(|index| subgroup(index * 1000, 100.0, 2.0)).collect();
+        noisy.push(subgroup(9000, 100.0, 8.0));
+        assert_eq!(ControlChart::new(noisy).noise_violations(), 1);
+
+        let empty = ControlChart::new(Vec::new());
+        assert_eq!((empty.mean_violations(), empty.cusum_signals()), (0, 0));
+    }
+
+    #[test]
+    fn cusum_signals_a_small_shift() {
+        // Een verschuiving van 1,5 sigma rond de centrale lijn blijft binnen de
+        // drie sigma grenzen, maar de CUSUM ziet hem wel
+        let subgroups = (0..20)
+            .map(|index| subgroup(index * 1000, if index < 10 { 100.0 } else { 103.0 }, 2.0))
+            .collect();
+        let chart = ControlChart::new(subgroups);
+        assert_eq!(chart.mean_violations(), 0);
+        assert_eq!(chart.cusum_limit, 5.0);
+        assert_eq!(chart.cusum[9], (0.0, 10.0));
+        assert_eq!(chart.cusum[19], (10.0, 0.0));
+        // Onderste som boven de grens bij subgroep 6 t/m 12, bovenste bij 16 t/m 20
+        assert_eq!(chart.cusum_signals(), 12);
+
+        let stable = ControlChart::new((0..20).map(|index| subgroup(index * 1000, 100.0, 2.0)).collect());
+        assert_eq!(stable.cusum_signals(), 0);
+    }
+
+    #[test]
+    fn advance_drops_data_older_than_the_retention() {
+        let mut statistics = Statistics::new(Some(10_000));
+        for time in (0..3000).step_by(100) {
+            statistics.on_measurement(time, &Measurement { id: 1, connected: true, value: 100 + (time % 200) as u16 });
+        }
+        statistics.on_detection(500);
+        statistics.on_met(2000, &LineMetrics { width: 200.0, length: 300.0, speed: 0.5 });
+        statistics.on_item(&item(2500));
+        assert_eq!(statistics.subgroups[0].len(), 2);
+
+        statistics.on_detection(10_800);
+        assert!(statistics.detections.iter().eq([10_800].iter()));
+        assert_eq!(statistics.speeds.len(), 1);
+        assert_eq!(statistics.items.len(), 1);
+        // De subgroep die op 0 ms begon is weg, die van 1000 ms nog niet
+        assert_eq!(statistics.subgroups[0].len(), 1);
+        assert_eq!(statistics.subgroups[0][0].time, 1000);
+
+        statistics.on_detection(13_000);
+        assert!(statistics.speeds.is_empty());
+        assert!(statistics.items.is_empty());
+        assert!(statistics.subgroups[0].is_empty());
+
+        // Zonder bewaartermijn (een opname) blijft alles staan
+        let mut recording = Statistics::new(None);
+        recording.on_detection(0);
+        recording.on_detection(10 * LIVE_RETENTION_MS);
+        assert_eq!(recording.detections.len(), 2);
+    }
+}
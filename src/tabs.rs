@@ -0,0 +1,238 @@
+use crate::{RenderableTab, Tab};
+use eframe::egui;
+use egui::Id;
+use egui_tiles::{Behavior, Tile, TileId, Tiles, Tree};
+use std::sync::{Arc, Mutex};
+
+// Een tab in de boom: welk soort tab het is, het hoeveelste exemplaar van dat soort
+// en de tab zelf met zijn eigen instellingen
+pub struct Pane {
+    pub kind: &'static str,
+    pub instance: usize,
+    pub tab: Tab,
+}
+
+impl Pane {
+    pub fn title(&self) -> String {
+        let tab = self.tab.lock().unwrap();
+        match self.instance {
+            1 => tab.title().to_string(),
+            instance => format!("{} ({})", tab.title(), instance),
+        }
+    }
+}
+
+struct TabKind {
+    id: &'static str,
+    title: &'static str,
+    factory: Box<dyn Fn() -> Tab>,
+}
+
+// Alle soorten tabs die de gebruiker kan openen. Elk soort registreert een factory
+// die een nieuw exemplaar maakt, zodat er meerdere van naast elkaar kunnen staan.
+#[derive(Default)]
+pub struct TabRegistry {
+    kinds: Vec<TabKind>,
+}
+
+impl TabRegistry {
+    pub fn register<T: RenderableTab + 'static>(
+        &mut self,
+        id: &'static str,
+        title: &'static str,
+        factory: impl Fn() -> T + 'static,
+    ) -> &mut Self {
+        self.kinds.push(TabKind {
+            id,
+            title,
+            factory: Box::new(move || Arc::new(Mutex::new(factory()))),
+        });
+        self
+    }
+
+    // Zonder `tab` krijgt de pane een nieuwe tab van deze soort
+    fn create(&self, kind: &str, instance: usize, tab: Option<Tab>) -> Option<Pane> {
+        let kind = self.kinds.iter().find(|registered| registered.id == kind)?;
+        Some(Pane {
+            kind: kind.id,
+            instance,
+            tab: tab.unwrap_or_else(|| (kind.factory)()),
+        })
+    }
+
+    // Volgorde van de soorten in het menu
+    fn position(&self, kind: &str) -> usize {
+        self.kinds.iter().position(|registered| registered.id == kind).unwrap_or(usize::MAX)
+    }
+}
+
+// Een vaste indeling van de tabs: groepen naast elkaar, elke groep met tabs onder
+// dezelfde tabbalk
+pub struct LayoutPreset {
+    pub name: &'static str,
+    groups: &'static [&'static [&'static str]],
+}
+
+pub const PRESETS: [LayoutPreset; 2] = [
+    // De operator ziet de band en de uitslag, zonder de instellingen van de hub
+    LayoutPreset {
+        name: "Operator",
+        groups: &[&["visualization"], &["results", "items", "gallery", "statistics"]],
+    },
+    LayoutPreset {
+        name: "Engineer",
+        groups: &[
+            &["results", "visualization", "gallery", "items", "statistics"],
+            &["logs", "serial_monitor", "events", "config", "recipes", "batch", "report", "firmware", "app_log"],
+        ],
+    },
+];
+
+enum Action {
+    Open(&'static str),
+    Duplicate(TileId),
+    Close(TileId),
+    Layout(&'static LayoutPreset),
+}
+
+// De tabs in het hoofdvenster en het View menu om ze te openen, te sluiten en te
+// dupliceren
+pub struct Workspace {
+    registry: TabRegistry,
+    tree: Tree<Pane>,
+}
+
+impl Workspace {
+    pub fn new(registry: TabRegistry, preset: &LayoutPreset) -> Self {
+        let tree = layout(&registry, preset);
+        Self { registry, tree }
+    }
+
+    pub fn ui(&mut self, behavior: &mut dyn Behavior<Pane>, ui: &mut egui::Ui) {
+        self.tree.ui(behavior, ui);
+    }
+
+    // De open tabs in de volgorde van de registry
+    fn panes(&self) -> Vec<(TileId, &Pane)> {
+        let mut panes: Vec<_> = self
+            .tree
+            .tiles
+            .iter()
+            .filter_map(|(tile_id, tile)| match tile {
+                Tile::Pane(pane) => Some((*tile_id, pane)),
+                Tile::Container(_) => None,
+            })
+            .collect();
+        panes.sort_by_key(|(_, pane)| (self.registry.position(pane.kind), pane.instance));
+        panes
+    }
+
+    pub fn open(&mut self, kind: &str) {
+        self.insert(kind, None);
+    }
+
+    fn insert(&mut self, kind: &str, tab: Option<Tab>) {
+        let instance = self
+            .panes()
+            .iter()
+            .filter(|(_, pane)| pane.kind == kind)
+            .map(|(_, pane)| pane.instance)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let Some(pane) = self.registry.create(kind, instance, tab) else {
+            re_log::warn!("Onbekend soort tab {}", kind);
+            return;
+        };
+
+        let pane_id = self.tree.tiles.insert_pane(pane);
+        match self.tree.root.and_then(|root| self.tree.tiles.get_mut(root)) {
+            Some(Tile::Container(container)) => container.add_child(pane_id),
+            // Alles gesloten of een losse tab: begin opnieuw met een tabbalk
+            _ => {
+                let children = self.tree.root.into_iter().chain([pane_id]).collect();
+                self.tree.root = Some(self.tree.tiles.insert_tab_tile(children));
+            }
+        }
+        self.tree.make_active(|tile_id, _| tile_id == pane_id);
+    }
+
+    pub fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
+
+        ui.menu_button("Openen", |ui| {
+            for kind in &self.registry.kinds {
+                if ui.button(kind.title).clicked() {
+                    action = Some(Action::Open(kind.id));
+                    ui.close_menu();
+                }
+            }
+        });
+
+        ui.separator();
+        let panes = self.panes();
+        if panes.is_empty() {
+            ui.weak("Geen tabs open");
+        }
+        for (tile_id, pane) in panes {
+            ui.menu_button(pane.title(), |ui| {
+                if ui.button("Dupliceren").clicked() {
+                    action = Some(Action::Duplicate(tile_id));
+                    ui.close_menu();
+                }
+                if ui.button("Sluiten").clicked() {
+                    action = Some(Action::Close(tile_id));
+                    ui.close_menu();
+                }
+            });
+        }
+
+        ui.separator();
+        ui.menu_button("Layout", |ui| {
+            for preset in &PRESETS {
+                if ui.button(preset.name).clicked() {
+                    action = Some(Action::Layout(preset));
+                    ui.close_menu();
+                }
+            }
+        });
+
+        match action {
+            Some(Action::Open(kind)) => self.open(kind),
+            // Een duplicaat neemt de instellingen van de tab over als die dat kan,
+            // anders begint het met de standaard instellingen van zijn soort
+            Some(Action::Duplicate(tile_id)) => {
+                if let Some(Tile::Pane(pane)) = self.tree.tiles.get(tile_id) {
+                    let (kind, tab) = (pane.kind, pane.tab.lock().unwrap().duplicate());
+                    self.insert(kind, tab);
+                }
+            }
+            Some(Action::Close(tile_id)) => {
+                self.tree.remove_recursively(tile_id);
+            }
+            // Een preset vervangt alle open tabs door nieuwe
+            Some(Action::Layout(preset)) => self.tree = layout(&self.registry, preset),
+            None => {}
+        }
+    }
+}
+
+fn layout(registry: &TabRegistry, preset: &LayoutPreset) -> Tree<Pane> {
+    let mut tiles = Tiles::default();
+    let groups = preset
+        .groups
+        .iter()
+        .map(|group| {
+            let panes = group
+                .iter()
+                .filter_map(|kind| registry.create(kind, 1, None))
+                .map(|pane| tiles.insert_pane(pane))
+                .collect();
+            tiles.insert_tab_tile(panes)
+        })
+        .collect();
+    let root = tiles.insert_horizontal_tile(groups);
+
+    // Elke preset een eigen id, zodat egui niets van de vorige indeling onthoudt
+    Tree::new(Id::new(("tabs", preset.name)), root, tiles)
+}
@@ -0,0 +1,388 @@
+use crate::heatmap::ScrollingHeatmap;
+use crate::snapshots::heat_color;
+use crate::{GlobalState, RenderableTab, Tab};
+use egui::{Color32, ColorImage, Rect, TextureHandle, TextureOptions};
+use metalstream::protocol::{Measurement, NUM_SENSORS};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// // 250 => samples per seconds * (band lengte (cm) / band snelheid (cm/s)) => 10*(100\4)
+const SAMPLE_BUF_SIZE: usize = 10*(100/4);
+
+// even nummer
+const VP_WIDTH: usize = 100;
+// Een rij per scan, dus de hele band
+const VP_HEIGHT: usize = SAMPLE_BUF_SIZE;
+
+const PX_PER_SENSOR: usize = VP_WIDTH / NUM_SENSORS;
+
+// Scans die tijdens een sessie bewaard blijven om terug te kunnen kijken;
+// bij 10 scans per seconde is dat tien uur
+const SESSION_SCANS: usize = 10 * 60 * 60 * 10;
+// Verder inzoomen dan dit aantal scans in beeld heeft geen zin
+const MIN_VISIBLE_SCANS: f64 = 10.0;
+
+// Een volledige scan van alle sensoren
+struct Scan {
+    // Hub timestamp van de laatste meting in de scan
+    timestamp: String,
+    values: [Option<u16>; NUM_SENSORS],
+    // Afwijking van de baseline, alleen als de sensor al gekalibreerd was
+    deviations: [Option<f32>; NUM_SENSORS],
+    // Afgelegde weg van de band sinds het begin van de sessie, in cm
+    position: f64,
+}
+
+// Welk deel van de sessie in beeld is, in scans. Live volgt het beeld de
+// nieuwste scan; gepauzeerd blijft het staan terwijl de metingen doorlopen.
+#[derive(Clone, Copy)]
+struct View {
+    live: bool,
+    end: f64,
+    rows: f64,
+}
+
+impl View {
+    fn first(&self) -> f64 {
+        self.end - self.rows
+    }
+
+    // Verschuif het beeld met een deel van de hoogte; positief is terug in de tijd
+    fn pan(&mut self, fraction: f64) {
+        self.live = false;
+        self.end -= fraction * self.rows;
+    }
+}
+
+// Kleuren van de heatmap, per tab in te stellen
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Colormap {
+    #[default]
+    Gray,
+    Heat,
+}
+
+impl Colormap {
+    const ALL: [Colormap; 2] = [Colormap::Gray, Colormap::Heat];
+
+    fn label(self) -> &'static str {
+        match self {
+            Colormap::Gray => "Grijs",
+            Colormap::Heat => "Warmte",
+        }
+    }
+}
+
+// Een rij pixels voor een scan; een sensor zonder waarde blijft zwart
+fn scan_row(values: &[Option<u16>; NUM_SENSORS], colormap: Colormap) -> [Color32; VP_WIDTH] {
+    let mut row = [Color32::BLACK; VP_WIDTH];
+    for (sensor, value) in values.iter().enumerate() {
+        let Some(value) = value else {
+            continue;
+        };
+        let intensity = (*value as f32 / 2000.0).clamp(0.0, 1.0);
+        let start = sensor * PX_PER_SENSOR;
+        for (offset, pixel) in row[start..start + PX_PER_SENSOR].iter_mut().enumerate() {
+            *pixel = match colormap {
+                Colormap::Gray => {
+                    let weight = 1.0 - offset as f32 / PX_PER_SENSOR as f32;
+                    Color32::from_gray((intensity * weight * 255.0) as u8)
+                }
+                Colormap::Heat => {
+                    let [r, g, b] = heat_color(intensity);
+                    Color32::from_rgb(r, g, b)
+                }
+            };
+        }
+    }
+    row
+}
+
+// De scans van de sessie, gedeeld door alle heatmaps. De GUI vult ze aan met de
+// metingen uit de updates van de engine; elke Visualization tab tekent ze op
+// zijn eigen manier.
+pub struct SensorHistory {
+    // De lopende scan en welke sensoren daar al in zitten
+    timestamp: String,
+    values: [Option<u16>; NUM_SENSORS],
+    deviations: [Option<f32>; NUM_SENSORS],
+    scanned: [bool; NUM_SENSORS],
+    // Alle scans van de sessie, voor het terugkijken
+    session: VecDeque<Scan>,
+    // Aantal scans sinds het begin, ook de weggegooide
+    total: u64,
+    position: f64,
+    last_scan: Option<u64>,
+}
+
+impl SensorHistory {
+    pub fn new() -> Self {
+        Self {
+            timestamp: String::new(),
+            values: [None; NUM_SENSORS],
+            deviations: [None; NUM_SENSORS],
+            scanned: [false; NUM_SENSORS],
+            session: VecDeque::new(),
+            total: 0,
+            position: 0.0,
+            last_scan: None,
+        }
+    }
+
+    // De heatmap loopt mee met de metingen en niet met de frames van de GUI:
+    // een sensor die al in de lopende scan zit begint een nieuwe scan, en de
+    // vorige scan wordt een rij
+    pub fn add_sensor_value(&mut self, time: u64, timestamp: &str, measurement: Measurement, speed: f64, baseline: Option<f64>) {
+        let index = measurement.id as usize - 1;
+        if self.scanned[index] {
+            self.push_scan(time, speed);
+        }
+        self.scanned[index] = true;
+        self.timestamp.clear();
+        self.timestamp.push_str(timestamp);
+        self.values[index] = measurement.connected.then_some(measurement.value);
+        self.deviations[index] = baseline
+            .filter(|_| measurement.connected)
+            .map(|baseline| (measurement.value as f64 - baseline) as f32);
+    }
+
+    fn push_scan(&mut self, time: u64, speed: f64) {
+        // De positie op de band volgt uit de snelheid (cm/s) en de tijd tussen scans
+        if let Some(last) = self.last_scan {
+            self.position += speed * time.saturating_sub(last) as f64 / 1000.0;
+        }
+        self.last_scan = Some(time);
+
+        if self.session.len() >= SESSION_SCANS {
+            self.session.pop_front();
+        }
+        self.session.push_back(Scan {
+            timestamp: self.timestamp.clone(),
+            values: self.values,
+            deviations: self.deviations,
+            position: self.position,
+        });
+        self.total += 1;
+
+        self.values = [None; NUM_SENSORS];
+        self.deviations = [None; NUM_SENSORS];
+        self.scanned = [false; NUM_SENSORS];
+    }
+
+    // Aantal scans dat vooraan uit de sessie is gevallen
+    fn dropped(&self) -> u64 {
+        self.total - self.session.len() as u64
+    }
+}
+
+pub struct VisualizationTab {
+    // Een rij per scan van alle sensoren, de nieuwste onderaan
+    heatmap: ScrollingHeatmap,
+    colormap: Colormap,
+    // Tot waar de scans van de sessie in de heatmap staan
+    synced: u64,
+    dropped: u64,
+    view: View,
+    // Texture voor een ingezoomd of gepauzeerd beeld, opnieuw getekend als het
+    // zichtbare deel verandert
+    view_texture: Option<TextureHandle>,
+    view_key: Option<(i64, usize, usize)>,
+}
+
+impl VisualizationTab {
+    pub fn new() -> Self {
+        Self {
+            heatmap: ScrollingHeatmap::new(VP_WIDTH, VP_HEIGHT),
+            colormap: Colormap::default(),
+            synced: 0,
+            dropped: 0,
+            view: View { live: true, end: 0.0, rows: VP_HEIGHT as f64 },
+            view_texture: None,
+            view_key: None,
+        }
+    }
+
+    // Zet de scans die sinds het vorige frame zijn bijgekomen in de heatmap; meer
+    // dan een volle heatmap hoeft niet, de rest zou er meteen weer uit schuiven
+    fn sync(&mut self, sensors: &SensorHistory) {
+        // Een gepauzeerd beeld blijft op dezelfde scans staan
+        if !self.view.live {
+            self.view.end -= (sensors.dropped() - self.dropped) as f64;
+        }
+        self.dropped = sensors.dropped();
+
+        let new = (sensors.total - self.synced).min(VP_HEIGHT as u64) as usize;
+        let start = sensors.session.len().saturating_sub(new);
+        for scan in sensors.session.range(start..) {
+            self.heatmap.push_row(&scan_row(&scan.values, self.colormap));
+        }
+        self.synced = sensors.total;
+    }
+
+    fn set_colormap(&mut self, colormap: Colormap) {
+        self.colormap = colormap;
+        self.heatmap = ScrollingHeatmap::new(VP_WIDTH, VP_HEIGHT);
+        self.synced = 0;
+        self.view_key = None;
+    }
+
+    // Zoomen met ctrl+scroll of knijpen rond de scan onder de cursor; scrollen en
+    // slepen verschuift het beeld en pauzeert het
+    fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response, rect: Rect, len: usize) {
+        let len = len as f64;
+        if let Some(pointer) = response.hover_pos() {
+            let (zoom, scroll) = ui.input(|input| (input.zoom_delta(), input.smooth_scroll_delta.y));
+            if zoom != 1.0 {
+                let below = 1.0 - ((pointer.y - rect.top()) / rect.height()) as f64;
+                let anchor = self.view.end - self.view.rows * below;
+                self.view.rows = (self.view.rows / zoom as f64).clamp(MIN_VISIBLE_SCANS, len.max(VP_HEIGHT as f64));
+                self.view.end = anchor + self.view.rows * below;
+            }
+            if scroll != 0.0 {
+                self.view.pan(scroll as f64 / rect.height() as f64);
+            }
+        }
+        if response.dragged() {
+            self.view.pan(response.drag_delta().y as f64 / rect.height() as f64);
+        }
+
+        if self.view.live {
+            self.view.end = len;
+        }
+        self.view.end = self.view.end.clamp(self.view.rows.min(len), len);
+    }
+
+    // Bij uitzoomen valt een pixelrij over meerdere scans; dan telt per sensor
+    // de hoogste waarde, zodat korte pieken zichtbaar blijven
+    fn render_view(&self, sensors: &SensorHistory, first: i64, rows: usize, height: usize) -> ColorImage {
+        let len = sensors.session.len() as i64;
+        let mut pixels = Vec::with_capacity(VP_WIDTH * height);
+        for line in 0..height {
+            let from = first + (line * rows / height) as i64;
+            let to = (first + ((line + 1) * rows / height) as i64).max(from + 1);
+            let mut values = [None; NUM_SENSORS];
+            for scan in from.max(0)..to.min(len) {
+                for (value, scanned) in values.iter_mut().zip(sensors.session[scan as usize].values) {
+                    *value = (*value).max(scanned);
+                }
+            }
+            pixels.extend(scan_row(&values, self.colormap));
+        }
+        ColorImage { size: [VP_WIDTH, height], pixels }
+    }
+
+    fn paint(&mut self, ui: &egui::Ui, rect: Rect, sensors: &SensorHistory) {
+        let painter = ui.painter_at(rect);
+
+        // Live en niet ingezoomd komt het beeld rechtstreeks uit de ringbuffer
+        if self.view.live && self.view.rows as usize == VP_HEIGHT {
+            let uv = self.heatmap.uv();
+            let texture = self.heatmap.upload(ui.ctx(), "sensor");
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+            return;
+        }
+
+        let first = self.view.first().round() as i64;
+        let rows = (self.view.rows.round() as usize).max(1);
+        let height = rows.min(rect.height() as usize).max(1);
+        let key = (first, rows, height);
+        if self.view_key != Some(key) {
+            let image = self.render_view(sensors, first, rows, height);
+            match &mut self.view_texture {
+                Some(texture) => texture.set(image, TextureOptions::NEAREST),
+                None => self.view_texture = Some(ui.ctx().load_texture("sensor_view", image, TextureOptions::NEAREST)),
+            }
+            self.view_key = Some(key);
+        }
+        if let Some(texture) = &self.view_texture {
+            let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+        }
+    }
+
+    // Sensor en scan onder de cursor
+    fn readout(&self, sensors: &SensorHistory, rect: Rect, pointer: egui::Pos2) -> Option<String> {
+        let fraction = ((pointer.y - rect.top()) / rect.height()) as f64;
+        let scan = (self.view.first() + fraction * self.view.rows).floor();
+        let column = ((pointer.x - rect.left()) / rect.width() * VP_WIDTH as f32) as usize;
+        let sensor = column / PX_PER_SENSOR;
+        if scan < 0.0 || sensor >= NUM_SENSORS {
+            return None;
+        }
+        let scan = sensors.session.get(scan as usize)?;
+
+        let raw = scan.values[sensor].map_or("niet verbonden".to_string(), |value| value.to_string());
+        let calibrated = scan.deviations[sensor].map_or("niet gekalibreerd".to_string(), |deviation| format!("{:+.0}", deviation));
+        Some(format!(
+            "S{:02} · ruw {} · gekalibreerd {} · hub {} · positie {:.2} m",
+            sensor + 1,
+            raw,
+            calibrated,
+            scan.timestamp,
+            scan.position / 100.0
+        ))
+    }
+}
+
+impl RenderableTab for VisualizationTab {
+    fn title(&self) -> &str {
+        "Visualization"
+    }
+
+    // De kopie kijkt naar hetzelfde stuk van de sessie met dezelfde kleuren
+    fn duplicate(&self) -> Option<Tab> {
+        let mut tab = Self::new();
+        tab.colormap = self.colormap;
+        tab.view = self.view;
+        Some(Arc::new(Mutex::new(tab)))
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, state: &mut GlobalState) {
+        let sensors = &state.sensor_history;
+        self.sync(sensors);
+
+        ui.horizontal(|ui| {
+            let label = if self.view.live { "⏸ Pauze" } else { "▶ Live" };
+            if ui.button(label).clicked() {
+                self.view.live = !self.view.live;
+            }
+            if ui.button("Zoom herstellen").clicked() {
+                self.view.rows = VP_HEIGHT as f64;
+            }
+
+            let mut colormap = self.colormap;
+            egui::ComboBox::from_id_salt("colormap")
+                .selected_text(colormap.label())
+                .show_ui(ui, |ui| {
+                    for option in Colormap::ALL {
+                        ui.selectable_value(&mut colormap, option, option.label());
+                    }
+                });
+            if colormap != self.colormap {
+                self.set_colormap(colormap);
+            }
+
+            let first = self.view.first().max(0.0) as usize;
+            ui.label(format!(
+                "Scans {}–{} van {} · {}",
+                first,
+                self.view.end as usize,
+                sensors.session.len(),
+                if self.view.live { "LIVE" } else { "GEPAUZEERD" }
+            ));
+        });
+        // Een andere colormap begint met een lege heatmap
+        self.sync(sensors);
+
+        let height = (ui.available_height() - 2.0 * ui.spacing().interact_size.y).max(VP_HEIGHT as f32);
+        let size = egui::vec2(ui.available_width().clamp(VP_WIDTH as f32, 4.0 * VP_WIDTH as f32), height);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        self.handle_input(ui, &response, rect, sensors.session.len());
+        self.paint(ui, rect, sensors);
+
+        match response.hover_pos().and_then(|pointer| self.readout(sensors, rect, pointer)) {
+            Some(readout) => ui.label(readout),
+            None => ui.weak("Beweeg over de heatmap voor de waarden; scroll of sleep om terug te kijken, ctrl+scroll om te zoomen"),
+        };
+    }
+}